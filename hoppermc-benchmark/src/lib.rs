@@ -13,6 +13,12 @@ pub struct BenchmarkMetrics {
     pub total_load_time_us: AtomicU64,
     pub total_chunks_saved: AtomicUsize,
     pub total_save_time_us: AtomicU64,
+    pub total_flushes: AtomicUsize,
+    pub total_flushed_chunks: AtomicUsize,
+    pub total_flush_time_us: AtomicU64,
+    pub total_region_loads: AtomicUsize,
+    pub total_region_chunks: AtomicUsize,
+    pub total_region_load_time_us: AtomicU64,
 
     // Detailed Breakdown
     pub total_generation_biomes_us: AtomicU64,
@@ -35,8 +41,41 @@ pub struct BenchmarkMetrics {
     // Cache
     pub total_cache_hits: AtomicUsize,
     pub total_cache_misses: AtomicUsize,
+    pub total_cache_evictions: AtomicUsize,
+    pub cache_bytes: AtomicU64,
+    pub cache_entries: AtomicUsize,
+    pub total_disk_cache_hits: AtomicUsize,
+    pub total_disk_cache_misses: AtomicUsize,
+    pub disk_cache_bytes: AtomicU64,
+
+    // Prefetch
+    pub total_prefetch_issued: AtomicUsize,
+    pub total_prefetch_hits: AtomicUsize,
+    pub total_prefetch_wasted: AtomicUsize,
+    pub total_prefetch_directional: AtomicUsize,
+    pub total_prefetch_cancelled: AtomicUsize,
+
+    // Storage health
+    pub total_breaker_trips: AtomicUsize,
+    pub total_breaker_rejected: AtomicUsize,
+    pub breaker_open: AtomicUsize,
+    pub total_storage_read_errors: AtomicUsize,
+    pub total_degraded_reads: AtomicUsize,
+    pub total_refused_writes: AtomicUsize,
 
     pub total_db_size_bytes: AtomicU64,
+    // Payload bytes before / after storage-side compression (0 = not reported)
+    pub storage_plain_bytes: AtomicU64,
+    pub storage_stored_bytes: AtomicU64,
+
+    // Connection pool (Postgres; max size 0 = not reported)
+    pub pool_max_size: AtomicUsize,
+    pub pool_acquires: AtomicU64,
+    pub pool_timeouts: AtomicU64,
+    pub pool_wait_us: AtomicU64,
+    pub pool_max_wait_us: AtomicU64,
+    pub pool_in_use_sum: AtomicU64,
+    pub pool_peak_in_use: AtomicUsize,
 
     // Session
     pub start_time: Option<Instant>,
@@ -56,6 +95,26 @@ impl BenchmarkMetrics {
         self.total_db_size_bytes.store(size_bytes, Ordering::Relaxed);
     }
 
+    pub fn record_storage_compression(&self, plain_bytes: u64, stored_bytes: u64) {
+        self.storage_plain_bytes.store(plain_bytes, Ordering::Relaxed);
+        self.storage_stored_bytes.store(stored_bytes, Ordering::Relaxed);
+    }
+
+    /// Connection checkouts so far: count, wait-timeouts, summed and longest wait.
+    pub fn record_pool_wait(&self, acquires: u64, timeouts: u64, total_wait: Duration, max_wait: Duration) {
+        self.pool_acquires.store(acquires, Ordering::Relaxed);
+        self.pool_timeouts.store(timeouts, Ordering::Relaxed);
+        self.pool_wait_us.store(total_wait.as_micros() as u64, Ordering::Relaxed);
+        self.pool_max_wait_us.store(max_wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Pool size, and connections in use right after each checkout (summed, and the peak).
+    pub fn record_pool_usage(&self, max_size: usize, in_use_sum: u64, peak_in_use: usize) {
+        self.pool_max_size.store(max_size, Ordering::Relaxed);
+        self.pool_in_use_sum.store(in_use_sum, Ordering::Relaxed);
+        self.pool_peak_in_use.store(peak_in_use, Ordering::Relaxed);
+    }
+
     pub fn record_generation(&self, duration: Duration) {
         self.total_chunks_generated.fetch_add(1, Ordering::Relaxed);
         let us = duration.as_micros() as u64;
@@ -73,6 +132,45 @@ impl BenchmarkMetrics {
         self.total_save_time_us.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
     }
 
+    pub fn record_flush(&self, duration: Duration, chunks: usize) {
+        self.total_flushes.fetch_add(1, Ordering::Relaxed);
+        self.total_flushed_chunks.fetch_add(chunks, Ordering::Relaxed);
+        self.total_flush_time_us.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// One bulk fetch of a region's stored chunks.
+    pub fn record_region_load(&self, duration: Duration, chunks: usize) {
+        self.total_region_loads.fetch_add(1, Ordering::Relaxed);
+        self.total_region_chunks.fetch_add(chunks, Ordering::Relaxed);
+        self.total_region_load_time_us.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_breaker_trip(&self) {
+        self.total_breaker_trips.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_breaker_rejected(&self) {
+        self.total_breaker_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_breaker_open(&self, open: bool) {
+        self.breaker_open.store(open as usize, Ordering::Relaxed);
+    }
+
+    pub fn record_storage_read_error(&self) {
+        self.total_storage_read_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A chunk served from the generator because storage was unavailable (read-only policy).
+    pub fn record_degraded_read(&self) {
+        self.total_degraded_reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A write refused because the chunk was not served from storage.
+    pub fn record_refused_write(&self) {
+        self.total_refused_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn record_generation_biomes(&self, duration: Duration) {
         self.total_generation_biomes_us.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
     }
@@ -116,6 +214,49 @@ impl BenchmarkMetrics {
         self.total_cache_misses.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn record_cache_evictions(&self, count: usize) {
+        self.total_cache_evictions.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_cache_usage(&self, bytes: u64, entries: usize) {
+        self.cache_bytes.store(bytes, Ordering::Relaxed);
+        self.cache_entries.store(entries, Ordering::Relaxed);
+    }
+
+    pub fn record_disk_cache_hit(&self) {
+        self.total_disk_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_disk_cache_miss(&self) {
+        self.total_disk_cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_disk_cache_usage(&self, bytes: u64) {
+        self.disk_cache_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_prefetch_issued(&self) {
+        self.total_prefetch_issued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_prefetch_hit(&self) {
+        self.total_prefetch_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_prefetch_wasted(&self, count: usize) {
+        self.total_prefetch_wasted.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// A prefetch plan shaped by an inferred heading (rather than the plain square).
+    pub fn record_prefetch_directional(&self) {
+        self.total_prefetch_directional.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Queued prefetch jobs dropped because the player moved away from them.
+    pub fn record_prefetch_cancelled(&self, count: usize) {
+        self.total_prefetch_cancelled.fetch_add(count, Ordering::Relaxed);
+    }
+
     pub fn generate_report(&self) -> String {
         let uptime = self.start_time.unwrap_or_else(Instant::now).elapsed();
         let generated = self.total_chunks_generated.load(Ordering::Relaxed);
@@ -145,15 +286,53 @@ impl BenchmarkMetrics {
         let misses = self.total_cache_misses.load(Ordering::Relaxed);
         let total_requests = hits + misses;
         let hit_rate = if total_requests > 0 { (hits as f64 / total_requests as f64) * 100.0 } else { 0.0 };
+        let evictions = self.total_cache_evictions.load(Ordering::Relaxed);
+        let cache_mb = self.cache_bytes.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0;
+        let cache_entries = self.cache_entries.load(Ordering::Relaxed);
+
+        let disk_hits = self.total_disk_cache_hits.load(Ordering::Relaxed);
+        let disk_misses = self.total_disk_cache_misses.load(Ordering::Relaxed);
+        let disk_requests = disk_hits + disk_misses;
+        let disk_hit_rate = if disk_requests > 0 { (disk_hits as f64 / disk_requests as f64) * 100.0 } else { 0.0 };
+        let disk_mb = self.disk_cache_bytes.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0;
+        // Share of all lookups served by either cache tier
+        let prefetch_issued = self.total_prefetch_issued.load(Ordering::Relaxed);
+        let prefetch_hits = self.total_prefetch_hits.load(Ordering::Relaxed);
+        let prefetch_wasted = self.total_prefetch_wasted.load(Ordering::Relaxed);
+        let prefetch_directional = self.total_prefetch_directional.load(Ordering::Relaxed);
+        let prefetch_cancelled = self.total_prefetch_cancelled.load(Ordering::Relaxed);
+        let prefetch_hit_rate = if prefetch_issued > 0 { (prefetch_hits as f64 / prefetch_issued as f64) * 100.0 } else { 0.0 };
+        let prefetch_waste_rate = if prefetch_issued > 0 { (prefetch_wasted as f64 / prefetch_issued as f64) * 100.0 } else { 0.0 };
+
+        let combined_hit_rate = if total_requests > 0 { ((hits + disk_hits) as f64 / total_requests as f64) * 100.0 } else { 0.0 };
 
         let loaded = self.total_chunks_loaded.load(Ordering::Relaxed);
         let load_time = self.total_load_time_us.load(Ordering::Relaxed) as f64 / 1000.0;
         let load_avg = if loaded > 0 { load_time / loaded as f64 } else { 0.0 };
         
+        let region_loads = self.total_region_loads.load(Ordering::Relaxed);
+        let region_chunks = self.total_region_chunks.load(Ordering::Relaxed);
+        let region_load_time = self.total_region_load_time_us.load(Ordering::Relaxed) as f64 / 1000.0;
+        let region_chunks_avg = if region_loads > 0 { region_chunks as f64 / region_loads as f64 } else { 0.0 };
+        let region_load_avg = if region_loads > 0 { region_load_time / region_loads as f64 } else { 0.0 };
+
+        let breaker_trips = self.total_breaker_trips.load(Ordering::Relaxed);
+        let breaker_rejected = self.total_breaker_rejected.load(Ordering::Relaxed);
+        let breaker_state = if self.breaker_open.load(Ordering::Relaxed) > 0 { "OPEN" } else { "closed" };
+        let storage_read_errors = self.total_storage_read_errors.load(Ordering::Relaxed);
+        let degraded_reads = self.total_degraded_reads.load(Ordering::Relaxed);
+        let refused_writes = self.total_refused_writes.load(Ordering::Relaxed);
+
         let saved = self.total_chunks_saved.load(Ordering::Relaxed);
         let save_time = self.total_save_time_us.load(Ordering::Relaxed) as f64 / 1000.0;
         let save_avg = if saved > 0 { save_time / saved as f64 } else { 0.0 };
 
+        let flushes = self.total_flushes.load(Ordering::Relaxed);
+        let flushed_chunks = self.total_flushed_chunks.load(Ordering::Relaxed);
+        let flush_time = self.total_flush_time_us.load(Ordering::Relaxed) as f64 / 1000.0;
+        let flush_batch_avg = if flushes > 0 { flushed_chunks as f64 / flushes as f64 } else { 0.0 };
+        let flush_avg = if flushes > 0 { flush_time / flushes as f64 } else { 0.0 };
+
         // FUSE stats
         let fuse_requests = self.total_fuse_read_count.load(Ordering::Relaxed);
         let fuse_time = self.total_fuse_read_time_us.load(Ordering::Relaxed) as f64 / 1000.0; // ms
@@ -179,7 +358,36 @@ impl BenchmarkMetrics {
         } else { 0.0 };
 
         let db_size_mb = self.total_db_size_bytes.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0;
+        let storage_plain = self.storage_plain_bytes.load(Ordering::Relaxed);
+        let storage_stored = self.storage_stored_bytes.load(Ordering::Relaxed);
+        let storage_compression = if storage_stored > 0 {
+            format!("{:.2}x ({:.2} MB NBT -> {:.2} MB stored)",
+                storage_plain as f64 / storage_stored as f64,
+                storage_plain as f64 / 1024.0 / 1024.0,
+                storage_stored as f64 / 1024.0 / 1024.0)
+        } else {
+            "n/a".to_string()
+        };
         
+        let pool_max_size = self.pool_max_size.load(Ordering::Relaxed);
+        let pool_acquires = self.pool_acquires.load(Ordering::Relaxed);
+        let pool_timeouts = self.pool_timeouts.load(Ordering::Relaxed);
+        let pool_checkouts = pool_acquires + pool_timeouts;
+        let (pool_wait, pool_utilisation) = if pool_max_size > 0 {
+            let wait_ms = self.pool_wait_us.load(Ordering::Relaxed) as f64 / 1000.0;
+            let max_wait_ms = self.pool_max_wait_us.load(Ordering::Relaxed) as f64 / 1000.0;
+            let avg_wait = if pool_checkouts > 0 { wait_ms / pool_checkouts as f64 } else { 0.0 };
+            let avg_in_use = if pool_acquires > 0 { self.pool_in_use_sum.load(Ordering::Relaxed) as f64 / pool_acquires as f64 } else { 0.0 };
+            let peak_in_use = self.pool_peak_in_use.load(Ordering::Relaxed);
+            (
+                format!("{:.2} ms avg, {:.2} ms max ({} checkouts, {} timed out)", avg_wait, max_wait_ms, pool_acquires, pool_timeouts),
+                format!("{:.1}% ({:.1} avg / {} peak of {} connections)",
+                    avg_in_use / pool_max_size as f64 * 100.0, avg_in_use, peak_in_use, pool_max_size),
+            )
+        } else {
+            ("n/a".to_string(), "n/a".to_string())
+        };
+
         let total_chunks = generated + (loaded as usize);
         let est_mca_mb = if total_chunks > 0 {
             // Est: chunk (4KB) + per-region header overhead
@@ -209,10 +417,20 @@ impl BenchmarkMetrics {
                - Compression: {:.2} ms/chunk\n\n\
              [Storage Read]\n\
              Chunks Loaded: {}\n\
-             Avg Time: {:.2} ms/chunk\n\n\
+             Avg Time: {:.2} ms/chunk\n\
+             Region Bulk Loads: {} (avg {:.1} chunks, {:.2} ms/region)\n\n\
              [Storage Write]\n\
              Chunks Saved: {}\n\
-             Avg Time: {:.2} ms/chunk\n\n\
+             Avg Time: {:.2} ms/chunk\n\
+             Write-back Flushes: {} (avg {:.1} chunks, {:.2} ms/flush)\n\n\
+             [Storage Health]\n\
+             Circuit Breaker: {} ({} trips, {} calls rejected)\n\
+             Read Errors: {}\n\
+             Degraded Reads: {} (generated while storage was down)\n\
+             Refused Writes: {}\n\n\
+             [Connection Pool]\n\
+             Wait: {}\n\
+             Utilisation: {}\n\n\
              [FUSE Filesystem]\n\
              Requests: {}\n\
              Avg Latency: {:.2} ms\n\
@@ -223,24 +441,51 @@ impl BenchmarkMetrics {
              Hits: {}\n\
              Misses: {}\n\
              Hit Rate: {:.1}%\n\
+             Entries: {}\n\
+             Memory: {:.2} MB\n\
+             Evictions: {}\n\n\
+             [Disk Cache]\n\
+             Hits: {}\n\
+             Misses: {}\n\
+             Hit Rate: {:.1}% (of memory misses)\n\
+             Size: {:.2} MB\n\
+             Combined Hit Rate: {:.1}% (memory + disk)\n\n\
+             [Prefetch]\n\
+             Chunks Prefetched: {}\n\
+             Directional Plans: {}\n\
+             Hit Rate: {:.1}% ({} used)\n\
+             Waste Rate: {:.1}% ({} evicted unread)\n\
+             Cancelled: {} (player moved away)\n\
              \n\
              [World Weight]\n\
              Estimated MCA Size: {:.2} MB (standard .mca files)\n\
              Actual DB Size: {:.2} MB (PostgreSQL table)\n\
-             Efficiency: {:.1}x (DB vs MCA)\n",
+             Efficiency: {:.1}x (DB vs MCA)\n\
+             Storage Compression: {} (payloads vs plain NBT)\n",
             self.config_summary,
             uptime,
             generated, gen_time_total, gen_avg, gen_max,
             biome_avg, noise_avg, surface_avg, conv_avg,
             ser_avg, comp_avg,
             loaded, load_avg,
+            region_loads, region_chunks_avg, region_load_avg,
             saved, save_avg,
+            flushes, flush_batch_avg, flush_avg,
+            breaker_state, breaker_trips, breaker_rejected,
+            storage_read_errors, degraded_reads, refused_writes,
+            pool_wait, pool_utilisation,
             // FUSE Params
             fuse_requests, fuse_avg_latency, fuse_overhead, fuse_throughput, 
             compression_ratio, avg_raw_kb, avg_comp_kb,
             hits, misses, hit_rate,
+            cache_entries, cache_mb, evictions,
+            disk_hits, disk_misses, disk_hit_rate, disk_mb, combined_hit_rate,
+            prefetch_issued, prefetch_directional,
+            prefetch_hit_rate, prefetch_hits, prefetch_waste_rate, prefetch_wasted,
+            prefetch_cancelled,
             est_mca_mb, db_size_mb,
-            if db_size_mb > 0.0 { est_mca_mb / db_size_mb } else { 0.0 }
+            if db_size_mb > 0.0 { est_mca_mb / db_size_mb } else { 0.0 },
+            storage_compression
         )
     }
 }
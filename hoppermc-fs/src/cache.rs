@@ -0,0 +1,243 @@
+// Byte-budgeted LRU cache for compressed chunk blobs.
+//
+// Chunk blobs range from a few KB (flat) to hundreds of KB (vanilla with entities),
+// so counting entries says nothing about RAM. Every entry is weighted by its blob
+// size and the least recently used entries are evicted until the budget fits.
+
+use hoppermc_benchmark::BenchmarkMetrics;
+use lru::LruCache;
+use std::sync::Arc;
+
+// Rough per-entry bookkeeping cost (key, Vec header, LRU node + hash slot).
+const ENTRY_OVERHEAD_BYTES: usize = 64;
+
+struct CacheEntry {
+    blob: Vec<u8>,
+    // Inserted by the prefetcher and not read yet
+    prefetched: bool,
+}
+
+pub struct ChunkCache {
+    entries: LruCache<(i32, i32), CacheEntry>,
+    max_bytes: usize,
+    used_bytes: usize,
+    evictions: u64,
+    benchmark: Option<Arc<BenchmarkMetrics>>,
+}
+
+impl ChunkCache {
+    pub fn new(max_bytes: usize, benchmark: Option<Arc<BenchmarkMetrics>>) -> Self {
+        Self {
+            entries: LruCache::unbounded(),
+            max_bytes,
+            used_bytes: 0,
+            evictions: 0,
+            benchmark,
+        }
+    }
+
+    fn weight(blob: &[u8]) -> usize {
+        blob.len() + ENTRY_OVERHEAD_BYTES
+    }
+
+    /// Look up a blob and mark it as most recently used.
+    pub fn get(&mut self, key: &(i32, i32)) -> Option<&Vec<u8>> {
+        self.get_tracked(key).map(|(blob, _)| blob)
+    }
+
+    /// Like `get`, but also reports whether this is the first read of a prefetched
+    /// entry (a prefetch hit).
+    pub fn get_tracked(&mut self, key: &(i32, i32)) -> Option<(&Vec<u8>, bool)> {
+        let entry = self.entries.get_mut(key)?;
+        let first_prefetch_hit = std::mem::take(&mut entry.prefetched);
+        if first_prefetch_hit && let Some(bench) = &self.benchmark {
+            bench.record_prefetch_hit();
+        }
+        Some((&entry.blob, first_prefetch_hit))
+    }
+
+    /// Check presence without touching the LRU order.
+    pub fn contains(&self, key: &(i32, i32)) -> bool {
+        self.entries.contains(key)
+    }
+
+    /// Insert (or replace) a blob, evicting least recently used entries until the
+    /// byte budget is respected. Blobs larger than the whole budget are not cached.
+    /// Returns the number of evicted entries.
+    pub fn put(&mut self, key: (i32, i32), blob: Vec<u8>) -> usize {
+        self.insert(key, blob, false)
+    }
+
+    /// Insert a blob produced by the prefetcher. It counts as a prefetch hit on its
+    /// first read, or as wasted work if it is evicted unread.
+    pub fn put_prefetched(&mut self, key: (i32, i32), blob: Vec<u8>) -> usize {
+        if let Some(bench) = &self.benchmark {
+            bench.record_prefetch_issued();
+        }
+        self.insert(key, blob, true)
+    }
+
+    fn insert(&mut self, key: (i32, i32), blob: Vec<u8>, prefetched: bool) -> usize {
+        self.remove(&key);
+
+        let weight = Self::weight(&blob);
+        if weight > self.max_bytes {
+            log::debug!("Chunk ({}, {}) blob of {} bytes exceeds cache budget, not caching", key.0, key.1, blob.len());
+            self.report();
+            return 0;
+        }
+
+        let mut evicted = 0;
+        let mut wasted = 0;
+        while self.used_bytes + weight > self.max_bytes {
+            match self.entries.pop_lru() {
+                Some((_, old)) => {
+                    self.used_bytes -= Self::weight(&old.blob);
+                    evicted += 1;
+                    if old.prefetched {
+                        wasted += 1;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        self.entries.put(key, CacheEntry { blob, prefetched });
+        self.used_bytes += weight;
+        self.evictions += evicted as u64;
+
+        if let Some(bench) = &self.benchmark {
+            bench.record_cache_evictions(evicted);
+            bench.record_prefetch_wasted(wasted);
+        }
+        self.report();
+        evicted
+    }
+
+    pub fn remove(&mut self, key: &(i32, i32)) -> Option<Vec<u8>> {
+        let old = self.entries.pop(key)?;
+        self.used_bytes -= Self::weight(&old.blob);
+        self.report();
+        Some(old.blob)
+    }
+
+    /// Bytes currently accounted against the budget (blobs + bookkeeping overhead).
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    fn report(&self) {
+        if let Some(bench) = &self.benchmark {
+            bench.record_cache_usage(self.used_bytes as u64, self.entries.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_by_bytes_not_entries() {
+        // Budget fits two 1000-byte blobs (plus overhead), not three.
+        let mut cache = ChunkCache::new(2 * (1000 + ENTRY_OVERHEAD_BYTES), None);
+        assert_eq!(cache.put((0, 0), vec![0; 1000]), 0);
+        assert_eq!(cache.put((1, 0), vec![0; 1000]), 0);
+        assert_eq!(cache.put((2, 0), vec![0; 1000]), 1);
+
+        assert!(!cache.contains(&(0, 0)));
+        assert!(cache.contains(&(1, 0)));
+        assert!(cache.contains(&(2, 0)));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.evictions(), 1);
+        assert!(cache.used_bytes() <= cache.max_bytes());
+    }
+
+    #[test]
+    fn test_large_blob_evicts_many_small() {
+        let mut cache = ChunkCache::new(10_000, None);
+        for i in 0..10 {
+            cache.put((i, 0), vec![0; 500]);
+        }
+        assert_eq!(cache.len(), 10);
+
+        // 10 * 564 + 6064 bytes needs four of the small entries gone.
+        let evicted = cache.put((100, 100), vec![0; 6000]);
+        assert_eq!(evicted, 4);
+        assert!(cache.contains(&(100, 100)));
+        assert!(cache.used_bytes() <= 10_000);
+    }
+
+    #[test]
+    fn test_replace_updates_weight() {
+        let mut cache = ChunkCache::new(100_000, None);
+        cache.put((0, 0), vec![0; 5000]);
+        cache.put((0, 0), vec![0; 100]);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.used_bytes(), 100 + ENTRY_OVERHEAD_BYTES);
+    }
+
+    #[test]
+    fn test_oversized_blob_not_cached() {
+        let mut cache = ChunkCache::new(1000, None);
+        cache.put((0, 0), vec![0; 100]);
+        assert_eq!(cache.put((1, 1), vec![0; 5000]), 0);
+        assert!(!cache.contains(&(1, 1)));
+        assert!(cache.contains(&(0, 0)));
+    }
+
+    #[test]
+    fn test_prefetch_hit_reported_once() {
+        let mut cache = ChunkCache::new(100_000, None);
+        cache.put_prefetched((0, 0), vec![1]);
+        cache.put((1, 0), vec![2]);
+
+        assert_eq!(cache.get_tracked(&(0, 0)).map(|(_, hit)| hit), Some(true));
+        assert_eq!(cache.get_tracked(&(0, 0)).map(|(_, hit)| hit), Some(false));
+        assert_eq!(cache.get_tracked(&(1, 0)).map(|(_, hit)| hit), Some(false));
+    }
+
+    #[test]
+    fn test_unread_prefetch_eviction_counts_as_waste() {
+        let bench = Arc::new(BenchmarkMetrics::default());
+        let mut cache = ChunkCache::new(2 * (10 + ENTRY_OVERHEAD_BYTES), Some(bench.clone()));
+        cache.put_prefetched((0, 0), vec![0; 10]);
+        cache.put_prefetched((1, 0), vec![0; 10]);
+        cache.get(&(1, 0));
+        cache.put((2, 0), vec![0; 10]); // evicts unread (0, 0)
+        cache.put((3, 0), vec![0; 10]); // evicts read (1, 0)
+
+        use std::sync::atomic::Ordering;
+        assert_eq!(bench.total_prefetch_issued.load(Ordering::Relaxed), 2);
+        assert_eq!(bench.total_prefetch_hits.load(Ordering::Relaxed), 1);
+        assert_eq!(bench.total_prefetch_wasted.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_get_refreshes_lru_order() {
+        let mut cache = ChunkCache::new(2 * (10 + ENTRY_OVERHEAD_BYTES), None);
+        cache.put((0, 0), vec![0; 10]);
+        cache.put((1, 0), vec![0; 10]);
+        assert!(cache.get(&(0, 0)).is_some());
+        cache.put((2, 0), vec![0; 10]);
+
+        assert!(cache.contains(&(0, 0)));
+        assert!(!cache.contains(&(1, 0)));
+    }
+}
@@ -0,0 +1,301 @@
+// Persistent second-tier chunk cache (below the in-memory LRU).
+//
+// Holds compressed chunk blobs as one file per chunk under
+// `<dir>/<storage scope>/<generator fingerprint>/c.<x>.<z>.bin`, so a restart (or a
+// generator change) never serves blobs produced by a different generator, and mounts
+// of different worlds sharing a directory never see each other's chunks. Survives restarts:
+// the index is rebuilt from the directory on open, oldest files first.
+
+use hoppermc_benchmark::BenchmarkMetrics;
+use lru::LruCache;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+struct DiskIndex {
+    entries: LruCache<(i32, i32), u64>, // value: file size in bytes
+    used_bytes: u64,
+}
+
+pub struct DiskCache {
+    root: PathBuf,
+    max_bytes: u64,
+    index: Mutex<DiskIndex>,
+    benchmark: Option<Arc<BenchmarkMetrics>>,
+}
+
+fn parse_chunk_filename(name: &str) -> Option<(i32, i32)> {
+    let parts: Vec<&str> = name.split('.').collect();
+    if parts.len() != 4 || parts[0] != "c" || parts[3] != "bin" {
+        return None;
+    }
+    let x = parts[1].parse::<i32>().ok()?;
+    let z = parts[2].parse::<i32>().ok()?;
+    Some((x, z))
+}
+
+fn sanitize_component(component: &str) -> String {
+    component
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect()
+}
+
+impl DiskCache {
+    /// `scope` identifies the storage the chunks belong to (backend, location, world, dimension).
+    pub fn open(dir: &Path, scope: &str, fingerprint: &str, max_bytes: u64, benchmark: Option<Arc<BenchmarkMetrics>>) -> anyhow::Result<Self> {
+        let root = dir.join(sanitize_component(scope)).join(sanitize_component(fingerprint));
+        std::fs::create_dir_all(&root)?;
+
+        // Rebuild index: oldest files are the first eviction candidates.
+        let mut found = Vec::new();
+        for entry in std::fs::read_dir(&root)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(key) = name.to_str().and_then(parse_chunk_filename) else {
+                continue;
+            };
+            let meta = entry.metadata()?;
+            let mtime = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
+            found.push((mtime, key, meta.len()));
+        }
+        found.sort_by_key(|(mtime, _, _)| *mtime);
+
+        let cache = Self {
+            root,
+            max_bytes,
+            index: Mutex::new(DiskIndex { entries: LruCache::unbounded(), used_bytes: 0 }),
+            benchmark,
+        };
+
+        {
+            let mut index = cache.index.lock().unwrap();
+            for (_, key, len) in found {
+                index.entries.put(key, len);
+                index.used_bytes += len;
+            }
+            cache.evict_to_fit(&mut index, 0);
+            log::info!(
+                "Disk cache at {:?}: {} chunks, {:.1} MB",
+                cache.root, index.entries.len(), index.used_bytes as f64 / 1024.0 / 1024.0
+            );
+            cache.report(&index);
+        }
+
+        Ok(cache)
+    }
+
+    /// Drops cached blobs for `keys` under every generator fingerprint of `scope`, for
+    /// changes made behind a mount's back (history restore, rollback). Returns the number
+    /// of files removed. A running mount keeps its index; it notices the missing file on
+    /// the next read and falls back to storage.
+    pub fn invalidate(dir: &Path, scope: &str, keys: &[(i32, i32)]) -> anyhow::Result<usize> {
+        let scope_dir = dir.join(sanitize_component(scope));
+        let entries = match std::fs::read_dir(&scope_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut removed = 0;
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            for (x, z) in keys {
+                match std::fs::remove_file(entry.path().join(format!("c.{}.{}.bin", x, z))) {
+                    Ok(()) => removed += 1,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    fn path_for(&self, x: i32, z: i32) -> PathBuf {
+        self.root.join(format!("c.{}.{}.bin", x, z))
+    }
+
+    pub fn get(&self, x: i32, z: i32) -> Option<Vec<u8>> {
+        {
+            let mut index = self.index.lock().unwrap();
+            index.entries.get(&(x, z))?;
+        }
+
+        match std::fs::read(self.path_for(x, z)) {
+            Ok(blob) => Some(blob),
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    log::debug!("Disk cache entry ({}, {}) was invalidated, dropping", x, z);
+                } else {
+                    log::warn!("Disk cache entry ({}, {}) unreadable, dropping: {:?}", x, z, e);
+                }
+                let mut index = self.index.lock().unwrap();
+                if let Some(len) = index.entries.pop(&(x, z)) {
+                    index.used_bytes -= len;
+                }
+                self.report(&index);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, x: i32, z: i32, blob: &[u8]) {
+        let len = blob.len() as u64;
+        if len > self.max_bytes {
+            return;
+        }
+
+        // Write to a temp file and rename, so a crash never leaves a torn blob behind.
+        let path = self.path_for(x, z);
+        let tmp = path.with_extension("tmp");
+        if let Err(e) = std::fs::write(&tmp, blob).and_then(|_| std::fs::rename(&tmp, &path)) {
+            log::warn!("Failed to write disk cache entry ({}, {}): {:?}", x, z, e);
+            let _ = std::fs::remove_file(&tmp);
+            return;
+        }
+
+        let mut index = self.index.lock().unwrap();
+        if let Some(old) = index.entries.pop(&(x, z)) {
+            index.used_bytes -= old;
+        }
+        self.evict_to_fit(&mut index, len);
+        index.entries.put((x, z), len);
+        index.used_bytes += len;
+        self.report(&index);
+    }
+
+    pub fn remove(&self, x: i32, z: i32) {
+        let mut index = self.index.lock().unwrap();
+        if let Some(len) = index.entries.pop(&(x, z)) {
+            index.used_bytes -= len;
+            let _ = std::fs::remove_file(self.path_for(x, z));
+        }
+        self.report(&index);
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.index.lock().unwrap().used_bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn evict_to_fit(&self, index: &mut DiskIndex, incoming: u64) {
+        while index.used_bytes + incoming > self.max_bytes {
+            let Some(((x, z), len)) = index.entries.pop_lru() else {
+                break;
+            };
+            index.used_bytes -= len;
+            if let Err(e) = std::fs::remove_file(self.path_for(x, z)) {
+                log::debug!("Failed to remove evicted disk cache entry ({}, {}): {:?}", x, z, e);
+            }
+        }
+    }
+
+    fn report(&self, index: &DiskIndex) {
+        if let Some(bench) = &self.benchmark {
+            bench.record_disk_cache_usage(index.used_bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hoppermc-disk-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_put_get_survives_reopen() {
+        let dir = temp_dir("reopen");
+        {
+            let cache = DiskCache::open(&dir, "pg_raw-default-overworld", "flat-v1", 1024 * 1024, None).unwrap();
+            cache.put(3, -7, &[1, 2, 3]);
+            assert_eq!(cache.get(3, -7), Some(vec![1, 2, 3]));
+        }
+
+        let cache = DiskCache::open(&dir, "pg_raw-default-overworld", "flat-v1", 1024 * 1024, None).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(3, -7), Some(vec![1, 2, 3]));
+        assert_eq!(cache.get(0, 0), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_keyed_by_fingerprint() {
+        let dir = temp_dir("fingerprint");
+        let flat = DiskCache::open(&dir, "pg_raw-default-overworld", "flat-v1", 1024 * 1024, None).unwrap();
+        flat.put(0, 0, &[9; 16]);
+
+        let vanilla = DiskCache::open(&dir, "pg_raw-default-overworld", "vanilla-overworld-42", 1024 * 1024, None).unwrap();
+        assert_eq!(vanilla.get(0, 0), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_keyed_by_storage_scope() {
+        let dir = temp_dir("scope");
+        let survival = DiskCache::open(&dir, "pg_raw-db_5432_mc-survival-overworld", "flat-v1", 1024 * 1024, None).unwrap();
+        survival.put(0, 0, &[9; 16]);
+
+        let creative = DiskCache::open(&dir, "pg_raw-db_5432_mc-creative-overworld", "flat-v1", 1024 * 1024, None).unwrap();
+        assert_eq!(creative.get(0, 0), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_evicts_to_size_limit() {
+        let dir = temp_dir("evict");
+        let cache = DiskCache::open(&dir, "pg_raw-default-overworld", "flat-v1", 250, None).unwrap();
+        cache.put(0, 0, &[0; 100]);
+        cache.put(1, 0, &[0; 100]);
+        cache.put(2, 0, &[0; 100]);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.used_bytes() <= 250);
+        assert_eq!(cache.get(0, 0), None);
+        assert!(!cache.path_for(0, 0).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_invalidate_drops_entries_for_every_fingerprint() {
+        let dir = temp_dir("invalidate");
+        let flat = DiskCache::open(&dir, "pg_raw-default-overworld", "flat-v1", 1024 * 1024, None).unwrap();
+        let vanilla = DiskCache::open(&dir, "pg_raw-default-overworld", "vanilla-overworld-42", 1024 * 1024, None).unwrap();
+        let other = DiskCache::open(&dir, "pg_raw-default-nether", "flat-v1", 1024 * 1024, None).unwrap();
+        flat.put(0, 0, &[1; 16]);
+        flat.put(1, 0, &[2; 16]);
+        vanilla.put(0, 0, &[3; 16]);
+        other.put(0, 0, &[4; 16]);
+
+        let removed = DiskCache::invalidate(&dir, "pg_raw-default-overworld", &[(0, 0), (5, 5)]).unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(flat.get(0, 0), None);
+        assert_eq!(flat.len(), 1);
+        assert_eq!(flat.get(1, 0), Some(vec![2; 16]));
+        assert_eq!(vanilla.get(0, 0), None);
+        assert_eq!(other.get(0, 0), Some(vec![4; 16]));
+
+        assert_eq!(DiskCache::invalidate(&dir, "missing-scope", &[(0, 0)]).unwrap(), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_chunk_filename() {
+        assert_eq!(parse_chunk_filename("c.-1.20.bin"), Some((-1, 20)));
+        assert_eq!(parse_chunk_filename("c.1.2.tmp"), None);
+        assert_eq!(parse_chunk_filename("r.0.0.mca"), None);
+    }
+}
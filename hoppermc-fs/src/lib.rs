@@ -1,5 +1,5 @@
 use fuser::{FileAttr, FileType, Filesystem, Request};
-use libc::{ENOENT, ENODATA};
+use libc::{ENOENT, ENODATA, EIO};
 use std::ffi::OsStr;
 use std::time::{Duration, UNIX_EPOCH, SystemTime};
 use hoppermc_anvil as region;
@@ -7,6 +7,11 @@ use hoppermc_anvil as region;
 
 pub mod virtual_file;
 pub mod inode;
+pub mod cache;
+pub mod disk_cache;
+pub mod write_back;
+pub mod prefetch;
+pub mod prefetch_queue;
 
 
 use virtual_file::VirtualFile;
@@ -286,8 +291,10 @@ impl Filesystem for McFUSE {
              
              let vf = self.virtual_file.clone();
              std::thread::spawn(move || {
-                 vf.write_at(offset, &data_vec, x, z);
-                 reply.written(data_vec.len() as u32);
+                 match vf.write_at(offset, &data_vec, x, z) {
+                     Ok(()) => reply.written(data_vec.len() as u32),
+                     Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO)),
+                 }
              });
         } else if inode::is_generic_inode(ino) {
             // Generic file, just say yes
@@ -313,7 +320,10 @@ impl Filesystem for McFUSE {
              let offset = offset as u64;
              let size = size as usize;
              
-             reply.data(&self.virtual_file.read_at(offset, size, x, z));
+             match self.virtual_file.read_at(offset, size, x, z) {
+                 Ok(data) => reply.data(&data),
+                 Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO)),
+             }
          } else if inode::is_generic_inode(ino) {
               // Generic files are empty on read
               reply.data(&[]);
@@ -348,7 +358,16 @@ impl Filesystem for McFUSE {
         _datasync: bool,
         reply: fuser::ReplyEmpty,
     ) {
-        if inode::is_region_inode(ino) || inode::is_generic_inode(ino) {
+        if inode::is_region_inode(ino) {
+            // Commit buffered chunk writes before acknowledging
+            let vf = self.virtual_file.clone();
+            std::thread::spawn(move || {
+                match vf.flush() {
+                    Ok(()) => reply.ok(),
+                    Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO)),
+                }
+            });
+        } else if inode::is_generic_inode(ino) {
             reply.ok();
         } else {
             reply.error(ENOENT);
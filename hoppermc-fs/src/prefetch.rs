@@ -0,0 +1,321 @@
+// Motion-aware prefetch planning.
+//
+// Minecraft requests chunks in a square around each player, so while a player moves
+// the newly requested chunks form a leading edge. We keep a short history of recent
+// requests, infer a heading and speed from how their centroid moves, and prefetch a
+// cone ahead of the player instead of the full square around every miss. Requests are
+// grouped by proximity, roughly one history per player (or group of players moving
+// together), so players elsewhere don't blur each other's heading. Without a clear
+// heading (spawn, standing still) we fall back to the square.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Recent requests kept to infer a heading
+const HISTORY_LEN: usize = 64;
+// Fewer requests than this can't tell movement from the initial view-distance load
+const MIN_SAMPLES: usize = 8;
+// History older than this describes a previous trip, not the current one
+const HISTORY_MAX_AGE: Duration = Duration::from_secs(10);
+// Minimum centroid displacement (chunks) to call it movement
+const MIN_DISPLACEMENT: f64 = 1.5;
+// Cone half-angle around the heading (tan(35 deg))
+const CONE_SLOPE: f64 = 0.7;
+// A request within this many chunks of a tracker's recent requests belongs to it
+const CLUSTER_RADIUS: i32 = 8;
+// Trackers kept at once (the stalest is dropped beyond this)
+const MAX_TRACKERS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrefetchShape {
+    /// Full square of `radius` around every miss.
+    Square,
+    /// Cone of `lookahead` chunks along the inferred heading (square fallback).
+    Motion { lookahead: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heading {
+    /// Unit vector of travel in chunk space.
+    pub dx: f64,
+    pub dz: f64,
+    /// Chunks per second.
+    pub speed: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefetchTarget {
+    pub x: i32,
+    pub z: i32,
+    /// Distance from the triggering chunk (chunks).
+    pub distance: f64,
+    /// Expected time until the player reaches it (None without a heading).
+    pub eta: Option<Duration>,
+}
+
+#[derive(Default)]
+struct MotionTracker {
+    history: VecDeque<(i32, i32, Instant)>,
+}
+
+impl MotionTracker {
+    /// Chebyshev distance from (x, z) to the closest recorded request.
+    fn distance(&self, x: i32, z: i32) -> Option<i32> {
+        self.history.iter().map(|(hx, hz, _)| (hx - x).abs().max((hz - z).abs())).min()
+    }
+
+    fn last_seen(&self) -> Option<Instant> {
+        self.history.back().map(|(_, _, at)| *at)
+    }
+
+    fn record(&mut self, x: i32, z: i32, now: Instant) -> Option<Heading> {
+        let history = &mut self.history;
+        while history.front().is_some_and(|(_, _, at)| now.duration_since(*at) > HISTORY_MAX_AGE) {
+            history.pop_front();
+        }
+        history.push_back((x, z, now));
+        if history.len() > HISTORY_LEN {
+            history.pop_front();
+        }
+        infer_heading(history)
+    }
+}
+
+/// Split the history into four consecutive quarters and compare their centroids
+/// (position and time). Movement means the centroid advances along the same
+/// heading from every quarter to the next; a stationary view-distance load spirals
+/// around the player and fails that check even when its first and last quarters
+/// happen to be apart.
+fn infer_heading(history: &VecDeque<(i32, i32, Instant)>) -> Option<Heading> {
+    if history.len() < MIN_SAMPLES {
+        return None;
+    }
+    let base = history.front()?.2;
+    let quarter = history.len() / 4;
+
+    let centroids: Vec<(f64, f64, f64)> = (0..4)
+        .map(|i| {
+            let end = if i == 3 { history.len() } else { (i + 1) * quarter };
+            let (mut sx, mut sz, mut st) = (0.0, 0.0, 0.0);
+            for (x, z, at) in history.range(i * quarter..end) {
+                sx += *x as f64;
+                sz += *z as f64;
+                st += at.duration_since(base).as_secs_f64();
+            }
+            let n = (end - i * quarter) as f64;
+            (sx / n, sz / n, st / n)
+        })
+        .collect();
+
+    let (first, last) = (centroids[0], centroids[3]);
+    let (dx, dz) = (last.0 - first.0, last.1 - first.1);
+    let displacement = (dx * dx + dz * dz).sqrt();
+    if displacement < MIN_DISPLACEMENT {
+        return None;
+    }
+    let (ux, uz) = (dx / displacement, dz / displacement);
+    let progresses = centroids
+        .windows(2)
+        .all(|w| (w[1].0 - w[0].0) * ux + (w[1].1 - w[0].1) * uz > 0.0);
+    if !progresses {
+        return None;
+    }
+    let dt = (last.2 - first.2).max(0.05);
+    Some(Heading { dx: ux, dz: uz, speed: displacement / dt })
+}
+
+pub struct PrefetchPlanner {
+    radius: u8,
+    shape: PrefetchShape,
+    // One per cluster of recent requests
+    trackers: Vec<MotionTracker>,
+}
+
+impl PrefetchPlanner {
+    pub fn new(radius: u8, shape: PrefetchShape) -> Self {
+        Self { radius, shape, trackers: Vec::new() }
+    }
+
+    /// The tracker for the cluster (x, z) falls in, creating one if it starts a new cluster.
+    fn tracker(&mut self, x: i32, z: i32, now: Instant) -> &mut MotionTracker {
+        self.trackers.retain(|t| t.last_seen().is_some_and(|at| now.duration_since(at) <= HISTORY_MAX_AGE));
+        let nearest = self.trackers.iter()
+            .enumerate()
+            .filter_map(|(i, t)| t.distance(x, z).map(|d| (i, d)))
+            .filter(|(_, d)| *d <= CLUSTER_RADIUS)
+            .min_by_key(|(_, d)| *d)
+            .map(|(i, _)| i);
+        let index = match nearest {
+            Some(i) => i,
+            None => {
+                if self.trackers.len() >= MAX_TRACKERS
+                    && let Some(stalest) = self.trackers.iter().enumerate().min_by_key(|(_, t)| t.last_seen()).map(|(i, _)| i)
+                {
+                    self.trackers.swap_remove(stalest);
+                }
+                self.trackers.push(MotionTracker::default());
+                self.trackers.len() - 1
+            }
+        };
+        &mut self.trackers[index]
+    }
+
+    pub fn radius(&self) -> u8 {
+        self.radius
+    }
+
+    /// Furthest distance (chunks) a plan can reach from its miss.
+    pub fn reach(&self) -> u8 {
+        match self.shape {
+            PrefetchShape::Square => self.radius,
+            PrefetchShape::Motion { lookahead } => self.radius.max(lookahead),
+        }
+    }
+
+    /// Record a newly requested chunk and return what to prefetch around it, most
+    /// urgent first. The bool is true when the plan follows an inferred heading.
+    pub fn plan(&mut self, x: i32, z: i32) -> (Vec<PrefetchTarget>, bool) {
+        self.plan_at(x, z, Instant::now())
+    }
+
+    fn plan_at(&mut self, x: i32, z: i32, now: Instant) -> (Vec<PrefetchTarget>, bool) {
+        match self.shape {
+            PrefetchShape::Square => (square(x, z, self.radius as i32), false),
+            PrefetchShape::Motion { lookahead } => match self.tracker(x, z, now).record(x, z, now) {
+                Some(heading) => (cone(x, z, heading, lookahead as i32), true),
+                None => (square(x, z, self.radius as i32), false),
+            },
+        }
+    }
+}
+
+fn square(cx: i32, cz: i32, radius: i32) -> Vec<PrefetchTarget> {
+    let mut targets = Vec::new();
+    for dx in -radius..=radius {
+        for dz in -radius..=radius {
+            if dx == 0 && dz == 0 {
+                continue;
+            }
+            let distance = ((dx * dx + dz * dz) as f64).sqrt();
+            targets.push(PrefetchTarget { x: cx + dx, z: cz + dz, distance, eta: None });
+        }
+    }
+    targets.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    targets
+}
+
+fn cone(cx: i32, cz: i32, heading: Heading, lookahead: i32) -> Vec<PrefetchTarget> {
+    let mut targets = Vec::new();
+    for dx in -lookahead..=lookahead {
+        for dz in -lookahead..=lookahead {
+            let (fx, fz) = (dx as f64, dz as f64);
+            let along = fx * heading.dx + fz * heading.dz;
+            if along <= 0.0 || along > lookahead as f64 {
+                continue; // behind the player or past the lookahead
+            }
+            let lateral = (fx * heading.dz - fz * heading.dx).abs();
+            if lateral > 1.0 + along * CONE_SLOPE {
+                continue;
+            }
+            let eta = Duration::from_secs_f64(along / heading.speed.max(0.1));
+            let distance = (fx * fx + fz * fz).sqrt();
+            targets.push(PrefetchTarget { x: cx + dx, z: cz + dz, distance, eta: Some(eta) });
+        }
+    }
+    // Soonest arrival first; chunks off the centre line break ties
+    targets.sort_by(|a, b| a.eta.cmp(&b.eta).then(a.distance.total_cmp(&b.distance)));
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square_without_history() {
+        let mut planner = PrefetchPlanner::new(1, PrefetchShape::Motion { lookahead: 6 });
+        let (targets, directional) = planner.plan(0, 0);
+        assert!(!directional);
+        assert_eq!(targets.len(), 8);
+    }
+
+    #[test]
+    fn test_cone_follows_heading() {
+        let mut planner = PrefetchPlanner::new(1, PrefetchShape::Motion { lookahead: 6 });
+        let start = Instant::now();
+
+        // Player flies +X: leading edge column advances by one chunk every 100ms
+        let mut result = (Vec::new(), false);
+        for step in 0..12 {
+            let now = start + Duration::from_millis(step * 100);
+            for z in -2..=2 {
+                result = planner.plan_at(step as i32, z, now);
+            }
+        }
+
+        let (targets, directional) = result;
+        assert!(directional);
+        assert!(!targets.is_empty());
+        // Nothing behind the player, and the cone reaches past the square radius
+        assert!(targets.iter().all(|t| t.x > 11));
+        assert!(targets.iter().any(|t| t.x >= 11 + 5));
+        // Sorted by expected arrival
+        assert!(targets.windows(2).all(|w| w[0].eta <= w[1].eta));
+    }
+
+    #[test]
+    fn test_two_players_keep_their_own_heading() {
+        let mut planner = PrefetchPlanner::new(1, PrefetchShape::Motion { lookahead: 6 });
+        let start = Instant::now();
+
+        // Player A flies +X near the origin while player B flies -Z far away; their requests interleave
+        let (mut a, mut b) = ((Vec::new(), false), (Vec::new(), false));
+        for step in 0..12 {
+            let now = start + Duration::from_millis(step * 100);
+            for offset in -2..=2 {
+                a = planner.plan_at(step as i32, offset, now);
+                b = planner.plan_at(1000 + offset, -(step as i32), now);
+            }
+        }
+
+        assert_eq!(planner.trackers.len(), 2);
+        let ((a, a_directional), (b, b_directional)) = (a, b);
+        assert!(a_directional && b_directional);
+        assert!(a.iter().all(|t| t.x > 11));
+        assert!(b.iter().all(|t| t.z < -11));
+    }
+
+    #[test]
+    fn test_stationary_falls_back_to_square() {
+        let mut planner = PrefetchPlanner::new(2, PrefetchShape::Motion { lookahead: 6 });
+        let start = Instant::now();
+
+        // Initial view-distance load around (0, 0): concentric rings, each swept in angle order
+        let mut requests = vec![(0, 0)];
+        for r in 1..=3i32 {
+            let mut ring: Vec<(i32, i32)> = (-r..=r)
+                .flat_map(|x| (-r..=r).map(move |z| (x, z)))
+                .filter(|(x, z)| x.abs().max(z.abs()) == r)
+                .collect();
+            ring.sort_by(|a, b| (a.1 as f64).atan2(a.0 as f64).total_cmp(&(b.1 as f64).atan2(b.0 as f64)));
+            requests.extend(ring);
+        }
+
+        for (i, (x, z)) in requests.into_iter().enumerate() {
+            let (targets, directional) = planner.plan_at(x, z, start + Duration::from_millis(i as u64 * 10));
+            assert!(!directional, "ring load at ({}, {}) looked like movement", x, z);
+            assert_eq!(targets.len(), 24);
+        }
+    }
+
+    #[test]
+    fn test_square_shape_ignores_motion() {
+        let mut planner = PrefetchPlanner::new(1, PrefetchShape::Square);
+        let start = Instant::now();
+        for step in 0..12 {
+            let (targets, directional) = planner.plan_at(step, 0, start + Duration::from_millis(step as u64 * 100));
+            assert!(!directional);
+            assert_eq!(targets.len(), 8);
+        }
+    }
+}
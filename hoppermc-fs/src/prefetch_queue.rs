@@ -0,0 +1,282 @@
+// Prioritised, cancellable prefetch work queue.
+//
+// Prefetch plans are pushed as jobs into a priority heap drained by a fixed pool of
+// workers. The newest plan goes first (it belongs to where the player is now), and
+// within a plan its own order (nearest / soonest arrival) is kept. Workers stay idle
+// while any foreground miss is being served, so prefetch never competes with the chunk
+// the server is blocked on. Jobs whose target is far from every recent miss are
+// dropped when popped instead of being generated for nobody.
+
+use hoppermc_benchmark::BenchmarkMetrics;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+// Recent miss positions used to decide whether a queued job is still wanted
+const RECENT_CENTERS: usize = 16;
+
+struct Job {
+    x: i32,
+    z: i32,
+    // Plan sequence number: newer plans are more urgent
+    plan: u64,
+    // Position within its plan: lower is more urgent
+    rank: usize,
+    // Identifies the latest queued entry for this chunk (older entries are skipped)
+    seq: u64,
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.plan.cmp(&other.plan).then(other.rank.cmp(&self.rank))
+    }
+}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+#[derive(Default)]
+struct QueueState {
+    heap: BinaryHeap<Job>,
+    queued: HashMap<(i32, i32), u64>,
+    recent_centers: VecDeque<(i32, i32)>,
+    next_plan: u64,
+    next_seq: u64,
+    foreground: usize,
+    closed: bool,
+}
+
+impl QueueState {
+    fn is_stale(&self, x: i32, z: i32, max_distance: i32) -> bool {
+        !self.recent_centers.is_empty()
+            && self.recent_centers.iter().all(|(cx, cz)| (x - cx).abs().max((z - cz).abs()) > max_distance)
+    }
+}
+
+pub struct PrefetchQueue {
+    state: Mutex<QueueState>,
+    wake: Notify,
+    max_distance: i32,
+    benchmark: Option<Arc<BenchmarkMetrics>>,
+}
+
+/// Marks a foreground miss in progress; workers wait until every guard is dropped.
+pub struct ForegroundGuard<'a> {
+    queue: &'a PrefetchQueue,
+}
+
+impl Drop for ForegroundGuard<'_> {
+    fn drop(&mut self) {
+        let idle = {
+            let mut state = self.queue.state.lock().unwrap();
+            state.foreground -= 1;
+            state.foreground == 0
+        };
+        if idle {
+            self.queue.wake.notify_waiters();
+        }
+    }
+}
+
+impl PrefetchQueue {
+    /// `max_distance`: jobs further than this (chunks) from every recent miss are dropped.
+    pub fn new(max_distance: u8, benchmark: Option<Arc<BenchmarkMetrics>>) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            wake: Notify::new(),
+            max_distance: max_distance as i32,
+            benchmark,
+        }
+    }
+
+    /// Default worker count: half the cores (generation is CPU bound and the
+    /// foreground needs the rest), at least one.
+    pub fn default_concurrency() -> usize {
+        std::thread::available_parallelism().map(|n| n.get() / 2).unwrap_or(1).max(1)
+    }
+
+    /// Queue a plan (targets most urgent first) around the miss at `center`.
+    /// Chunks already queued are re-prioritised to this plan.
+    pub fn push_plan(&self, center: (i32, i32), targets: impl IntoIterator<Item = (i32, i32)>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+            if state.recent_centers.front() != Some(&center) {
+                state.recent_centers.push_front(center);
+                state.recent_centers.truncate(RECENT_CENTERS);
+            }
+            state.next_plan += 1;
+            let plan = state.next_plan;
+
+            for (rank, (x, z)) in targets.into_iter().enumerate() {
+                state.next_seq += 1;
+                let seq = state.next_seq;
+                state.queued.insert((x, z), seq);
+                state.heap.push(Job { x, z, plan, rank, seq });
+            }
+        }
+        self.wake.notify_waiters();
+    }
+
+    /// Hold while serving a foreground miss.
+    pub fn foreground(&self) -> ForegroundGuard<'_> {
+        self.state.lock().unwrap().foreground += 1;
+        ForegroundGuard { queue: self }
+    }
+
+    /// Number of chunks waiting to be prefetched.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().queued.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stop the workers and drop everything queued.
+    pub fn close(&self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            state.heap.clear();
+            state.queued.clear();
+        }
+        self.wake.notify_waiters();
+    }
+
+    /// Wait for the next jobs to run (at most `max`, most urgent first). Returns None once
+    /// the queue is closed.
+    pub async fn next_batch(&self, max: usize) -> Option<Vec<(i32, i32)>> {
+        loop {
+            let notified = self.wake.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let jobs = self.try_pop(max)?;
+            if !jobs.is_empty() {
+                return Some(jobs);
+            }
+            notified.await;
+        }
+    }
+
+    /// None: closed. Empty: nothing runnable right now.
+    fn try_pop(&self, max: usize) -> Option<Vec<(i32, i32)>> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return None;
+        }
+        if state.foreground > 0 {
+            return Some(Vec::new());
+        }
+
+        let mut cancelled = 0;
+        let mut found = Vec::new();
+        while found.len() < max && let Some(job) = state.heap.pop() {
+            if state.queued.get(&(job.x, job.z)) != Some(&job.seq) {
+                continue; // superseded by a newer plan
+            }
+            state.queued.remove(&(job.x, job.z));
+            if state.is_stale(job.x, job.z, self.max_distance) {
+                cancelled += 1;
+                continue;
+            }
+            found.push((job.x, job.z));
+        }
+
+        if cancelled > 0 && let Some(bench) = &self.benchmark {
+            bench.record_prefetch_cancelled(cancelled);
+        }
+        Some(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn pop(queue: &PrefetchQueue) -> Option<(i32, i32)> {
+        queue.try_pop(1).and_then(|jobs| jobs.first().copied())
+    }
+
+    #[test]
+    fn test_newest_plan_first_then_plan_order() {
+        let queue = PrefetchQueue::new(8, None);
+        queue.push_plan((0, 0), [(1, 0), (2, 0)]);
+        queue.push_plan((1, 0), [(2, 0), (3, 0)]);
+
+        // (2, 0) was re-prioritised to the newer plan and is only returned once
+        assert_eq!(pop(&queue), Some((2, 0)));
+        assert_eq!(pop(&queue), Some((3, 0)));
+        assert_eq!(pop(&queue), Some((1, 0)));
+        assert_eq!(pop(&queue), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_batches_keep_priority_order() {
+        let queue = PrefetchQueue::new(8, None);
+        queue.push_plan((0, 0), [(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(queue.try_pop(2), Some(vec![(1, 0), (2, 0)]));
+        assert_eq!(queue.try_pop(2), Some(vec![(3, 0)]));
+        assert_eq!(queue.try_pop(2), Some(vec![]));
+    }
+
+    #[test]
+    fn test_far_jobs_are_cancelled() {
+        let bench = Arc::new(BenchmarkMetrics::default());
+        let queue = PrefetchQueue::new(4, Some(bench.clone()));
+        queue.push_plan((0, 0), [(1, 0), (2, 0)]);
+        for i in 1..=RECENT_CENTERS as i32 {
+            queue.push_plan((i * 100, 0), []);
+        }
+
+        assert_eq!(pop(&queue), None);
+        assert_eq!(bench.total_prefetch_cancelled.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_foreground_pauses_workers() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let queue = Arc::new(PrefetchQueue::new(8, None));
+        queue.push_plan((0, 0), [(1, 1)]);
+
+        let guard = queue.foreground();
+        let worker = {
+            let queue = queue.clone();
+            rt.spawn(async move { queue.next_batch(4).await })
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!worker.is_finished());
+
+        drop(guard);
+        assert_eq!(rt.block_on(worker).unwrap(), Some(vec![(1, 1)]));
+    }
+
+    #[test]
+    fn test_close_stops_workers() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let queue = Arc::new(PrefetchQueue::new(8, None));
+        let worker = {
+            let queue = queue.clone();
+            rt.spawn(async move { queue.next_batch(4).await })
+        };
+        queue.close();
+        assert_eq!(rt.block_on(worker).unwrap(), None);
+    }
+}
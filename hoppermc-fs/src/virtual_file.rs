@@ -1,19 +1,68 @@
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
 use hoppermc_gen::WorldGenerator;
 use hoppermc_anvil as region;
 use hoppermc_storage::ChunkStorage;
 use hoppermc_benchmark::BenchmarkMetrics;
-use lru::LruCache;
-use std::num::NonZeroUsize;
+use crate::cache::ChunkCache;
+use crate::disk_cache::DiskCache;
+use crate::prefetch::{PrefetchPlanner, PrefetchShape};
+use crate::prefetch_queue::PrefetchQueue;
+use crate::write_back::{WriteBackBuffer, WriteBackConfig, WriteDurability};
+
+// A region's header is re-read whenever the server reopens it; don't bulk load it again this soon
+const REGION_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+// Longest a foreground miss waits for an in-flight region bulk load before querying itself
+const REGION_LOAD_WAIT: Duration = Duration::from_secs(5);
+// Prefetch targets a worker loads from storage in one round-trip; small so the most urgent
+// chunks still spread over the workers
+const PREFETCH_BATCH: usize = 8;
+
+// Backoff between storage retries under the retry policy
+const RETRY_BACKOFF_MIN: Duration = Duration::from_millis(25);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_millis(200);
+/// Longest the retry policy blocks a read. Retries sleep on the FUSE session thread,
+/// which serves nothing else meanwhile.
+pub const MAX_RETRY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// What reads do while storage is unavailable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DegradedPolicy {
+    /// Fail the read with EIO.
+    Fail,
+    /// Block and retry until storage answers or the timeout (at most `MAX_RETRY_TIMEOUT`)
+    /// passes, then EIO.
+    Retry { timeout: Duration },
+    /// Serve a generated chunk but refuse to write it back (it would overwrite the
+    /// real stored chunk).
+    ReadOnly,
+}
+
+struct RegionLoad {
+    started: Instant,
+    done: tokio::sync::watch::Receiver<bool>,
+    // Chunks written while the load was in flight; what it read for them is stale
+    written: Arc<Mutex<HashSet<(i32, i32)>>>,
+}
 
 pub struct VirtualFile {
     pub generator: Arc<dyn WorldGenerator>,
     pub storage: Option<Arc<dyn ChunkStorage>>,
     pub rt: tokio::runtime::Handle,
     pub benchmark: Option<Arc<BenchmarkMetrics>>,
-    pub cache: Arc<Mutex<LruCache<(i32, i32), Vec<u8>>>>,
+    pub cache: Arc<Mutex<ChunkCache>>,
+    pub disk_cache: Option<Arc<DiskCache>>,
+    pub write_back: Option<Arc<WriteBackBuffer>>,
     pub prefetch_radius: u8,
-    pub prefetch_limiter: Arc<tokio::sync::Semaphore>,
+    pub prefetch_queue: Arc<PrefetchQueue>,
+    prefetch_concurrency: usize,
+    prefetch_workers_started: Once,
+    prefetch_planner: Mutex<PrefetchPlanner>,
+    region_loads: Mutex<HashMap<(i32, i32), RegionLoad>>,
+    degraded_policy: DegradedPolicy,
+    // Chunks served without their stored version; writes to them are refused
+    degraded_chunks: Mutex<HashSet<(i32, i32)>>,
     header_cache: Vec<u8>, // Cached header
 }
 
@@ -23,26 +72,116 @@ impl VirtualFile {
         storage: Option<Arc<dyn ChunkStorage>>, 
         rt: tokio::runtime::Handle,
         benchmark: Option<Arc<BenchmarkMetrics>>,
-        cache_size: usize,
+        cache_max_bytes: usize,
         prefetch_radius: u8,
     ) -> Self {
-        let cap = NonZeroUsize::new(cache_size).unwrap_or(NonZeroUsize::new(500).unwrap());
-        // Limit concurrent heavy generations (e.g. 2 threads to avoid starvation)
-        let limiter = Arc::new(tokio::sync::Semaphore::new(2));
+        let planner = PrefetchPlanner::new(prefetch_radius, PrefetchShape::Square);
+        let queue = PrefetchQueue::new(planner.reach(), benchmark.clone());
+        let cache = ChunkCache::new(cache_max_bytes, benchmark.clone());
         
         Self { 
             generator, 
             storage, 
             rt, 
             benchmark,
-            cache: Arc::new(Mutex::new(LruCache::new(cap))),
+            cache: Arc::new(Mutex::new(cache)),
+            disk_cache: None,
+            write_back: None,
             prefetch_radius,
-            prefetch_limiter: limiter,
+            prefetch_queue: Arc::new(queue),
+            // Workers are started on the first prefetch, so builders can still change this
+            prefetch_concurrency: PrefetchQueue::default_concurrency(),
+            prefetch_workers_started: Once::new(),
+            prefetch_planner: Mutex::new(planner),
+            region_loads: Mutex::new(HashMap::new()),
+            degraded_policy: DegradedPolicy::Fail,
+            degraded_chunks: Mutex::new(HashSet::new()),
             header_cache: region::generate_header(),
         }
     }
 
-    pub fn read_at(&self, offset: u64, size: usize, region_x: i32, region_z: i32) -> Vec<u8> {
+    /// Choose how prefetch targets are picked around each miss.
+    pub fn with_prefetch_shape(mut self, shape: PrefetchShape) -> Self {
+        let planner = PrefetchPlanner::new(self.prefetch_radius, shape);
+        self.prefetch_queue = Arc::new(PrefetchQueue::new(planner.reach(), self.benchmark.clone()));
+        self.prefetch_planner = Mutex::new(planner);
+        self
+    }
+
+    /// Number of background prefetch workers (defaults to half the CPU cores).
+    pub fn with_prefetch_concurrency(mut self, workers: usize) -> Self {
+        self.prefetch_concurrency = workers.max(1);
+        self
+    }
+
+    /// Choose how reads behave while storage is unavailable.
+    pub fn with_degraded_policy(mut self, policy: DegradedPolicy) -> Self {
+        self.degraded_policy = policy;
+        self
+    }
+
+    /// Attach a persistent disk tier below the in-memory cache.
+    pub fn with_disk_cache(mut self, disk_cache: DiskCache) -> Self {
+        self.disk_cache = Some(Arc::new(disk_cache));
+        self
+    }
+
+    /// Buffer intercepted writes and flush them to storage in batches.
+    /// No-op without storage (there is nothing to write back to).
+    pub fn with_write_back(mut self, config: WriteBackConfig) -> Self {
+        if let Some(storage) = &self.storage {
+            let buffer = Arc::new(WriteBackBuffer::new(storage.clone(), config, self.benchmark.clone()));
+            buffer.spawn_flusher(&self.rt);
+            self.write_back = Some(buffer);
+        }
+        self
+    }
+
+    /// Flush buffered writes to storage (fsync). Must not be called from within the runtime.
+    pub fn flush(&self) -> std::io::Result<()> {
+        if let Some(wb) = &self.write_back {
+            self.rt.block_on(wb.flush()).map_err(|e| {
+                log::error!("Flush failed: {:?}", e);
+                std::io::Error::from_raw_os_error(libc::EIO)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Load a chunk from storage, retrying per the degraded policy. A successful answer
+    /// (found or not) lifts the write protection of a chunk served while storage was down.
+    fn load_stored(&self, storage: &Arc<dyn ChunkStorage>, x: i32, z: i32) -> anyhow::Result<Option<Vec<u8>>> {
+        let deadline = match self.degraded_policy {
+            DegradedPolicy::Retry { timeout } => Some(Instant::now() + timeout.min(MAX_RETRY_TIMEOUT)),
+            _ => None,
+        };
+        let mut backoff = RETRY_BACKOFF_MIN;
+
+        loop {
+            let start = Instant::now();
+            let result = self.rt.block_on(storage.load_chunk(x, z));
+            if let Some(bench) = &self.benchmark {
+                bench.record_load(start.elapsed());
+            }
+
+            match result {
+                Ok(data) => {
+                    self.degraded_chunks.lock().unwrap().remove(&(x, z));
+                    return Ok(data);
+                }
+                Err(e) => match deadline {
+                    Some(deadline) if Instant::now() + backoff < deadline => {
+                        log::warn!("Storage unavailable loading ({}, {}), retrying in {:?}: {}", x, z, backoff, e);
+                        std::thread::sleep(backoff);
+                        backoff = (backoff * 2).min(RETRY_BACKOFF_MAX);
+                    }
+                    _ => return Err(e),
+                },
+            }
+        }
+    }
+
+    pub fn read_at(&self, offset: u64, size: usize, region_x: i32, region_z: i32) -> std::io::Result<Vec<u8>> {
         let mut response_data = Vec::with_capacity(size);
 
         // --- 1. HEADER GENERATION (0..8192) ---
@@ -52,6 +191,9 @@ impl VirtualFile {
                 log::debug!("Region r.{}.{} Read Header at 0, size {}", region_x, region_z, size);
             }
 
+            // The server is opening this region: fetch its stored chunks in one query
+            self.trigger_region_load(region_x, region_z);
+
             let start_in_header = offset as usize;
             let end_in_header = std::cmp::min(start_in_header + size, region::HEADER_BYTES as usize);
             if start_in_header < region::HEADER_BYTES as usize {
@@ -72,30 +214,46 @@ impl VirtualFile {
                 let abs_x = region_x * 32 + rel_x;
                 let abs_z = region_z * 32 + rel_z;
                 
-                // Check Cache first
-                let cached_blob: Option<Vec<u8>> = {
+                // Check Cache first (after any bulk load of this region has landed)
+                self.wait_for_region_load(region_x, region_z);
+                let (cached_blob, prefetch_hit) = {
                     let mut cache = self.cache.lock().unwrap();
-                    cache.get(&(abs_x, abs_z)).cloned()
+                    match cache.get_tracked(&(abs_x, abs_z)) {
+                        Some((blob, hit)) => (Some(blob.clone()), hit),
+                        None => (None, false),
+                    }
                 };
                 
+                // Memory miss: try the disk tier before storage/generation
+                let disk_blob = if cached_blob.is_none() { self.disk_get(abs_x, abs_z) } else { None };
+
                 let chunk_blob = if let Some(blob) = cached_blob {
                     if let Some(bench) = &self.benchmark { bench.record_cache_hit(); }
+                    // First read of a prefetched chunk: keep feeding the motion tracker
+                    if prefetch_hit && self.prefetch_radius > 0 {
+                        self.trigger_prefetch(abs_x, abs_z);
+                    }
+                    blob
+                } else if let Some(blob) = disk_blob {
+                    if let Some(bench) = &self.benchmark { bench.record_cache_miss(); }
+                    self.cache.lock().unwrap().put((abs_x, abs_z), blob.clone());
+                    if self.prefetch_radius > 0 {
+                        self.trigger_prefetch(abs_x, abs_z);
+                    }
                     blob
                 } else {
                     if let Some(bench) = &self.benchmark { bench.record_cache_miss(); }
-                    // CACHE MISS - Load/Generate
+                    // CACHE MISS - Load/Generate. Prefetch workers wait until this is served.
+                    let _foreground = self.prefetch_queue.foreground();
+                    // Generated while storage was down: never cached, so recovery serves the stored chunk
+                    let mut degraded = false;
                     
-                    // 1. Try to load from Storage first (if storage is enabled)
-                    let nbt_res = if let Some(storage) = &self.storage {
-                        let start = std::time::Instant::now();
-                        let storage_data = self.rt.block_on(async {
-                            storage.load_chunk(abs_x, abs_z).await
-                        });
-                        if let Some(bench) = &self.benchmark {
-                            bench.record_load(start.elapsed());
-                        }
-
-                        match storage_data {
+                    // 1. Try to load from Storage first (if storage is enabled).
+                    //    Buffered writes are newer than anything stored.
+                    let nbt_res = if let Some(nbt) = self.write_back.as_ref().and_then(|wb| wb.get(abs_x, abs_z)) {
+                        Ok(nbt)
+                    } else if let Some(storage) = &self.storage {
+                        match self.load_stored(storage, abs_x, abs_z) {
                             Ok(Some(raw_nbt)) => {
                                 // Found in DB! Verify consistency
                                 if let Err(e) = region::verify_chunk_coords(&raw_nbt, abs_x, abs_z) {
@@ -117,7 +275,17 @@ impl VirtualFile {
                                 res
                             },
                             Err(e) => {
-                                log::error!("Error loading chunk from DB: {:?}", e);
+                                // Storage is down: the real chunk may exist, so a generated stand-in
+                                // must never be written back over it.
+                                log::error!("Error loading chunk ({}, {}) from DB: {:?}", abs_x, abs_z, e);
+                                if let Some(bench) = &self.benchmark { bench.record_storage_read_error(); }
+
+                                if self.degraded_policy != DegradedPolicy::ReadOnly {
+                                    return Err(std::io::Error::from_raw_os_error(libc::EIO));
+                                }
+                                log::warn!("Serving generated chunk ({}, {}) read-only while storage is unavailable", abs_x, abs_z);
+                                if let Some(bench) = &self.benchmark { bench.record_degraded_read(); }
+                                degraded = true;
                                 let start_gen = std::time::Instant::now();
                                 let res = self.generator.generate_chunk(abs_x, abs_z, &self.rt, self.benchmark.as_deref());
                                 if let Some(bench) = &self.benchmark { bench.record_generation(start_gen.elapsed()); }
@@ -145,8 +313,18 @@ impl VirtualFile {
                             if let Some(bench) = &self.benchmark { bench.record_compression(start_comp.elapsed()); }
 
                             if let Some(blob) = blob_opt {
-                                // Update Cache
-                                self.cache.lock().unwrap().put((abs_x, abs_z), blob.clone());
+                                // Write-protect what the server gets instead of the stored chunk
+                                if degraded {
+                                    self.degraded_chunks.lock().unwrap().insert((abs_x, abs_z));
+                                }
+
+                                // Update Cache (both tiers)
+                                if !degraded {
+                                    self.cache.lock().unwrap().put((abs_x, abs_z), blob.clone());
+                                    if let Some(disk) = &self.disk_cache {
+                                        disk.put(abs_x, abs_z, &blob);
+                                    }
+                                }
 
                                 // Record Sizes (Only if we just generated/compressed it)
                                 if let Some(bench) = &self.benchmark {
@@ -213,9 +391,9 @@ impl VirtualFile {
             log::warn!("CRITICAL: read_at returning 0 bytes for non-zero request size {} at offset {} (r.{}.{})", size, offset, region_x, region_z);
         }
 
-        response_data
+        Ok(response_data)
     }
-    pub fn write_at(&self, offset: u64, data: &[u8], region_x: i32, region_z: i32) {
+    pub fn write_at(&self, offset: u64, data: &[u8], region_x: i32, region_z: i32) -> std::io::Result<()> {
         // --- WRITE INTERCEPTION ---
         // If writing to header area (0..8192) -> Ignore (it's virtual).
         // If writing data area:
@@ -268,9 +446,33 @@ impl VirtualFile {
                      };
                      
                      log::info!("Intercepted write for Chunk ({}, {}). Size: {} bytes.", save_x, save_z, raw_nbt.len());
+
+                     // The server never saw the stored version of this chunk: writing it back
+                     // would replace real terrain with what was generated during the outage.
+                     if self.degraded_chunks.lock().unwrap().contains(&(save_x, save_z)) {
+                         log::warn!("Refusing write-back of chunk ({}, {}): it was not served from storage", save_x, save_z);
+                         if let Some(bench) = &self.benchmark { bench.record_refused_write(); }
+                         return Err(std::io::Error::from_raw_os_error(libc::EROFS));
+                     }
                      
                      // 3. Save to DB (if storage is enabled)
-                     if let Some(storage) = &self.storage {
+                     self.mark_written(save_x, save_z);
+                     if let Some(wb) = &self.write_back {
+                         let generation = match self.rt.block_on(wb.mark_dirty(save_x, save_z, raw_nbt.clone())) {
+                             Ok(generation) => generation,
+                             Err(e) => {
+                                 log::error!("Failed to buffer chunk ({}, {}): {:?}", save_x, save_z, e);
+                                 return Err(std::io::Error::from_raw_os_error(libc::ENOSPC));
+                             }
+                         };
+                         if wb.durability() == WriteDurability::Commit {
+                             if let Err(e) = self.rt.block_on(wb.commit(save_x, save_z, generation)) {
+                                 log::error!("Failed to commit chunk ({}, {}) to DB: {:?}", save_x, save_z, e);
+                                 return Err(std::io::Error::from_raw_os_error(libc::EIO));
+                             }
+                         }
+                         self.cache_written_chunk(save_x, save_z, &raw_nbt);
+                     } else if let Some(storage) = &self.storage {
                          let start = std::time::Instant::now();
                          let result = self.rt.block_on(async {
                              storage.save_chunk(save_x, save_z, &raw_nbt).await
@@ -285,10 +487,7 @@ impl VirtualFile {
                              log::debug!("Chunk ({}, {}) saved to DB successfully.", save_x, save_z);
                              
                              // Update Cache with NEW BLOB
-                             if let Some(new_blob) = region::compress_and_wrap_chunk(&raw_nbt) {
-                                 let mut cache = self.cache.lock().unwrap();
-                                 cache.put((save_x, save_z), new_blob);
-                             }
+                             self.cache_written_chunk(save_x, save_z, &raw_nbt);
                          }
                      } else {
                          log::debug!("Storage disabled, skipping save for chunk ({}, {}).", save_x, save_z);
@@ -298,91 +497,297 @@ impl VirtualFile {
                  }
              }
         }
+        Ok(())
     }
 
-    fn trigger_prefetch(&self, center_x: i32, center_z: i32) {
-        let radius = self.prefetch_radius as i32;
-        
-        for dx in -radius..=radius {
-            for dz in -radius..=radius {
-                if dx == 0 && dz == 0 { continue; } // Skip center
-                
-                let tx = center_x + dx;
-                let tz = center_z + dz;
-
-                // Clone state for each neighbor task
-                let limiter = self.prefetch_limiter.clone();
-                let generator = self.generator.clone();
-                let storage = self.storage.clone();
-                let cache = self.cache.clone(); 
-                let rt_handle = self.rt.clone();
-                let benchmark = self.benchmark.clone();
-                
-                // Spawn a task per neighbor - they will compete for the semaphore
-                self.rt.spawn(async move {
-                    // 1. Check Cache (Fast check)
-                    {
-                        if cache.lock().unwrap().contains(&(tx, tz)) {
-                            return;
-                        }
-                    }
-                    
-                    // 2. Acquire Permit (throttling)
-                    let _permit = match limiter.acquire().await {
-                        Ok(p) => p,
-                        Err(_) => return,
-                    };
-                    
-                    // Double check cache after acquiring permit (in case another thread finished it)
-                    {
-                        if cache.lock().unwrap().contains(&(tx, tz)) {
-                            return;
-                        }
-                    }
+    fn cache_written_chunk(&self, x: i32, z: i32, raw_nbt: &[u8]) {
+        let Some(new_blob) = region::compress_and_wrap_chunk(raw_nbt) else {
+            // The previous version must not outlive a restart
+            if let Some(disk) = &self.disk_cache {
+                disk.remove(x, z);
+            }
+            return;
+        };
+        if let Some(disk) = &self.disk_cache {
+            disk.put(x, z, &new_blob);
+        }
+        self.cache.lock().unwrap().put((x, z), new_blob);
+    }
 
-                    // 3. Check DB
-                    if let Some(storage) = &storage {
-                        if let Ok(Some(_)) = storage.load_chunk(tx, tz).await {
-                             return; 
-                        }
+    /// Disk tier lookup. The tier mirrors what this mount last served or wrote, so a hit is
+    /// served as is; changes made behind the mount's back (history restore, rollback) drop
+    /// their entries through `DiskCache::invalidate`.
+    fn disk_get(&self, x: i32, z: i32) -> Option<Vec<u8>> {
+        let disk = self.disk_cache.as_ref()?;
+        let blob = disk.get(x, z);
+        if let Some(bench) = &self.benchmark {
+            if blob.is_some() { bench.record_disk_cache_hit(); } else { bench.record_disk_cache_miss(); }
+        }
+        blob
+    }
+
+    /// Tell an in-flight bulk load of this chunk's region not to cache what it read for it.
+    /// Called before the write reaches storage or the cache.
+    fn mark_written(&self, x: i32, z: i32) {
+        if let Some(load) = self.region_loads.lock().unwrap().get(&(x >> 5, z >> 5)) && !*load.done.borrow() {
+            load.written.lock().unwrap().insert((x, z));
+        }
+    }
+
+    /// Bulk load every stored chunk of a region into the cache in the background.
+    fn trigger_region_load(&self, region_x: i32, region_z: i32) {
+        let Some(storage) = self.storage.clone() else { return };
+
+        let (done_tx, written) = {
+            let mut loads = self.region_loads.lock().unwrap();
+            if loads.get(&(region_x, region_z)).is_some_and(|l| l.started.elapsed() < REGION_RELOAD_INTERVAL) {
+                return;
+            }
+            // Finished loads past the reload interval no longer hold anything back
+            loads.retain(|_, l| !*l.done.borrow() || l.started.elapsed() < REGION_RELOAD_INTERVAL);
+            let (tx, rx) = tokio::sync::watch::channel(false);
+            let written = Arc::new(Mutex::new(HashSet::new()));
+            loads.insert((region_x, region_z), RegionLoad { started: Instant::now(), done: rx, written: written.clone() });
+            (tx, written)
+        };
+
+        let cache = self.cache.clone();
+        let write_back = self.write_back.clone();
+        let benchmark = self.benchmark.clone();
+
+        self.rt.spawn(async move {
+            let start = Instant::now();
+            match storage.load_region(region_x, region_z).await {
+                Ok(chunks) => {
+                    if let Some(bench) = &benchmark {
+                        bench.record_region_load(start.elapsed(), chunks.len());
                     }
-                    
-                    // 4. Generate & Save
-                    let gen_ref = generator.clone();
-                    let rt = rt_handle.clone();
-                    let bench = benchmark.clone();
-                    
-                    let start_bg_gen = std::time::Instant::now();
+                    log::debug!("Region r.{}.{}: bulk loaded {} chunks in {:?}", region_x, region_z, chunks.len(), start.elapsed());
 
+                    // Compression is CPU bound, keep it off the async workers
                     let res = tokio::task::spawn_blocking(move || {
-                        gen_ref.generate_chunk(tx, tz, &rt, bench.as_deref())
+                        for (x, z, nbt) in chunks {
+                            if let Err(e) = region::verify_chunk_coords(&nbt, x, z) {
+                                log::error!("CRITICAL: DB Corruption detected for ({}, {}). Error: {:?}. Skipping bulk load.", x, z, e);
+                                continue;
+                            }
+                            let Some(blob) = region::compress_and_wrap_chunk(&nbt) else { continue };
+                            // Checked and inserted under one cache lock: writes mark the chunk
+                            // before caching it, so a write either shows up here or overwrites us
+                            let mut cache = cache.lock().unwrap();
+                            // Buffered, written or already cached chunks are newer than what is stored
+                            if write_back.as_ref().is_some_and(|wb| wb.contains(x, z))
+                                || written.lock().unwrap().contains(&(x, z))
+                                || cache.contains(&(x, z))
+                            {
+                                continue;
+                            }
+                            cache.put((x, z), blob);
+                        }
                     }).await;
-                    
-                    // Record TOTAL time/count for background chunks too
-                    if let Some(bench) = &benchmark {
-                        bench.record_generation(start_bg_gen.elapsed());
+                    if let Err(e) = res {
+                        log::warn!("Region r.{}.{} bulk load task failed: {:?}", region_x, region_z, e);
                     }
+                }
+                Err(e) => {
+                    log::warn!("Region r.{}.{} bulk load failed, falling back to per-chunk loads: {:?}", region_x, region_z, e);
+                }
+            }
+            let _ = done_tx.send(true);
+        });
+    }
 
-                    match res {
-                        Ok(Ok(nbt)) => {
-                             // Save to DB
-                             if let Some(storage) = &storage {
-                                 let _ = storage.save_chunk(tx, tz, &nbt).await;
-                             }
-                             
-                             // Update Cache
-                             if let Some(blob) = region::compress_and_wrap_chunk(&nbt) {
-                                 cache.lock().unwrap().put((tx, tz), blob);
-                             }
-                        },
-                        Ok(Err(e)) => {
-                             log::warn!("Prefetch generation failed for ({}, {}): {:?}", tx, tz, e);
-                        },
-                        Err(e) => {
-                             log::warn!("Prefetch task join failed for ({}, {}): {:?}", tx, tz, e);
-                        }
+    /// Block until an in-flight bulk load of this region finishes (bounded by REGION_LOAD_WAIT).
+    fn wait_for_region_load(&self, region_x: i32, region_z: i32) {
+        let mut done = match self.region_loads.lock().unwrap().get(&(region_x, region_z)) {
+            Some(load) if !*load.done.borrow() => load.done.clone(),
+            _ => return,
+        };
+        let waited = self.rt.block_on(async {
+            tokio::time::timeout(REGION_LOAD_WAIT, done.wait_for(|loaded| *loaded)).await
+        });
+        if waited.is_err() {
+            log::debug!("Region r.{}.{} bulk load still running, loading chunk individually", region_x, region_z);
+        }
+    }
+
+    fn trigger_prefetch(&self, center_x: i32, center_z: i32) {
+        let (targets, directional) = self.prefetch_planner.lock().unwrap().plan(center_x, center_z);
+        if directional && let Some(bench) = &self.benchmark {
+            bench.record_prefetch_directional();
+        }
+
+        let targets: Vec<(i32, i32)> = {
+            let cache = self.cache.lock().unwrap();
+            targets.iter().map(|t| (t.x, t.z)).filter(|key| !cache.contains(key)).collect()
+        };
+        self.prefetch_queue.push_plan((center_x, center_z), targets);
+
+        self.prefetch_workers_started.call_once(|| {
+            let concurrency = self.prefetch_concurrency.max(1);
+            log::info!("Starting {} prefetch workers", concurrency);
+            for _ in 0..concurrency {
+                let worker = PrefetchWorker {
+                    queue: self.prefetch_queue.clone(),
+                    generator: self.generator.clone(),
+                    storage: self.storage.clone(),
+                    cache: self.cache.clone(),
+                    disk_cache: self.disk_cache.clone(),
+                    write_back: self.write_back.clone(),
+                    rt: self.rt.clone(),
+                    benchmark: self.benchmark.clone(),
+                };
+                self.rt.spawn(worker.run());
+            }
+        });
+    }
+}
+
+impl Drop for VirtualFile {
+    fn drop(&mut self) {
+        self.prefetch_queue.close();
+    }
+}
+
+/// Background worker draining the prefetch queue.
+struct PrefetchWorker {
+    queue: Arc<PrefetchQueue>,
+    generator: Arc<dyn WorldGenerator>,
+    storage: Option<Arc<dyn ChunkStorage>>,
+    cache: Arc<Mutex<ChunkCache>>,
+    disk_cache: Option<Arc<DiskCache>>,
+    write_back: Option<Arc<WriteBackBuffer>>,
+    rt: tokio::runtime::Handle,
+    benchmark: Option<Arc<BenchmarkMetrics>>,
+}
+
+impl PrefetchWorker {
+    async fn run(self) {
+        while let Some(targets) = self.queue.next_batch(PREFETCH_BATCH).await {
+            self.prefetch_batch(targets).await;
+        }
+    }
+
+    async fn prefetch_batch(&self, targets: Vec<(i32, i32)>) {
+        // 1. Check Cache (a foreground read may have loaded it while queued) and buffered
+        //    writes not yet flushed
+        let targets: Vec<(i32, i32)> = {
+            let cache = self.cache.lock().unwrap();
+            targets.into_iter()
+                .filter(|(x, z)| !cache.contains(&(*x, *z)) && !self.write_back.as_ref().is_some_and(|wb| wb.contains(*x, *z)))
+                .collect()
+        };
+        if targets.is_empty() {
+            return;
+        }
+
+        // 2. Check disk tier (promote to memory, no storage round-trip needed)
+        let targets: Vec<(i32, i32)> = match &self.disk_cache {
+            Some(disk) => targets.into_iter()
+                .filter(|&(x, z)| match disk.get(x, z) {
+                    Some(blob) => {
+                        self.cache.lock().unwrap().put_prefetched((x, z), blob);
+                        false
                     }
-                });
+                    None => true,
+                })
+                .collect(),
+            None => targets,
+        };
+        if targets.is_empty() {
+            return;
+        }
+
+        // 3. Load whatever storage already has in one round-trip
+        let mut missing = targets.clone();
+        if let Some(storage) = &self.storage {
+            let stored = match storage.load_chunks(&targets).await {
+                Ok(stored) => stored,
+                // Can't tell what exists: generating and saving could overwrite it
+                Err(e) => {
+                    log::debug!("Prefetch load of {} chunks failed: {:?}", targets.len(), e);
+                    return;
+                }
+            };
+            missing.retain(|(x, z)| !stored.iter().any(|(sx, sz, _)| (sx, sz) == (x, z)));
+            self.cache_stored(stored).await;
+        }
+
+        for (tx, tz) in missing {
+            self.prefetch_chunk(tx, tz).await;
+        }
+    }
+
+    /// Compress stored chunks into the cache, unless something newer got there first.
+    async fn cache_stored(&self, stored: Vec<(i32, i32, Vec<u8>)>) {
+        if stored.is_empty() {
+            return;
+        }
+        let cache = self.cache.clone();
+        let disk_cache = self.disk_cache.clone();
+        let write_back = self.write_back.clone();
+        // Compression is CPU bound, keep it off the async workers
+        let res = tokio::task::spawn_blocking(move || {
+            for (x, z, nbt) in stored {
+                if let Err(e) = region::verify_chunk_coords(&nbt, x, z) {
+                    log::error!("CRITICAL: DB Corruption detected for ({}, {}). Error: {:?}. Skipping prefetch.", x, z, e);
+                    continue;
+                }
+                let Some(blob) = region::compress_and_wrap_chunk(&nbt) else { continue };
+                let mut cache = cache.lock().unwrap();
+                if cache.contains(&(x, z)) || write_back.as_ref().is_some_and(|wb| wb.contains(x, z)) {
+                    continue;
+                }
+                // Under the cache lock, so a concurrent write's blob can't be overwritten
+                if let Some(disk) = &disk_cache {
+                    disk.put(x, z, &blob);
+                }
+                cache.put_prefetched((x, z), blob);
+            }
+        }).await;
+        if let Err(e) = res {
+            log::warn!("Prefetch cache task failed: {:?}", e);
+        }
+    }
+
+    /// Generate and save a chunk storage does not have.
+    async fn prefetch_chunk(&self, tx: i32, tz: i32) {
+        // 4. Generate & Save
+        let gen_ref = self.generator.clone();
+        let rt = self.rt.clone();
+        let bench = self.benchmark.clone();
+        
+        let start_bg_gen = std::time::Instant::now();
+
+        let res = tokio::task::spawn_blocking(move || {
+            gen_ref.generate_chunk(tx, tz, &rt, bench.as_deref())
+        }).await;
+        
+        // Record TOTAL time/count for background chunks too
+        if let Some(bench) = &self.benchmark {
+            bench.record_generation(start_bg_gen.elapsed());
+        }
+
+        match res {
+            Ok(Ok(nbt)) => {
+                 // Save to DB, unless the backend regenerates untouched chunks itself
+                 if let Some(storage) = &self.storage && storage.needs_generated_chunks() {
+                     let _ = storage.save_chunk(tx, tz, &nbt).await;
+                 }
+                 
+                 // Update Cache (both tiers)
+                 if let Some(blob) = region::compress_and_wrap_chunk(&nbt) {
+                     if let Some(disk) = &self.disk_cache {
+                         disk.put(tx, tz, &blob);
+                     }
+                     self.cache.lock().unwrap().put_prefetched((tx, tz), blob);
+                 }
+            },
+            Ok(Err(e)) => {
+                 log::warn!("Prefetch generation failed for ({}, {}): {:?}", tx, tz, e);
+            },
+            Err(e) => {
+                 log::warn!("Prefetch task join failed for ({}, {}): {:?}", tx, tz, e);
             }
         }
     }
@@ -392,57 +797,283 @@ impl VirtualFile {
 mod tests {
     use super::*;
     use anyhow::Result;
-    use async_trait::async_trait;
+    use hoppermc_storage::memory::MemoryStorage;
 
     struct MockGenerator;
     impl WorldGenerator for MockGenerator {
-        fn generate_chunk(&self, _x: i32, _z: i32, _rt: &tokio::runtime::Handle, _bench: Option<&BenchmarkMetrics>) -> Result<Vec<u8>> {
-            // Return dummy NBT data
-            Ok(vec![1, 2, 3, 4])
+        fn generate_chunk(&self, x: i32, z: i32, _rt: &tokio::runtime::Handle, _bench: Option<&BenchmarkMetrics>) -> Result<Vec<u8>> {
+            // Minimal chunk NBT: just enough for the coordinate check
+            let mut root = std::collections::HashMap::new();
+            root.insert("xPos".to_string(), fastnbt::Value::Int(x));
+            root.insert("zPos".to_string(), fastnbt::Value::Int(z));
+            Ok(fastnbt::to_bytes(&fastnbt::Value::Compound(root))?)
         }
-    }
 
-    struct MockStorage;
-    #[async_trait]
-    impl ChunkStorage for MockStorage {
-        async fn save_chunk(&self, _x: i32, _z: i32, _data: &[u8]) -> Result<()> {
-            Ok(())
-        }
-        async fn load_chunk(&self, _x: i32, _z: i32) -> Result<Option<Vec<u8>>> {
-            Ok(None)
+        fn fingerprint(&self) -> String {
+            "mock".to_string()
         }
     }
 
     #[test]
     fn test_virtual_file_read_header() {
         let generator = Arc::new(MockGenerator);
-        let storage = Arc::new(MockStorage);
+        let storage = Arc::new(MemoryStorage::default());
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let vf = VirtualFile::new(generator, storage, rt.handle().clone(), None, 500, 0);
+        let vf = VirtualFile::new(generator, Some(storage), rt.handle().clone(), None, 64 * 1024 * 1024, 0);
 
         // Read first 10 bytes of header. Region 0,0
-        let data = vf.read_at(0, 10, 0, 0);
+        let data = vf.read_at(0, 10, 0, 0).unwrap();
         assert_eq!(data.len(), 10);
     }
 
     #[test]
     fn test_virtual_file_read_chunk_offset() {
         let generator = Arc::new(MockGenerator);
-        let storage = Arc::new(MockStorage);
+        let storage = Arc::new(MemoryStorage::default());
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let vf = VirtualFile::new(generator, storage, rt.handle().clone(), None, 500, 0);
+        let vf = VirtualFile::new(generator, Some(storage), rt.handle().clone(), None, 64 * 1024 * 1024, 0);
 
         // Calculate offset for chunk 0,0
         // Header is 8192 bytes
         let chunk_offset = region::get_chunk_file_offset(0, 0); 
         
         // Read 5 bytes from there. Region 0,0
-        let data = vf.read_at(chunk_offset, 5, 0, 0);
+        let data = vf.read_at(chunk_offset, 5, 0, 0).unwrap();
         assert_eq!(data.len(), 5);
         
-        // The first 4 bytes are length (big endian). 
-        // Our mock returns 4 bytes [1,2,3,4]. Compressed it will be larger.
-        // But we can check it's not all zeros.
+        // The first 4 bytes are length (big endian), so it's not all zeros.
         assert_ne!(data, vec![0, 0, 0, 0, 0]);
     }
+
+    #[test]
+    fn test_virtual_file_serves_stored_chunk() {
+        let generator = Arc::new(MockGenerator);
+        let storage = Arc::new(MemoryStorage::default());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let mut root = std::collections::HashMap::new();
+        root.insert("xPos".to_string(), fastnbt::Value::Int(33));
+        root.insert("zPos".to_string(), fastnbt::Value::Int(-2));
+        root.insert("Built".to_string(), fastnbt::Value::Byte(1));
+        let stored = fastnbt::to_bytes(&fastnbt::Value::Compound(root)).unwrap();
+        rt.block_on(storage.save_chunk(33, -2, &stored)).unwrap();
+
+        let vf = VirtualFile::new(generator, Some(storage), rt.handle().clone(), None, 64 * 1024 * 1024, 0);
+
+        // Chunk (33, -2) lives in region (1, -1) at local (1, 30)
+        let chunk_offset = region::get_chunk_file_offset(1, 30);
+        let data = vf.read_at(chunk_offset, 4096, 1, -1).unwrap();
+        assert_eq!(region::unwrap_and_decompress_chunk(&data).unwrap(), stored);
+    }
+
+    /// Storage whose region loads wait until the test releases them, counting batched loads.
+    struct GatedStorage {
+        inner: MemoryStorage,
+        gate: tokio::sync::Semaphore,
+        batch_loads: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl ChunkStorage for GatedStorage {
+        async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+            self.inner.save_chunk(x, z, data).await
+        }
+
+        async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+            self.inner.load_chunk(x, z).await
+        }
+
+        async fn load_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+            let _permit = self.gate.acquire().await?;
+            self.inner.load_region(region_x, region_z).await
+        }
+
+        async fn load_chunks(&self, coords: &[(i32, i32)]) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+            self.batch_loads.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.inner.load_chunks(coords).await
+        }
+    }
+
+    /// Storage that is down: every load fails.
+    struct DownStorage;
+
+    #[async_trait::async_trait]
+    impl ChunkStorage for DownStorage {
+        async fn save_chunk(&self, _x: i32, _z: i32, _data: &[u8]) -> Result<()> {
+            anyhow::bail!("storage down")
+        }
+
+        async fn load_chunk(&self, _x: i32, _z: i32) -> Result<Option<Vec<u8>>> {
+            anyhow::bail!("storage down")
+        }
+    }
+
+    #[test]
+    fn test_degraded_reads() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let open = |policy| {
+            VirtualFile::new(Arc::new(MockGenerator), Some(Arc::new(DownStorage)), rt.handle().clone(), None, 64 * 1024 * 1024, 0)
+                .with_degraded_policy(policy)
+        };
+        let chunk_offset = region::get_chunk_file_offset(0, 0);
+
+        // Retrying is capped well below the configured timeout, and nothing was served to protect
+        let vf = open(DegradedPolicy::Retry { timeout: Duration::from_secs(10) });
+        let start = Instant::now();
+        assert_eq!(vf.read_at(chunk_offset, 4096, 0, 0).unwrap_err().raw_os_error(), Some(libc::EIO));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(vf.degraded_chunks.lock().unwrap().is_empty());
+
+        // A generated stand-in is write-protected and not cached
+        let vf = open(DegradedPolicy::ReadOnly);
+        vf.read_at(chunk_offset, 4096, 0, 0).unwrap();
+        assert!(vf.degraded_chunks.lock().unwrap().contains(&(0, 0)));
+        assert!(!vf.cache.lock().unwrap().contains(&(0, 0)));
+    }
+
+    #[test]
+    fn test_region_load_does_not_overwrite_newer_write() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(GatedStorage { inner: MemoryStorage::default(), gate: tokio::sync::Semaphore::new(0), batch_loads: Default::default() });
+        let old = MockGenerator.generate_chunk(4, 5, rt.handle(), None).unwrap();
+        rt.block_on(storage.save_chunk(4, 5, &old)).unwrap();
+        let vf = VirtualFile::new(Arc::new(MockGenerator), Some(storage.clone()), rt.handle().clone(), None, 64 * 1024 * 1024, 0);
+
+        // The load reads the old version, then a write lands and is evicted before the load caches
+        vf.trigger_region_load(0, 0);
+        let mut root = std::collections::HashMap::new();
+        root.insert("xPos".to_string(), fastnbt::Value::Int(4));
+        root.insert("zPos".to_string(), fastnbt::Value::Int(5));
+        root.insert("Written".to_string(), fastnbt::Value::Byte(1));
+        let new = fastnbt::to_bytes(&fastnbt::Value::Compound(root)).unwrap();
+        vf.mark_written(4, 5);
+        vf.cache_written_chunk(4, 5, &new);
+        vf.cache.lock().unwrap().remove(&(4, 5));
+        storage.gate.add_permits(1);
+        vf.wait_for_region_load(0, 0);
+
+        assert!(!vf.cache.lock().unwrap().contains(&(4, 5)));
+    }
+
+    #[test]
+    fn test_prefetch_batches_storage_loads() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(GatedStorage { inner: MemoryStorage::default(), gate: tokio::sync::Semaphore::new(0), batch_loads: Default::default() });
+        let mut root = std::collections::HashMap::new();
+        root.insert("xPos".to_string(), fastnbt::Value::Int(1));
+        root.insert("zPos".to_string(), fastnbt::Value::Int(0));
+        root.insert("Built".to_string(), fastnbt::Value::Byte(1));
+        let stored = fastnbt::to_bytes(&fastnbt::Value::Compound(root)).unwrap();
+        rt.block_on(storage.save_chunk(1, 0, &stored)).unwrap();
+
+        let vf = VirtualFile::new(Arc::new(MockGenerator), Some(storage.clone()), rt.handle().clone(), None, 64 * 1024 * 1024, 0);
+        let worker = PrefetchWorker {
+            queue: vf.prefetch_queue.clone(),
+            generator: vf.generator.clone(),
+            storage: vf.storage.clone(),
+            cache: vf.cache.clone(),
+            disk_cache: None,
+            write_back: None,
+            rt: rt.handle().clone(),
+            benchmark: None,
+        };
+        rt.block_on(worker.prefetch_batch(vec![(1, 0), (2, 0)]));
+
+        // One batched load: the stored chunk is cached as is, the other one generated and saved
+        assert_eq!(storage.batch_loads.load(std::sync::atomic::Ordering::Relaxed), 1);
+        let blob = vf.cache.lock().unwrap().get(&(1, 0)).cloned().unwrap();
+        assert_eq!(region::unwrap_and_decompress_chunk(&blob).unwrap(), stored);
+        assert!(vf.cache.lock().unwrap().contains(&(2, 0)));
+        assert!(rt.block_on(storage.exists(2, 0)).unwrap());
+    }
+
+    /// Storage that keeps chunks as deltas against the generator, like `pg_weightless`.
+    struct DeltaStorage(MemoryStorage);
+
+    #[async_trait::async_trait]
+    impl ChunkStorage for DeltaStorage {
+        async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+            self.0.save_chunk(x, z, data).await
+        }
+
+        async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+            self.0.load_chunk(x, z).await
+        }
+
+        fn needs_generated_chunks(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_prefetch_skips_saving_generated_chunks_for_delta_storage() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(DeltaStorage(MemoryStorage::default()));
+        let vf = VirtualFile::new(Arc::new(MockGenerator), Some(storage.clone()), rt.handle().clone(), None, 64 * 1024 * 1024, 0);
+        let worker = PrefetchWorker {
+            queue: vf.prefetch_queue.clone(),
+            generator: vf.generator.clone(),
+            storage: vf.storage.clone(),
+            cache: vf.cache.clone(),
+            disk_cache: None,
+            write_back: None,
+            rt: rt.handle().clone(),
+            benchmark: None,
+        };
+        rt.block_on(worker.prefetch_batch(vec![(2, 0)]));
+
+        assert!(vf.cache.lock().unwrap().contains(&(2, 0)));
+        assert!(!rt.block_on(storage.exists(2, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_finished_region_loads_are_pruned() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let vf = VirtualFile::new(Arc::new(MockGenerator), Some(Arc::new(MemoryStorage::default())), rt.handle().clone(), None, 64 * 1024 * 1024, 0);
+        vf.trigger_region_load(0, 0);
+        vf.wait_for_region_load(0, 0);
+        vf.region_loads.lock().unwrap().get_mut(&(0, 0)).unwrap().started -= REGION_RELOAD_INTERVAL;
+
+        vf.trigger_region_load(1, 0);
+        let loads = vf.region_loads.lock().unwrap();
+        assert!(!loads.contains_key(&(0, 0)) && loads.contains_key(&(1, 0)));
+    }
+
+    #[test]
+    fn test_disk_tier_serves_stored_chunks_until_invalidated() {
+        let dir = std::env::temp_dir().join(format!("hoppermc-vf-disk-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let storage = Arc::new(MemoryStorage::default());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let chunk_offset = region::get_chunk_file_offset(2, 3);
+        let open = |storage: &Arc<MemoryStorage>| {
+            let disk = DiskCache::open(&dir, "memory", "mock", 1024 * 1024, None).unwrap();
+            VirtualFile::new(Arc::new(MockGenerator), Some(storage.clone()), rt.handle().clone(), None, 64 * 1024 * 1024, 0)
+                .with_disk_cache(disk)
+        };
+        let chunk = |version: i8| {
+            let mut root = std::collections::HashMap::new();
+            root.insert("xPos".to_string(), fastnbt::Value::Int(2));
+            root.insert("zPos".to_string(), fastnbt::Value::Int(3));
+            root.insert("Version".to_string(), fastnbt::Value::Byte(version));
+            fastnbt::to_bytes(&fastnbt::Value::Compound(root)).unwrap()
+        };
+        let (first, second) = (chunk(1), chunk(2));
+
+        // Stored chunks are kept on disk across a restart
+        rt.block_on(storage.save_chunk(2, 3, &first)).unwrap();
+        open(&storage).read_at(chunk_offset, 4096, 0, 0).unwrap();
+        assert_eq!(DiskCache::open(&dir, "memory", "mock", 1024 * 1024, None).unwrap().len(), 1);
+
+        // A hit is served without asking storage
+        rt.block_on(storage.save_chunk(2, 3, &second)).unwrap();
+        let data = open(&storage).read_at(chunk_offset, 4096, 0, 0).unwrap();
+        assert_eq!(region::unwrap_and_decompress_chunk(&data).unwrap(), first);
+
+        // Restore and rollback invalidate what they changed: storage wins again
+        assert_eq!(DiskCache::invalidate(&dir, "memory", &[(2, 3)]).unwrap(), 1);
+        let data = open(&storage).read_at(chunk_offset, 4096, 0, 0).unwrap();
+        assert_eq!(region::unwrap_and_decompress_chunk(&data).unwrap(), second);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,479 @@
+// Write-back buffering for intercepted chunk writes.
+//
+// Instead of one blocking `save_chunk` round-trip per FUSE write, chunks are marked
+// dirty in memory and flushed to storage in batches: on a timer, when the dirty set
+// reaches half its cap, and on fsync/shutdown. Reads are served from the dirty set,
+// so a buffered chunk is never shadowed by its older stored version. The dirty set never
+// holds more than `max_dirty_chunks`: a new chunk past that waits for a flush, and fails
+// if the flush cannot make room. Chunks that fail to save are retried with backoff, not
+// on every write.
+
+use hoppermc_benchmark::BenchmarkMetrics;
+use hoppermc_storage::ChunkStorage;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+// Chunks per `save_chunks` call
+const FLUSH_BATCH: usize = 256;
+// Parallel saves when a failed batch is retried chunk by chunk
+const FLUSH_CONCURRENCY: usize = 8;
+// Backoff before retrying a chunk that failed to save, doubling per failure
+const RETRY_MIN_DELAY: Duration = Duration::from_millis(100);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteDurability {
+    /// Acknowledge the write once it is buffered in memory (fastest, loses the
+    /// last flush interval on crash).
+    Buffered,
+    /// Acknowledge the write once the batch containing it is committed to storage.
+    /// Concurrent writes are grouped into a single flush.
+    Commit,
+}
+
+impl std::str::FromStr for WriteDurability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "buffer" | "buffered" => Ok(Self::Buffered),
+            "commit" => Ok(Self::Commit),
+            other => anyhow::bail!("Invalid write durability '{}': use buffer or commit", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WriteBackConfig {
+    pub durability: WriteDurability,
+    pub flush_interval: Duration,
+    pub max_dirty_chunks: usize,
+}
+
+impl Default for WriteBackConfig {
+    fn default() -> Self {
+        Self {
+            durability: WriteDurability::Commit,
+            flush_interval: Duration::from_secs(1),
+            max_dirty_chunks: 256,
+        }
+    }
+}
+
+struct DirtyChunk {
+    nbt: Arc<Vec<u8>>,
+    generation: u64,
+    // Newest generation of this chunk a flush has saved; writes up to it are committed
+    committed: u64,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+// A dirty chunk taken for a flush: coordinates, NBT and write generation
+type PendingChunk = ((i32, i32), Arc<Vec<u8>>, u64);
+
+#[derive(Default)]
+struct DirtyState {
+    chunks: HashMap<(i32, i32), DirtyChunk>,
+    next_generation: u64,
+}
+
+pub struct WriteBackBuffer {
+    storage: Arc<dyn ChunkStorage>,
+    config: WriteBackConfig,
+    state: Mutex<DirtyState>,
+    // Serialises flushes; writers queued behind a running flush get batched into the next one.
+    flush_lock: tokio::sync::Mutex<()>,
+    flush_needed: Arc<tokio::sync::Notify>,
+    benchmark: Option<Arc<BenchmarkMetrics>>,
+}
+
+impl WriteBackBuffer {
+    pub fn new(storage: Arc<dyn ChunkStorage>, config: WriteBackConfig, benchmark: Option<Arc<BenchmarkMetrics>>) -> Self {
+        Self {
+            storage,
+            config,
+            state: Mutex::new(DirtyState::default()),
+            flush_lock: tokio::sync::Mutex::new(()),
+            flush_needed: Arc::new(tokio::sync::Notify::new()),
+            benchmark,
+        }
+    }
+
+    pub fn durability(&self) -> WriteDurability {
+        self.config.durability
+    }
+
+    /// Start the background flusher (timer + size threshold). Stops once the buffer is dropped.
+    /// Chunks waiting out a retry backoff are left for a later tick.
+    pub fn spawn_flusher(self: &Arc<Self>, rt: &tokio::runtime::Handle) {
+        let weak: Weak<Self> = Arc::downgrade(self);
+        let flush_needed = self.flush_needed.clone();
+        let interval = self.config.flush_interval;
+        rt.spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {},
+                    _ = flush_needed.notified() => {},
+                }
+                let Some(buffer) = weak.upgrade() else { break };
+                if let Err(e) = buffer.flush_due().await {
+                    log::error!("Write-back flush failed, chunks stay dirty: {:?}", e);
+                }
+            }
+        });
+    }
+
+    /// Buffer a chunk's raw NBT. Returns the write generation used to wait for its commit.
+    /// If the buffer is full, flushes first; fails if that does not make room.
+    pub async fn mark_dirty(&self, x: i32, z: i32, nbt: Vec<u8>) -> anyhow::Result<u64> {
+        let nbt = Arc::new(nbt);
+        if let Some(generation) = self.try_mark_dirty(x, z, &nbt) {
+            return Ok(generation);
+        }
+        if let Err(e) = self.flush_due().await {
+            log::debug!("Flush to make room for chunk ({}, {}) reported: {:?}", x, z, e);
+        }
+        self.try_mark_dirty(x, z, &nbt).ok_or_else(|| {
+            anyhow::anyhow!("Write-back buffer is full ({} chunks) and storage is not taking them", self.config.max_dirty_chunks)
+        })
+    }
+
+    fn try_mark_dirty(&self, x: i32, z: i32, nbt: &Arc<Vec<u8>>) -> Option<u64> {
+        let (generation, dirty_count) = {
+            let mut state = self.state.lock().unwrap();
+            let cap = self.config.max_dirty_chunks.max(1);
+            if state.chunks.len() >= cap && !state.chunks.contains_key(&(x, z)) {
+                return None;
+            }
+            state.next_generation += 1;
+            let generation = state.next_generation;
+            // Every earlier write of a chunk that is not dirty has been committed
+            let committed = state.chunks.get(&(x, z)).map_or(generation - 1, |c| c.committed);
+            state.chunks.insert((x, z), DirtyChunk { nbt: nbt.clone(), generation, committed, failures: 0, retry_at: None });
+            (generation, state.chunks.len())
+        };
+
+        if dirty_count * 2 >= self.config.max_dirty_chunks {
+            self.flush_needed.notify_one();
+        }
+        Some(generation)
+    }
+
+    /// Latest buffered (not yet committed) NBT for a chunk.
+    pub fn get(&self, x: i32, z: i32) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        state.chunks.get(&(x, z)).map(|c| c.nbt.as_ref().clone())
+    }
+
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        self.state.lock().unwrap().chunks.contains_key(&(x, z))
+    }
+
+    pub fn dirty_count(&self) -> usize {
+        self.state.lock().unwrap().chunks.len()
+    }
+
+    /// Whether a flush has saved this write, or a newer write of the same chunk.
+    fn is_committed(&self, x: i32, z: i32, generation: u64) -> bool {
+        let state = self.state.lock().unwrap();
+        match state.chunks.get(&(x, z)) {
+            Some(c) => c.committed >= generation,
+            None => true,
+        }
+    }
+
+    /// Flush and wait until the given write is committed (ack-after-commit).
+    pub async fn commit(&self, x: i32, z: i32, generation: u64) -> anyhow::Result<()> {
+        // Other chunks of the batch may fail; only this write decides the result
+        if let Err(e) = self.flush_due().await {
+            log::debug!("Flush for chunk ({}, {}) reported: {:?}", x, z, e);
+        }
+        if self.is_committed(x, z, generation) {
+            Ok(())
+        } else {
+            anyhow::bail!("Chunk ({}, {}) was not committed", x, z)
+        }
+    }
+
+    /// Write all dirty chunks to storage in one batch, including those waiting out a retry
+    /// backoff (fsync, shutdown). Chunks that fail stay dirty.
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        self.flush_chunks(None).await
+    }
+
+    /// Like `flush`, but skips chunks whose retry backoff has not passed yet.
+    async fn flush_due(&self) -> anyhow::Result<()> {
+        self.flush_chunks(Some(Instant::now())).await
+    }
+
+    async fn flush_chunks(&self, due_at: Option<Instant>) -> anyhow::Result<()> {
+        let _guard = self.flush_lock.lock().await;
+
+        let batch: Vec<PendingChunk> = {
+            let state = self.state.lock().unwrap();
+            state.chunks.iter()
+                .filter(|(_, c)| match (due_at, c.retry_at) {
+                    (Some(now), Some(retry_at)) => retry_at <= now,
+                    _ => true,
+                })
+                .map(|(k, c)| (*k, c.nbt.clone(), c.generation))
+                .collect()
+        };
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let start = std::time::Instant::now();
+        let mut failed = 0usize;
+
+        for group in batch.chunks(FLUSH_BATCH) {
+            let chunks: Vec<_> = group.iter().map(|((x, z), nbt, _)| (*x, *z, nbt.as_ref().clone())).collect();
+            match self.storage.save_chunks(&chunks).await {
+                Ok(()) => {
+                    for ((x, z), _, generation) in group {
+                        self.mark_saved(*x, *z, *generation);
+                    }
+                }
+                Err(e) => {
+                    // Isolate the failing chunks so the rest of the batch still commits
+                    log::warn!("Batched flush of {} chunks failed, retrying one by one: {:?}", group.len(), e);
+                    failed += self.flush_each(group).await;
+                }
+            }
+        }
+
+        if let Some(bench) = &self.benchmark {
+            bench.record_flush(start.elapsed(), batch.len() - failed);
+        }
+        log::debug!("Write-back flush: {} chunks in {:?} ({} failed)", batch.len(), start.elapsed(), failed);
+
+        if failed > 0 {
+            anyhow::bail!("{} of {} chunks failed to flush", failed, batch.len());
+        }
+        Ok(())
+    }
+
+    /// Record a successful save. The chunk stays dirty if a newer write arrived meanwhile.
+    fn mark_saved(&self, x: i32, z: i32, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        let Some(chunk) = state.chunks.get_mut(&(x, z)) else { return };
+        if chunk.generation == generation {
+            state.chunks.remove(&(x, z));
+        } else {
+            chunk.committed = chunk.committed.max(generation);
+        }
+    }
+
+    /// Back off before retrying a chunk that failed to save, unless it was rewritten meanwhile.
+    fn mark_failed(&self, x: i32, z: i32, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(chunk) = state.chunks.get_mut(&(x, z))
+            && chunk.generation == generation
+        {
+            let delay = RETRY_MIN_DELAY.saturating_mul(1 << chunk.failures.min(16)).min(RETRY_MAX_DELAY);
+            chunk.failures += 1;
+            chunk.retry_at = Some(Instant::now() + delay);
+        }
+    }
+
+    /// Save chunks one by one (a few in parallel). Returns how many failed.
+    async fn flush_each(&self, group: &[PendingChunk]) -> usize {
+        let mut failed = 0;
+        for group in group.chunks(FLUSH_CONCURRENCY) {
+            let mut tasks = tokio::task::JoinSet::new();
+            for ((x, z), nbt, generation) in group.iter().cloned() {
+                let storage = self.storage.clone();
+                let benchmark = self.benchmark.clone();
+                tasks.spawn(async move {
+                    let start_save = std::time::Instant::now();
+                    let res = storage.save_chunk(x, z, &nbt).await;
+                    if let Some(bench) = &benchmark {
+                        bench.record_save(start_save.elapsed());
+                    }
+                    (x, z, generation, res)
+                });
+            }
+
+            while let Some(joined) = tasks.join_next().await {
+                match joined {
+                    Ok((x, z, generation, Ok(()))) => self.mark_saved(x, z, generation),
+                    Ok((x, z, generation, Err(e))) => {
+                        failed += 1;
+                        self.mark_failed(x, z, generation);
+                        log::error!("Failed to flush chunk ({}, {}) to storage: {:?}", x, z, e);
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        log::error!("Flush task join failed: {:?}", e);
+                    }
+                }
+            }
+        }
+        failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use async_trait::async_trait;
+
+    #[derive(Default)]
+    struct RecordingStorage {
+        saved: Mutex<Vec<(i32, i32, Vec<u8>)>>,
+        fail: std::sync::atomic::AtomicBool,
+        // Chunk that always fails to save
+        broken: Option<(i32, i32)>,
+        batches: std::sync::atomic::AtomicUsize,
+        rejected: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ChunkStorage for RecordingStorage {
+        async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+            if self.fail.load(std::sync::atomic::Ordering::Relaxed) {
+                anyhow::bail!("storage down");
+            }
+            if self.broken == Some((x, z)) {
+                self.rejected.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                anyhow::bail!("chunk ({}, {}) rejected", x, z);
+            }
+            self.saved.lock().unwrap().push((x, z, data.to_vec()));
+            Ok(())
+        }
+        async fn load_chunk(&self, _x: i32, _z: i32) -> Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+        // All or nothing, like a transaction
+        async fn save_chunks(&self, chunks: &[(i32, i32, Vec<u8>)]) -> Result<()> {
+            self.batches.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            if self.fail.load(std::sync::atomic::Ordering::Relaxed) || chunks.iter().any(|(x, z, _)| self.broken == Some((*x, *z))) {
+                anyhow::bail!("batch rejected");
+            }
+            self.saved.lock().unwrap().extend_from_slice(chunks);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_durability_from_str() {
+        assert_eq!("Commit".parse::<WriteDurability>().unwrap(), WriteDurability::Commit);
+        assert_eq!("buffered".parse::<WriteDurability>().unwrap(), WriteDurability::Buffered);
+        assert!("comit".parse::<WriteDurability>().is_err());
+    }
+
+    #[test]
+    fn test_dirty_served_until_flushed() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(RecordingStorage::default());
+        let wb = WriteBackBuffer::new(storage.clone(), WriteBackConfig::default(), None);
+
+        rt.block_on(wb.mark_dirty(1, 2, vec![1])).unwrap();
+        rt.block_on(wb.mark_dirty(1, 2, vec![2])).unwrap(); // overwrite before flush
+        rt.block_on(wb.mark_dirty(3, 4, vec![3])).unwrap();
+        assert_eq!(wb.get(1, 2), Some(vec![2]));
+        assert!(storage.saved.lock().unwrap().is_empty());
+
+        rt.block_on(wb.flush()).unwrap();
+        assert_eq!(wb.dirty_count(), 0);
+        assert_eq!(storage.batches.load(std::sync::atomic::Ordering::Relaxed), 1);
+        let saved = storage.saved.lock().unwrap();
+        assert_eq!(saved.len(), 2);
+        assert!(saved.contains(&(1, 2, vec![2])));
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_chunks_dirty() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(RecordingStorage::default());
+        storage.fail.store(true, std::sync::atomic::Ordering::Relaxed);
+        let wb = WriteBackBuffer::new(storage.clone(), WriteBackConfig::default(), None);
+
+        let generation = rt.block_on(wb.mark_dirty(0, 0, vec![7])).unwrap();
+        assert!(rt.block_on(wb.commit(0, 0, generation)).is_err());
+        assert_eq!(wb.get(0, 0), Some(vec![7]));
+
+        // Retried once the backoff has passed
+        storage.fail.store(false, std::sync::atomic::Ordering::Relaxed);
+        assert!(rt.block_on(wb.commit(0, 0, generation)).is_err());
+        std::thread::sleep(RETRY_MIN_DELAY);
+        rt.block_on(wb.commit(0, 0, generation)).unwrap();
+        assert!(!wb.contains(0, 0));
+    }
+
+    #[test]
+    fn test_commit_ignores_other_failing_chunks() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(RecordingStorage { broken: Some((0, 0)), ..Default::default() });
+        let wb = WriteBackBuffer::new(storage.clone(), WriteBackConfig::default(), None);
+
+        let failing = rt.block_on(wb.mark_dirty(0, 0, vec![1])).unwrap();
+        let generation = rt.block_on(wb.mark_dirty(5, 5, vec![2])).unwrap();
+        rt.block_on(wb.commit(5, 5, generation)).unwrap();
+        assert!(rt.block_on(wb.commit(0, 0, failing)).is_err());
+        assert_eq!(wb.get(0, 0), Some(vec![1]));
+        assert_eq!(*storage.saved.lock().unwrap(), vec![(5, 5, vec![2])]);
+    }
+
+    #[test]
+    fn test_failing_chunk_is_not_retried_on_every_write() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(RecordingStorage { broken: Some((0, 0)), ..Default::default() });
+        let wb = WriteBackBuffer::new(storage.clone(), WriteBackConfig::default(), None);
+
+        let failing = rt.block_on(wb.mark_dirty(0, 0, vec![1])).unwrap();
+        assert!(rt.block_on(wb.commit(0, 0, failing)).is_err());
+        for i in 1..10 {
+            let generation = rt.block_on(wb.mark_dirty(i, 0, vec![2])).unwrap();
+            rt.block_on(wb.commit(i, 0, generation)).unwrap();
+        }
+        assert_eq!(storage.rejected.load(std::sync::atomic::Ordering::Relaxed), 1);
+        // fsync still tries it
+        assert!(rt.block_on(wb.flush()).is_err());
+        assert_eq!(storage.rejected.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_superseded_write_is_not_committed_until_saved() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(RecordingStorage::default());
+        storage.fail.store(true, std::sync::atomic::Ordering::Relaxed);
+        let wb = WriteBackBuffer::new(storage.clone(), WriteBackConfig::default(), None);
+
+        let first = rt.block_on(wb.mark_dirty(0, 0, vec![1])).unwrap();
+        let second = rt.block_on(wb.mark_dirty(0, 0, vec![2])).unwrap();
+        assert!(rt.block_on(wb.commit(0, 0, first)).is_err());
+
+        storage.fail.store(false, std::sync::atomic::Ordering::Relaxed);
+        rt.block_on(wb.flush()).unwrap();
+        assert!(wb.is_committed(0, 0, first) && wb.is_committed(0, 0, second));
+        // A new write after the chunk left the buffer does not un-commit the old ones
+        let third = rt.block_on(wb.mark_dirty(0, 0, vec![3])).unwrap();
+        assert!(wb.is_committed(0, 0, second) && !wb.is_committed(0, 0, third));
+    }
+
+    #[test]
+    fn test_dirty_cap_is_enforced() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = Arc::new(RecordingStorage::default());
+        storage.fail.store(true, std::sync::atomic::Ordering::Relaxed);
+        let config = WriteBackConfig { max_dirty_chunks: 2, ..WriteBackConfig::default() };
+        let wb = WriteBackBuffer::new(storage.clone(), config, None);
+
+        rt.block_on(wb.mark_dirty(0, 0, vec![1])).unwrap();
+        rt.block_on(wb.mark_dirty(1, 0, vec![1])).unwrap();
+        // Rewriting a buffered chunk does not grow the buffer; a new one needs room
+        rt.block_on(wb.mark_dirty(1, 0, vec![2])).unwrap();
+        assert!(rt.block_on(wb.mark_dirty(2, 0, vec![1])).is_err());
+        assert_eq!(wb.dirty_count(), 2);
+
+        // Once storage takes writes again, the flush makes room
+        storage.fail.store(false, std::sync::atomic::Ordering::Relaxed);
+        std::thread::sleep(RETRY_MIN_DELAY);
+        rt.block_on(wb.mark_dirty(2, 0, vec![1])).unwrap();
+        assert_eq!(wb.dirty_count(), 1);
+    }
+}
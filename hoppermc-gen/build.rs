@@ -0,0 +1,42 @@
+// Exposes the Pumpkin revision the generator is built against as `PUMPKIN_REV`, so the
+// generator fingerprint follows the workspace pin instead of a copy of it.
+
+use std::path::Path;
+
+fn main() {
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let lock = workspace.join("Cargo.lock");
+    let manifest = workspace.join("Cargo.toml");
+    println!("cargo:rerun-if-changed={}", lock.display());
+    println!("cargo:rerun-if-changed={}", manifest.display());
+
+    let rev = locked_rev(&lock).or_else(|| pinned_rev(&manifest)).unwrap_or_else(|| {
+        println!("cargo:warning=Could not find the pumpkin-world revision; generator fingerprints use 'unknown'");
+        "unknown".to_string()
+    });
+    println!("cargo:rustc-env=PUMPKIN_REV={}", &rev[..rev.len().min(7)]);
+}
+
+/// Resolved commit of pumpkin-world: `source = "git+<url>?rev=<pin>#<commit>"`.
+fn locked_rev(path: &Path) -> Option<String> {
+    let lock = std::fs::read_to_string(path).ok()?;
+    let mut in_package = false;
+    for line in lock.lines() {
+        if line.starts_with("name = ") {
+            in_package = line == "name = \"pumpkin-world\"";
+        } else if in_package && let Some(source) = line.strip_prefix("source = ") {
+            let (_, commit) = source.trim_matches('"').rsplit_once('#')?;
+            return Some(commit.to_string());
+        }
+    }
+    None
+}
+
+/// `rev = "..."` of the pumpkin-world dependency in the workspace manifest.
+fn pinned_rev(path: &Path) -> Option<String> {
+    let manifest = std::fs::read_to_string(path).ok()?;
+    let line = manifest.lines().find(|line| line.trim_start().starts_with("pumpkin-world"))?;
+    let (_, rest) = line.split_once("rev = \"")?;
+    let (rev, _) = rest.split_once('"')?;
+    Some(rev.to_string())
+}
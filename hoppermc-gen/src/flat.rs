@@ -33,4 +33,9 @@ impl WorldGenerator for FlatGenerator {
 
         builder.build(x, z, rt)
     }
+
+    fn fingerprint(&self) -> String {
+        // Bump when the layer layout above changes
+        "flat-v1".to_string()
+    }
 }
\ No newline at end of file
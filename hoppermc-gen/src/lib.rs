@@ -6,6 +6,16 @@ use hoppermc_benchmark::BenchmarkMetrics;
 
 pub trait WorldGenerator: Send + Sync {
     fn generate_chunk(&self, x: i32, z: i32, rt: &Handle, benchmark: Option<&BenchmarkMetrics>) -> Result<Vec<u8>>;
+
+    /// Stable identifier of the generator and its settings (seed, dimension, engine version).
+    /// Anything derived from generated output (caches, deltas) must be keyed by it,
+    /// so a changed generator never reuses data produced by the old one.
+    ///
+    /// Defaults to the implementing type's name, which is only enough for generators
+    /// without settings; anything seeded or configurable must override it.
+    fn fingerprint(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
 }
 
 pub mod flat;
@@ -14,6 +14,9 @@ use pumpkin_data::noise_router::{OVERWORLD_BASE_NOISE_ROUTER, NETHER_BASE_NOISE_
 use anyhow::Result;
 use std::collections::HashMap;
 
+// Pumpkin revision the build resolved (see build.rs). Terrain output changes with it.
+const PUMPKIN_REV: &str = env!("PUMPKIN_REV");
+
 /// Vanilla-style world generator using Pumpkin's VanillaGenerator
 /// Generates realistic Minecraft terrain with biomes, caves, ores, etc.
 pub struct VanillaWorldGenerator {
@@ -28,6 +31,18 @@ impl VanillaWorldGenerator {
         Self::with_dimension(seed, Dimension::Overworld)
     }
     
+    /// Generator for a dimension by name: "overworld", "the_nether" or "the_end"
+    /// (a `minecraft:` prefix is accepted). None for any other name.
+    pub fn for_dimension(seed: u64, name: &str) -> Option<Self> {
+        let dimension = match name.strip_prefix("minecraft:").unwrap_or(name) {
+            "overworld" => Dimension::Overworld,
+            "the_nether" | "nether" => Dimension::Nether,
+            "the_end" | "end" => Dimension::End,
+            _ => return None,
+        };
+        Some(Self::with_dimension(seed, dimension))
+    }
+
     pub fn with_dimension(seed: u64, dimension: Dimension) -> Self {
         // Initialize noise configuration (cached, reused for all chunks)
         let random_config = GlobalRandomConfig::new(seed, false);
@@ -125,6 +140,15 @@ impl WorldGenerator for VanillaWorldGenerator {
 
         Ok(bytes.to_vec())
     }
+
+    fn fingerprint(&self) -> String {
+        let dimension = match self.dimension {
+            Dimension::Overworld => "overworld",
+            Dimension::Nether => "nether",
+            Dimension::End => "end",
+        };
+        format!("vanilla-{}-{}-pumpkin.{}", dimension, self.random_config.seed, PUMPKIN_REV)
+    }
 }
 
 impl VanillaWorldGenerator {
@@ -0,0 +1,15 @@
+-- sections[i] / section_y[i] describe the i-th entry of the chunk's sections list
+CREATE TABLE IF NOT EXISTS chunk_sections (
+    hash BYTEA PRIMARY KEY,
+    data BYTEA,
+    refcount BIGINT NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS chunks_dedup (
+    x INT,
+    z INT,
+    body BYTEA NOT NULL,
+    sections BYTEA[],
+    section_y SMALLINT[],
+    updated_at TIMESTAMP DEFAULT NOW(),
+    PRIMARY KEY (x, z)
+);
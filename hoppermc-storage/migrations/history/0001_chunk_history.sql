@@ -0,0 +1,9 @@
+-- Version history of saved chunks (HISTORY=true), shared by every mode
+CREATE TABLE IF NOT EXISTS chunk_history (
+    id BIGSERIAL PRIMARY KEY,
+    x INT NOT NULL,
+    z INT NOT NULL,
+    saved_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    data BYTEA NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_chunk_history_pos ON chunk_history (x, z, saved_at);
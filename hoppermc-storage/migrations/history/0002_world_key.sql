@@ -0,0 +1,9 @@
+-- Versions belong to a world and dimension; existing ones to world 1's overworld
+ALTER TABLE chunk_history
+    ADD COLUMN world_id INT NOT NULL DEFAULT 1 REFERENCES worlds (id),
+    ADD COLUMN dimension TEXT NOT NULL DEFAULT 'overworld';
+ALTER TABLE chunk_history
+    ALTER COLUMN world_id DROP DEFAULT,
+    ALTER COLUMN dimension DROP DEFAULT;
+DROP INDEX IF EXISTS idx_chunk_history_pos;
+CREATE INDEX idx_chunk_history_pos ON chunk_history (world_id, dimension, x, z, saved_at);
@@ -0,0 +1,22 @@
+-- slim + arrays rebuild the chunk; the other columns are a queryable projection
+CREATE TABLE IF NOT EXISTS chunks_hybrid (
+    x INT,
+    z INT,
+    data_version INT,
+    status TEXT,
+    inhabited_time BIGINT,
+    last_update BIGINT,
+    block_palette TEXT[],
+    biome_palette TEXT[],
+    block_entities TEXT[],
+    entities TEXT[],
+    meta JSONB,
+    slim BYTEA,
+    arrays BYTEA,
+    updated_at TIMESTAMP DEFAULT NOW(),
+    PRIMARY KEY (x, z)
+);
+CREATE INDEX IF NOT EXISTS idx_chunks_hybrid_block_palette ON chunks_hybrid USING GIN (block_palette);
+CREATE INDEX IF NOT EXISTS idx_chunks_hybrid_biome_palette ON chunks_hybrid USING GIN (biome_palette);
+CREATE INDEX IF NOT EXISTS idx_chunks_hybrid_block_entities ON chunks_hybrid USING GIN (block_entities);
+CREATE INDEX IF NOT EXISTS idx_chunks_hybrid_meta ON chunks_hybrid USING GIN (meta jsonb_path_ops);
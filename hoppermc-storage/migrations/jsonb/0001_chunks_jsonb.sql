@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS chunks_jsonb (
+    x INT,
+    z INT,
+    data JSONB,
+    updated_at TIMESTAMP DEFAULT NOW(),
+    PRIMARY KEY (x, z)
+);
+CREATE INDEX IF NOT EXISTS idx_chunks_jsonb_data ON chunks_jsonb USING GIN (data);
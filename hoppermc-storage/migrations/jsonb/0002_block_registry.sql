@@ -0,0 +1,21 @@
+-- Palette entries become ids into a global registry of block states
+CREATE TABLE IF NOT EXISTS block_registry (
+    id SERIAL PRIMARY KEY,
+    state TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    entry JSONB NOT NULL
+);
+CREATE OR REPLACE VIEW chunk_block_palettes AS
+    SELECT c.x, c.z, (s.section->>'Y')::INT AS section_y,
+           array_agg(r.state ORDER BY p.ord) AS palette
+    FROM chunks_jsonb c
+    CROSS JOIN LATERAL jsonb_array_elements(c.data->'sections') WITH ORDINALITY AS s(section, section_ord)
+    CROSS JOIN LATERAL jsonb_array_elements(s.section->'block_states'->'palette') WITH ORDINALITY AS p(id, ord)
+    JOIN block_registry r ON jsonb_typeof(p.id) = 'number' AND r.id = p.id::INT
+    GROUP BY c.x, c.z, s.section_ord, s.section->>'Y';
+CREATE OR REPLACE VIEW chunk_block_states AS
+    SELECT DISTINCT c.x, c.z, r.state, r.name
+    FROM chunks_jsonb c
+    CROSS JOIN LATERAL jsonb_array_elements(c.data->'sections') AS s(section)
+    CROSS JOIN LATERAL jsonb_array_elements(s.section->'block_states'->'palette') AS p(id)
+    JOIN block_registry r ON jsonb_typeof(p.id) = 'number' AND r.id = p.id::INT;
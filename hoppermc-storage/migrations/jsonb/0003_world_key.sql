@@ -0,0 +1,26 @@
+-- Key chunks by world and dimension; existing rows are world 1's overworld
+ALTER TABLE chunks_jsonb
+    ADD COLUMN world_id INT NOT NULL DEFAULT 1 REFERENCES worlds (id),
+    ADD COLUMN dimension TEXT NOT NULL DEFAULT 'overworld';
+ALTER TABLE chunks_jsonb
+    ALTER COLUMN world_id DROP DEFAULT,
+    ALTER COLUMN dimension DROP DEFAULT,
+    DROP CONSTRAINT chunks_jsonb_pkey,
+    ADD PRIMARY KEY (world_id, dimension, x, z);
+-- The registry views gain the new key columns (CREATE OR REPLACE cannot insert columns)
+DROP VIEW IF EXISTS chunk_block_palettes;
+DROP VIEW IF EXISTS chunk_block_states;
+CREATE VIEW chunk_block_palettes AS
+    SELECT c.world_id, c.dimension, c.x, c.z, (s.section->>'Y')::INT AS section_y,
+           array_agg(r.state ORDER BY p.ord) AS palette
+    FROM chunks_jsonb c
+    CROSS JOIN LATERAL jsonb_array_elements(c.data->'sections') WITH ORDINALITY AS s(section, section_ord)
+    CROSS JOIN LATERAL jsonb_array_elements(s.section->'block_states'->'palette') WITH ORDINALITY AS p(id, ord)
+    JOIN block_registry r ON jsonb_typeof(p.id) = 'number' AND r.id = p.id::INT
+    GROUP BY c.world_id, c.dimension, c.x, c.z, s.section_ord, s.section->>'Y';
+CREATE VIEW chunk_block_states AS
+    SELECT DISTINCT c.world_id, c.dimension, c.x, c.z, r.state, r.name
+    FROM chunks_jsonb c
+    CROSS JOIN LATERAL jsonb_array_elements(c.data->'sections') AS s(section)
+    CROSS JOIN LATERAL jsonb_array_elements(s.section->'block_states'->'palette') AS p(id)
+    JOIN block_registry r ON jsonb_typeof(p.id) = 'number' AND r.id = p.id::INT;
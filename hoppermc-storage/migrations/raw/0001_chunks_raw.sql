@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS chunks_raw (
+    x INT,
+    z INT,
+    data BYTEA,
+    updated_at TIMESTAMP DEFAULT NOW(),
+    PRIMARY KEY (x, z)
+);
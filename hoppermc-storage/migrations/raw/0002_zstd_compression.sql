@@ -0,0 +1,11 @@
+-- Optional zstd compression (PG_COMPRESSION=zstd).
+-- dict_id: NULL = plain NBT, 0 = zstd without dictionary, n = zstd with dictionary n.
+-- raw_size: uncompressed NBT size, for the compression ratio.
+ALTER TABLE chunks_raw ADD COLUMN IF NOT EXISTS dict_id INT;
+ALTER TABLE chunks_raw ADD COLUMN IF NOT EXISTS raw_size INT;
+CREATE TABLE IF NOT EXISTS zstd_dictionaries (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    samples INT NOT NULL,
+    data BYTEA NOT NULL
+);
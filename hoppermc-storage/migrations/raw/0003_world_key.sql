@@ -0,0 +1,9 @@
+-- Key chunks by world and dimension; existing rows are world 1's overworld
+ALTER TABLE chunks_raw
+    ADD COLUMN world_id INT NOT NULL DEFAULT 1 REFERENCES worlds (id),
+    ADD COLUMN dimension TEXT NOT NULL DEFAULT 'overworld';
+ALTER TABLE chunks_raw
+    ALTER COLUMN world_id DROP DEFAULT,
+    ALTER COLUMN dimension DROP DEFAULT,
+    DROP CONSTRAINT chunks_raw_pkey,
+    ADD PRIMARY KEY (world_id, dimension, x, z);
@@ -0,0 +1,9 @@
+-- No row = the chunk is exactly what the generator produces
+CREATE TABLE IF NOT EXISTS chunks_weightless (
+    x INT,
+    z INT,
+    generator TEXT NOT NULL,
+    delta BYTEA NOT NULL,
+    updated_at TIMESTAMP DEFAULT NOW(),
+    PRIMARY KEY (x, z)
+);
@@ -0,0 +1,12 @@
+-- Worlds sharing this database; chunk tables key on (world_id, dimension, x, z).
+-- generator / seed: NULL until the first mount records them.
+CREATE TABLE IF NOT EXISTS worlds (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    generator TEXT,
+    seed BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+-- Chunks stored before worlds existed belong to world 1
+INSERT INTO worlds (id, name) VALUES (1, 'default') ON CONFLICT DO NOTHING;
+SELECT setval(pg_get_serial_sequence('worlds', 'id'), GREATEST((SELECT MAX(id) FROM worlds), 1));
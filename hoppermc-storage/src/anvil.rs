@@ -0,0 +1,712 @@
+// Anvil directory storage: chunks persisted into ordinary `r.<x>.<z>.mca` region files.
+//
+// The files are real (dense) Anvil files, not the sparse virtual layout served over
+// FUSE, so a world folder written by this backend opens in any Minecraft tool. Each
+// region keeps a sector bitmap: a save always goes to a free run of sectors (first fit,
+// else appended) and is synced, the header is updated and synced afterwards and only then
+// are the old sectors freed, so a crash mid-write leaves the previous version readable.
+// Files whose free space passes the compaction threshold are rewritten packed on `close`
+// (or `compact`), or when they are closed to stay under the open region limit. Only saves
+// create region files; reads of a region that has none find nothing.
+
+use crate::{ChunkKeyStream, ChunkStorage};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::{StreamExt, TryFutureExt, TryStreamExt};
+use hoppermc_anvil::{compress_and_wrap_chunk, unwrap_and_decompress_chunk, HEADER_BYTES, SECTOR_BYTES};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const HEADER_SECTORS: usize = (HEADER_BYTES / SECTOR_BYTES) as usize;
+// The location entry stores the sector count in one byte; larger chunks would need
+// vanilla's external .mcc files, which this backend does not write.
+const MAX_CHUNK_SECTORS: usize = 255;
+// Compact a region once this fraction of its data sectors is free
+const DEFAULT_COMPACTION_THRESHOLD: f64 = 0.25;
+// Region files kept open at once; the least recently used idle one is closed beyond this
+pub const DEFAULT_MAX_OPEN_REGIONS: usize = 64;
+
+fn region_index(x: i32, z: i32) -> usize {
+    ((x & 31) + (z & 31) * 32) as usize
+}
+
+fn region_path(dir: &Path, region_x: i32, region_z: i32) -> PathBuf {
+    dir.join(format!("r.{}.{}.mca", region_x, region_z))
+}
+
+/// Region coordinates of the `r.<x>.<z>.mca` files in `dir`, sorted.
+fn region_files(dir: &Path) -> Result<Vec<(i32, i32)>> {
+    let mut regions = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to list region directory {:?}", dir))? {
+        let name = entry?.file_name();
+        let Some(coords) = name.to_str().and_then(|n| n.strip_prefix("r.")).and_then(|n| n.strip_suffix(".mca")) else {
+            continue;
+        };
+        if let Some((x, z)) = coords.split_once('.')
+            && let (Ok(x), Ok(z)) = (x.parse(), z.parse())
+        {
+            regions.push((x, z));
+        }
+    }
+    regions.sort_unstable();
+    Ok(regions)
+}
+
+fn in_bounds(bounds: Option<((i32, i32), (i32, i32))>, x: i32, z: i32) -> bool {
+    bounds.is_none_or(|(min, max)| (min.0..=max.0).contains(&x) && (min.1..=max.1).contains(&z))
+}
+
+fn unix_time() -> u32 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() as u32).unwrap_or(0)
+}
+
+struct RegionFile {
+    path: PathBuf,
+    file: File,
+    // (first sector, sector count) per chunk index; count 0 = absent
+    locations: [(u32, u8); 1024],
+    timestamps: [u32; 1024],
+    // true = in use (header sectors included)
+    sectors: Vec<bool>,
+}
+
+impl RegionFile {
+    /// Open a region file for reading and writing. With `create`, a missing (or truncated)
+    /// file starts with an empty header; without it, such a file is left alone and None returned.
+    fn open(path: &Path, create: bool) -> Result<Option<Self>> {
+        let mut file = match OpenOptions::new().read(true).write(true).create(create).truncate(false).open(path) {
+            Ok(file) => file,
+            Err(e) if !create && e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to open region file {:?}", path)),
+        };
+        if file.metadata()?.len() < HEADER_BYTES {
+            if !create {
+                return Ok(None);
+            }
+            file.set_len(0)?;
+            file.write_all(&[0u8; HEADER_BYTES as usize])?;
+        }
+        Self::load(path, file).map(Some)
+    }
+
+    /// Open an existing region file without ever writing to it. None if it is missing or
+    /// shorter than a header.
+    fn open_read_only(path: &Path) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to open region file {:?}", path)),
+        };
+        if file.metadata()?.len() < HEADER_BYTES {
+            return Ok(None);
+        }
+        Self::load(path, file).map(Some)
+    }
+
+    fn load(path: &Path, mut file: File) -> Result<Self> {
+        let mut header = vec![0u8; HEADER_BYTES as usize];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+
+        let total_sectors = (file.metadata()?.len().div_ceil(SECTOR_BYTES) as usize).max(HEADER_SECTORS);
+        let mut region = Self {
+            path: path.to_path_buf(),
+            file,
+            locations: [(0, 0); 1024],
+            timestamps: [0; 1024],
+            sectors: vec![false; total_sectors],
+        };
+        region.sectors[..HEADER_SECTORS].fill(true);
+
+        for i in 0..1024 {
+            let loc = &header[i * 4..i * 4 + 4];
+            let offset = u32::from_be_bytes([0, loc[0], loc[1], loc[2]]);
+            let count = loc[3];
+            let ts = &header[4096 + i * 4..4096 + i * 4 + 4];
+            region.timestamps[i] = u32::from_be_bytes([ts[0], ts[1], ts[2], ts[3]]);
+            if count == 0 {
+                continue;
+            }
+            let (start, end) = (offset as usize, offset as usize + count as usize);
+            if start < HEADER_SECTORS || end > total_sectors {
+                log::warn!("{:?}: chunk {} points outside the file (sectors {}..{}), ignoring it", path, i, start, end);
+                continue;
+            }
+            region.locations[i] = (offset, count);
+            region.sectors[start..end].fill(true);
+        }
+        Ok(region)
+    }
+
+    fn read(&mut self, index: usize) -> Result<Option<Vec<u8>>> {
+        let (offset, count) = self.locations[index];
+        if count == 0 {
+            return Ok(None);
+        }
+        let mut blob = vec![0u8; count as usize * SECTOR_BYTES as usize];
+        self.file.seek(SeekFrom::Start(offset as u64 * SECTOR_BYTES))?;
+        self.file.read_exact(&mut blob)?;
+
+        let length = u32::from_be_bytes([blob[0], blob[1], blob[2], blob[3]]) as usize;
+        if length == 0 || length + 4 > blob.len() {
+            anyhow::bail!("{:?}: chunk {} has invalid length {}", self.path, index, length);
+        }
+        blob.truncate(length + 4);
+        unwrap_and_decompress_chunk(&blob).map(Some)
+    }
+
+    /// Every readable chunk, with absolute coordinates. Unreadable ones are logged and skipped.
+    fn read_all(&mut self, region_x: i32, region_z: i32) -> Vec<(i32, i32, Vec<u8>)> {
+        let mut chunks = Vec::new();
+        for index in 0..1024 {
+            let (x, z) = (region_x * 32 + (index % 32) as i32, region_z * 32 + (index / 32) as i32);
+            match self.read(index) {
+                Ok(Some(data)) => chunks.push((x, z, data)),
+                Ok(None) => {}
+                Err(e) => log::error!("Failed to read chunk ({}, {}): {:?}", x, z, e),
+            }
+        }
+        chunks
+    }
+
+    /// Absolute coordinates of the chunks the header lists.
+    fn keys(&self, region_x: i32, region_z: i32) -> Vec<(i32, i32)> {
+        (0..1024)
+            .filter(|i| self.locations[*i].1 > 0)
+            .map(|i| (region_x * 32 + (i % 32) as i32, region_z * 32 + (i / 32) as i32))
+            .collect()
+    }
+
+    /// First free run of `count` sectors, or the end of the file.
+    fn allocate(&self, count: usize) -> usize {
+        let mut run = 0;
+        for (i, used) in self.sectors.iter().enumerate() {
+            run = if *used { 0 } else { run + 1 };
+            if run == count {
+                return i + 1 - count;
+            }
+        }
+        // Extend the trailing free run (if any) past the end
+        self.sectors.len() - run
+    }
+
+    fn write(&mut self, index: usize, nbt: &[u8]) -> Result<()> {
+        let blob = compress_and_wrap_chunk(nbt).ok_or_else(|| anyhow::anyhow!("Failed to compress chunk"))?;
+        let count = blob.len().div_ceil(SECTOR_BYTES as usize);
+        if count > MAX_CHUNK_SECTORS {
+            anyhow::bail!("{:?}: chunk {} needs {} sectors (max {})", self.path, index, count, MAX_CHUNK_SECTORS);
+        }
+
+        let start = self.allocate(count);
+        let mut padded = blob;
+        padded.resize(count * SECTOR_BYTES as usize, 0);
+        self.file.seek(SeekFrom::Start(start as u64 * SECTOR_BYTES))?;
+        self.file.write_all(&padded)?;
+        // The data must be on disk before the header points at it
+        self.file.sync_data()?;
+        if self.sectors.len() < start + count {
+            self.sectors.resize(start + count, false);
+        }
+        self.sectors[start..start + count].fill(true);
+
+        let old = self.locations[index];
+        self.locations[index] = (start as u32, count as u8);
+        self.timestamps[index] = unix_time();
+        self.write_header_entry(index)?;
+        self.file.sync_data()?;
+
+        // Free the previous copy only once the header on disk points at the new one
+        if old.1 > 0 {
+            self.sectors[old.0 as usize..old.0 as usize + old.1 as usize].fill(false);
+        }
+        Ok(())
+    }
+
+    /// Drop a chunk from the header; its sectors are freed once that is on disk.
+    fn delete(&mut self, index: usize) -> Result<bool> {
+        let old = self.locations[index];
+        if old.1 == 0 {
+            return Ok(false);
+        }
+        self.locations[index] = (0, 0);
+        self.timestamps[index] = 0;
+        self.write_header_entry(index)?;
+        self.file.sync_data()?;
+        self.sectors[old.0 as usize..old.0 as usize + old.1 as usize].fill(false);
+        Ok(true)
+    }
+
+    fn write_header_entry(&mut self, index: usize) -> Result<()> {
+        let (offset, count) = self.locations[index];
+        let o = offset.to_be_bytes();
+        self.file.seek(SeekFrom::Start(index as u64 * 4))?;
+        self.file.write_all(&[o[1], o[2], o[3], count])?;
+        self.file.seek(SeekFrom::Start(4096 + index as u64 * 4))?;
+        self.file.write_all(&self.timestamps[index].to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Fraction of data sectors (up to the last used one) that are free.
+    fn free_ratio(&self) -> f64 {
+        let end = self.sectors.iter().rposition(|used| *used).map(|i| i + 1).unwrap_or(HEADER_SECTORS);
+        let data = end - HEADER_SECTORS;
+        if data == 0 {
+            return 0.0;
+        }
+        let free = self.sectors[HEADER_SECTORS..end].iter().filter(|used| !**used).count();
+        free as f64 / data as f64
+    }
+
+    /// Rewrite the file with chunks packed back to back (temp file + rename).
+    fn compact(&mut self) -> Result<()> {
+        let tmp = self.path.with_extension("mca.tmp");
+        let mut out = File::create(&tmp).with_context(|| format!("Failed to create {:?}", tmp))?;
+        let mut locations = [(0u32, 0u8); 1024];
+        let mut next = HEADER_SECTORS as u32;
+        let mut data = Vec::new();
+
+        for (i, (offset, count)) in self.locations.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            let mut sectors = vec![0u8; *count as usize * SECTOR_BYTES as usize];
+            self.file.seek(SeekFrom::Start(*offset as u64 * SECTOR_BYTES))?;
+            self.file.read_exact(&mut sectors)?;
+            data.extend_from_slice(&sectors);
+            locations[i] = (next, *count);
+            next += *count as u32;
+        }
+
+        let mut header = vec![0u8; HEADER_BYTES as usize];
+        for i in 0..1024 {
+            let o = locations[i].0.to_be_bytes();
+            header[i * 4..i * 4 + 4].copy_from_slice(&[o[1], o[2], o[3], locations[i].1]);
+            header[4096 + i * 4..4096 + i * 4 + 4].copy_from_slice(&self.timestamps[i].to_be_bytes());
+        }
+        out.write_all(&header)?;
+        out.write_all(&data)?;
+        out.sync_all()?;
+        drop(out);
+
+        std::fs::rename(&tmp, &self.path).with_context(|| format!("Failed to replace {:?}", self.path))?;
+        self.file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.locations = locations;
+        self.sectors = vec![true; next as usize];
+        Ok(())
+    }
+}
+
+type SharedRegion = Arc<Mutex<RegionFile>>;
+
+/// Open region files, least recently used closed first.
+struct OpenRegions {
+    dir: PathBuf,
+    // Region and the tick it was last used at
+    regions: HashMap<(i32, i32), (SharedRegion, u64)>,
+    tick: u64,
+    max_open: usize,
+    compaction_threshold: f64,
+}
+
+impl OpenRegions {
+    /// The open region, or open it (closing idle ones past the limit). Without `create`,
+    /// None if the region has no file. Blocking.
+    fn get(&mut self, region_x: i32, region_z: i32, create: bool) -> Result<Option<SharedRegion>> {
+        self.tick += 1;
+        if let Some((region, used)) = self.regions.get_mut(&(region_x, region_z)) {
+            *used = self.tick;
+            return Ok(Some(region.clone()));
+        }
+        let Some(region) = RegionFile::open(&region_path(&self.dir, region_x, region_z), create)? else {
+            return Ok(None);
+        };
+        while self.regions.len() >= self.max_open.max(1) && self.close_idle() {}
+        let region = Arc::new(Mutex::new(region));
+        self.regions.insert((region_x, region_z), (region.clone(), self.tick));
+        Ok(Some(region))
+    }
+
+    /// Close the least recently used region nobody is using. Regions in use stay open
+    /// (over the limit) so a file is never open twice. Returns false if none was idle.
+    fn close_idle(&mut self) -> bool {
+        let Some(key) = self.regions.iter()
+            .filter(|(_, (region, _))| Arc::strong_count(region) == 1)
+            .min_by_key(|(_, (_, used))| *used)
+            .map(|(key, _)| *key)
+        else {
+            return false;
+        };
+        let (region, _) = self.regions.remove(&key).unwrap();
+        let mut region = region.lock().unwrap();
+        let ratio = region.free_ratio();
+        if ratio > self.compaction_threshold {
+            match region.compact() {
+                Ok(()) => log::info!("Compacted {:?} ({:.0}% free)", region.path, ratio * 100.0),
+                Err(e) => log::warn!("Failed to compact {:?}: {:?}", region.path, e),
+            }
+        }
+        true
+    }
+
+    fn all(&self) -> Vec<SharedRegion> {
+        self.regions.values().map(|(region, _)| region.clone()).collect()
+    }
+}
+
+pub struct AnvilStorage {
+    dir: PathBuf,
+    regions: Arc<Mutex<OpenRegions>>,
+    compaction_threshold: f64,
+}
+
+impl AnvilStorage {
+    /// Store regions in `dir` (created if missing). Existing `.mca` files are used as-is.
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create region directory {:?}", dir))?;
+        log::info!("Anvil storage in {:?}", dir);
+        let regions = OpenRegions {
+            dir: dir.to_path_buf(),
+            regions: HashMap::new(),
+            tick: 0,
+            max_open: DEFAULT_MAX_OPEN_REGIONS,
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+        };
+        Ok(Self { dir: dir.to_path_buf(), regions: Arc::new(Mutex::new(regions)), compaction_threshold: DEFAULT_COMPACTION_THRESHOLD })
+    }
+
+    /// Free-space fraction above which a region is compacted on `close` (1.0 = never).
+    pub fn with_compaction_threshold(mut self, threshold: f64) -> Self {
+        self.compaction_threshold = threshold;
+        self.regions.lock().unwrap().compaction_threshold = threshold;
+        self
+    }
+
+    /// Region files kept open at once.
+    pub fn with_max_open_regions(self, max_open: usize) -> Self {
+        self.regions.lock().unwrap().max_open = max_open;
+        self
+    }
+
+    /// Run `f` on a region, on the blocking thread pool. Only `create` makes a missing
+    /// region file; otherwise a missing region returns None.
+    async fn with_region<T, F>(&self, region_x: i32, region_z: i32, create: bool, f: F) -> Result<Option<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut RegionFile) -> Result<T> + Send + 'static,
+    {
+        let regions = self.regions.clone();
+        tokio::task::spawn_blocking(move || {
+            let Some(region) = regions.lock().unwrap().get(region_x, region_z, create)? else {
+                return Ok(None);
+            };
+            f(&mut region.lock().unwrap()).map(Some)
+        })
+        .await?
+    }
+
+    /// Compact every open region whose free space exceeds the threshold.
+    /// Returns the number of regions rewritten.
+    pub async fn compact(&self) -> Result<usize> {
+        let regions = self.regions.lock().unwrap().all();
+        let threshold = self.compaction_threshold;
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut compacted = 0;
+            for region in regions {
+                let mut region = region.lock().unwrap();
+                let ratio = region.free_ratio();
+                if ratio > threshold {
+                    region.compact()?;
+                    log::info!("Compacted {:?} ({:.0}% free)", region.path, ratio * 100.0);
+                    compacted += 1;
+                }
+            }
+            Ok(compacted)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl ChunkStorage for AnvilStorage {
+    async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+        let data = data.to_vec();
+        self.with_region(x >> 5, z >> 5, true, move |region| region.write(region_index(x, z), &data)).await?;
+        Ok(())
+    }
+
+    async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+        Ok(self.with_region(x >> 5, z >> 5, false, move |region| region.read(region_index(x, z))).await?.flatten())
+    }
+
+    async fn load_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        let chunks = self.with_region(region_x, region_z, false, move |region| Ok(region.read_all(region_x, region_z))).await?;
+        Ok(chunks.unwrap_or_default())
+    }
+
+    async fn exists(&self, x: i32, z: i32) -> Result<bool> {
+        let stored = self.with_region(x >> 5, z >> 5, false, move |region| Ok(region.locations[region_index(x, z)].1 > 0)).await?;
+        Ok(stored.unwrap_or(false))
+    }
+
+    async fn delete_chunk(&self, x: i32, z: i32) -> Result<bool> {
+        let deleted = self.with_region(x >> 5, z >> 5, false, move |region| region.delete(region_index(x, z))).await?;
+        Ok(deleted.unwrap_or(false))
+    }
+
+    /// Region by region, from the headers alone (read-only, so open regions are not disturbed).
+    fn list_chunks(&self, bounds: Option<((i32, i32), (i32, i32))>) -> ChunkKeyStream<'_> {
+        let dir = self.dir.clone();
+        let overlaps = move |(rx, rz): &(i32, i32)| {
+            bounds.is_none_or(|(min, max)| (min.0 >> 5..=max.0 >> 5).contains(rx) && (min.1 >> 5..=max.1 >> 5).contains(rz))
+        };
+        async move {
+            let regions = {
+                let dir = dir.clone();
+                tokio::task::spawn_blocking(move || region_files(&dir)).await??
+            };
+            Ok(futures::stream::iter(regions.into_iter().filter(overlaps))
+                .then(move |(rx, rz)| {
+                    let path = region_path(&dir, rx, rz);
+                    async move {
+                        let region = tokio::task::spawn_blocking(move || RegionFile::open_read_only(&path)).await??;
+                        let keys = region.map(|region| region.keys(rx, rz)).unwrap_or_default();
+                        Ok::<_, anyhow::Error>(futures::stream::iter(keys.into_iter().filter(move |(x, z)| in_bounds(bounds, *x, *z)).map(Ok)))
+                    }
+                })
+                .try_flatten())
+        }
+        .try_flatten_stream()
+        .boxed()
+    }
+
+    async fn get_total_size(&self) -> Result<u64> {
+        let mut total = 0;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.path().extension().is_some_and(|ext| ext == "mca") {
+                total += entry.metadata()?.len();
+            }
+        }
+        Ok(total)
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.compact().await?;
+        let regions = self.regions.lock().unwrap().all();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            for region in regions {
+                region.lock().unwrap().file.sync_all()?;
+            }
+            Ok(())
+        })
+        .await?
+    }
+}
+
+/// Read-only view of a region directory, for importing someone else's world: files are
+/// opened read-only and never created, truncated or compacted.
+pub struct AnvilReader {
+    dir: PathBuf,
+}
+
+impl AnvilReader {
+    pub fn open(dir: &Path) -> Result<Self> {
+        anyhow::ensure!(dir.is_dir(), "{:?} is not a directory", dir);
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    /// Region coordinates of the `r.<x>.<z>.mca` files, sorted.
+    pub fn regions(&self) -> Result<Vec<(i32, i32)>> {
+        region_files(&self.dir)
+    }
+
+    /// Every readable chunk of one region; empty if the file is missing or truncated.
+    /// Blocking: call from a blocking thread.
+    pub fn read_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        Ok(RegionFile::open_read_only(&region_path(&self.dir, region_x, region_z))?
+            .map(|mut region| region.read_all(region_x, region_z))
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hoppermc-anvil-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_roundtrip_and_reopen() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dir = temp_dir("roundtrip");
+        rt.block_on(async {
+            let storage = AnvilStorage::open(&dir).unwrap();
+            storage.save_chunk(0, 0, b"origin").await.unwrap();
+            storage.save_chunk(-1, 33, b"elsewhere").await.unwrap();
+            assert_eq!(storage.load_chunk(0, 0).await.unwrap(), Some(b"origin".to_vec()));
+            assert_eq!(storage.load_chunk(1, 0).await.unwrap(), None);
+            storage.close().await.unwrap();
+
+            assert!(dir.join("r.-1.1.mca").exists());
+            let reopened = AnvilStorage::open(&dir).unwrap();
+            assert_eq!(reopened.load_region(-1, 1).await.unwrap(), vec![(-1, 33, b"elsewhere".to_vec())]);
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reads_do_not_create_region_files() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dir = temp_dir("readonly");
+        rt.block_on(async {
+            let storage = AnvilStorage::open(&dir).unwrap();
+            assert_eq!(storage.load_chunk(5, 5).await.unwrap(), None);
+            assert!(storage.load_region(3, -2).await.unwrap().is_empty());
+            assert!(!storage.exists(100, 100).await.unwrap());
+            assert!(!storage.delete_chunk(-40, 7).await.unwrap());
+            assert!(std::fs::read_dir(&dir).unwrap().next().is_none());
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_exists_delete_and_list() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dir = temp_dir("list");
+        rt.block_on(async {
+            let storage = AnvilStorage::open(&dir).unwrap();
+            for (x, z) in [(0, 0), (1, 5), (-3, 2), (40, 40)] {
+                storage.save_chunk(x, z, &[1, 2]).await.unwrap();
+            }
+            assert!(storage.exists(1, 5).await.unwrap());
+            assert!(!storage.exists(5, 1).await.unwrap());
+
+            let mut all: Vec<(i32, i32)> = storage.list_chunks(None).try_collect().await.unwrap();
+            all.sort();
+            assert_eq!(all, vec![(-3, 2), (0, 0), (1, 5), (40, 40)]);
+            let inside: Vec<(i32, i32)> = storage.list_chunks(Some(((-5, 1), (0, 4)))).try_collect().await.unwrap();
+            assert_eq!(inside, vec![(-3, 2)]);
+
+            assert!(storage.delete_chunk(1, 5).await.unwrap());
+            assert!(!storage.delete_chunk(1, 5).await.unwrap());
+            assert_eq!(storage.load_chunk(1, 5).await.unwrap(), None);
+
+            // Deletion is in the header on disk
+            let reopened = AnvilStorage::open(&dir).unwrap();
+            assert!(!reopened.exists(1, 5).await.unwrap());
+            assert!(reopened.exists(0, 0).await.unwrap());
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reader_leaves_files_untouched() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dir = temp_dir("reader");
+        rt.block_on(async {
+            let storage = AnvilStorage::open(&dir).unwrap().with_compaction_threshold(1.0);
+            storage.save_chunk(0, 0, b"kept").await.unwrap();
+            storage.save_chunk(0, 0, b"rewritten, leaving free sectors behind").await.unwrap();
+            storage.close().await.unwrap();
+        });
+        // A truncated file the read-write backend would have reset to an empty header
+        std::fs::write(dir.join("r.1.1.mca"), b"short").unwrap();
+        let before = std::fs::read(dir.join("r.0.0.mca")).unwrap();
+
+        let reader = AnvilReader::open(&dir).unwrap();
+        assert_eq!(reader.regions().unwrap(), vec![(0, 0), (1, 1)]);
+        assert_eq!(reader.read_region(0, 0).unwrap(), vec![(0, 0, b"rewritten, leaving free sectors behind".to_vec())]);
+        assert!(reader.read_region(1, 1).unwrap().is_empty());
+        assert!(reader.read_region(7, 7).unwrap().is_empty());
+
+        assert_eq!(std::fs::read(dir.join("r.0.0.mca")).unwrap(), before);
+        assert_eq!(std::fs::read(dir.join("r.1.1.mca")).unwrap(), b"short");
+        assert!(!dir.join("r.7.7.mca").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_growing_chunk_moves_and_frees_sectors() {
+        let dir = temp_dir("grow");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut region = RegionFile::open(&dir.join("r.0.0.mca"), true).unwrap().unwrap();
+
+        // Incompressible payload so sector counts are predictable
+        let mut seed = 1u32;
+        let big: Vec<u8> = (0..3 * SECTOR_BYTES as usize).map(|_| { seed = seed.wrapping_mul(1664525).wrapping_add(1013904223); (seed >> 24) as u8 }).collect();
+
+        region.write(0, b"small").unwrap();
+        region.write(1, b"neighbour").unwrap();
+        assert_eq!(region.locations[0], (2, 1));
+        region.write(0, &big).unwrap();
+
+        // Chunk 0 no longer fits in place: it moves past its neighbour and sector 2 is freed
+        assert!(region.locations[0].0 > 3);
+        assert!(!region.sectors[2]);
+        assert_eq!(region.read(0).unwrap(), Some(big.clone()));
+
+        // The freed sector is reused by the next small chunk
+        region.write(2, b"reuse").unwrap();
+        assert_eq!(region.locations[2].0, 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_open_regions_are_bounded() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dir = temp_dir("bounded");
+        rt.block_on(async {
+            let storage = AnvilStorage::open(&dir).unwrap().with_max_open_regions(2);
+            for rx in 0..5 {
+                storage.save_chunk(rx * 32, 0, &[rx as u8]).await.unwrap();
+            }
+            assert_eq!(storage.regions.lock().unwrap().regions.len(), 2);
+            // The most recent regions stay open, closed ones reopen from disk
+            assert!(storage.regions.lock().unwrap().regions.contains_key(&(4, 0)));
+            for rx in 0..5 {
+                assert_eq!(storage.load_chunk(rx * 32, 0).await.unwrap(), Some(vec![rx as u8]));
+            }
+
+            // A region in use is never closed, even past the limit
+            let held = storage.regions.lock().unwrap().get(0, 0, false).unwrap().unwrap();
+            storage.save_chunk(5 * 32, 0, &[5]).await.unwrap();
+            storage.save_chunk(6 * 32, 0, &[6]).await.unwrap();
+            assert!(storage.regions.lock().unwrap().regions.contains_key(&(0, 0)));
+            drop(held);
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_compaction_shrinks_file() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dir = temp_dir("compact");
+        rt.block_on(async {
+            let storage = AnvilStorage::open(&dir).unwrap().with_compaction_threshold(0.0);
+            for i in 0..8 {
+                storage.save_chunk(i, 0, &[i as u8; 100]).await.unwrap();
+            }
+            // Rewrite everything: freed sectors are mostly reused, but a hole remains
+            for i in 0..8 {
+                storage.save_chunk(i, 0, &[i as u8; 200]).await.unwrap();
+            }
+            let before = storage.get_total_size().await.unwrap();
+            assert_eq!(storage.compact().await.unwrap(), 1);
+            let after = storage.get_total_size().await.unwrap();
+            assert!(after < before, "{} !< {}", after, before);
+            assert_eq!(after, HEADER_BYTES + 8 * SECTOR_BYTES);
+
+            for i in 0..8 {
+                assert_eq!(storage.load_chunk(i, 0).await.unwrap(), Some(vec![i as u8; 200]));
+            }
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
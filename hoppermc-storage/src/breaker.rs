@@ -0,0 +1,352 @@
+// Circuit breaker around a storage backend.
+//
+// After `failure_threshold` consecutive failures the breaker opens and every call fails
+// immediately with `StorageUnavailable` instead of waiting on a dead connection. Once
+// `open_duration` has passed a single trial call is let through (half-open): success
+// closes the breaker, failure opens it again. Only outages (connection, pool, IO,
+// timeout errors) count as failures; a rejected chunk or a parse error passes
+// through without affecting the breaker.
+
+use crate::{ChunkKeyStream, ChunkStorage, CompressionStats, PoolStats};
+use anyhow::Result;
+use async_trait::async_trait;
+use hoppermc_benchmark::BenchmarkMetrics;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Storage healthy, calls pass through.
+    Closed,
+    /// Storage considered down, calls are rejected.
+    Open,
+    /// Trial call in flight to probe whether storage is back.
+    HalfOpen,
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    pub failure_threshold: u32,
+    pub open_duration: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Returned (wrapped in `anyhow::Error`) for calls rejected by an open breaker.
+#[derive(Debug)]
+pub struct StorageUnavailable;
+
+impl std::fmt::Display for StorageUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "storage unavailable (circuit breaker open)")
+    }
+}
+
+impl std::error::Error for StorageUnavailable {}
+
+/// Whether an error means storage is unreachable, as opposed to a call that reached it
+/// and was refused (bad chunk data, full memory store, unsupported operation).
+pub fn is_outage(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if cause.is::<StorageUnavailable>() || cause.is::<std::io::Error>() || cause.is::<tokio::time::error::Elapsed>()
+            || cause.is::<deadpool_postgres::PoolError>()
+        {
+            return true;
+        }
+        if let Some(e) = cause.downcast_ref::<tokio_postgres::Error>() {
+            // Server-side errors are logical, except connection loss, cancellation
+            // (statement timeout, shutdown) and resource exhaustion
+            return e.as_db_error().is_none_or(|db| {
+                let code = db.code().code();
+                ["08", "53", "57", "58"].iter().any(|class| code.starts_with(class))
+            });
+        }
+        if let Some(e) = cause.downcast_ref::<redis::RedisError>() {
+            return e.is_io_error() || e.is_timeout() || e.is_connection_dropped() || e.is_connection_refusal();
+        }
+        if let Some(rusqlite::Error::SqliteFailure(e, _)) = cause.downcast_ref::<rusqlite::Error>() {
+            use rusqlite::ErrorCode::*;
+            return matches!(e.code, DatabaseBusy | DatabaseLocked | CannotOpen | DiskFull | SystemIoFailure);
+        }
+        #[cfg(feature = "s3")]
+        if let Some(e) = cause.downcast_ref::<object_store::Error>() {
+            return matches!(e, object_store::Error::Generic { .. } | object_store::Error::JoinError { .. });
+        }
+        false
+    })
+}
+
+struct BreakerInner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    inner: Mutex<BreakerInner>,
+    benchmark: Option<Arc<BenchmarkMetrics>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: BreakerConfig, benchmark: Option<Arc<BenchmarkMetrics>>) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(BreakerInner { state: BreakerState::Closed, consecutive_failures: 0, opened_at: None }),
+            benchmark,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.inner.lock().unwrap().state
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.state() == BreakerState::Closed
+    }
+
+    /// Whether a call may go to storage now. Moves Open -> HalfOpen once the open period is over.
+    pub fn allow(&self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&self, now: Instant) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            BreakerState::Closed => true,
+            // One trial at a time; a trial that never reported back (cancelled) is
+            // replaced after another open period.
+            BreakerState::Open | BreakerState::HalfOpen => {
+                let elapsed = inner.opened_at.map(|at| now.duration_since(at)).unwrap_or_default();
+                if elapsed >= self.config.open_duration {
+                    if inner.state == BreakerState::Open {
+                        log::info!("Storage circuit breaker half-open, probing storage");
+                    }
+                    inner.state = BreakerState::HalfOpen;
+                    inner.opened_at = Some(now);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state != BreakerState::Closed {
+            log::info!("Storage circuit breaker closed, storage recovered");
+            self.report(false);
+        }
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+    }
+
+    pub fn record_failure(&self) {
+        self.record_failure_at(Instant::now());
+    }
+
+    fn record_failure_at(&self, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        let trip = match inner.state {
+            BreakerState::HalfOpen => true,
+            BreakerState::Closed => inner.consecutive_failures >= self.config.failure_threshold,
+            BreakerState::Open => false,
+        };
+        if trip {
+            log::error!(
+                "Storage circuit breaker OPEN after {} consecutive failures, rejecting storage calls for {:?}",
+                inner.consecutive_failures, self.config.open_duration
+            );
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some(now);
+            if let Some(bench) = &self.benchmark {
+                bench.record_breaker_trip();
+            }
+            self.report(true);
+        }
+    }
+
+    fn report(&self, open: bool) {
+        if let Some(bench) = &self.benchmark {
+            bench.record_breaker_open(open);
+        }
+    }
+
+    /// Run a storage call through the breaker.
+    async fn call<T, F>(&self, fut: F) -> Result<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        if !self.allow() {
+            if let Some(bench) = &self.benchmark {
+                bench.record_breaker_rejected();
+            }
+            return Err(StorageUnavailable.into());
+        }
+        let result = fut.await;
+        match &result {
+            Ok(_) => self.record_success(),
+            Err(e) if is_outage(e) => self.record_failure(),
+            Err(_) => {}
+        }
+        result
+    }
+}
+
+/// `ChunkStorage` wrapper that routes every call through a `CircuitBreaker`.
+pub struct BreakerStorage {
+    inner: Arc<dyn ChunkStorage>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl BreakerStorage {
+    pub fn new(inner: Arc<dyn ChunkStorage>, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { inner, breaker }
+    }
+
+    pub fn breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.breaker
+    }
+}
+
+#[async_trait]
+impl ChunkStorage for BreakerStorage {
+    async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+        self.breaker.call(self.inner.save_chunk(x, z, data)).await
+    }
+
+    async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+        self.breaker.call(self.inner.load_chunk(x, z)).await
+    }
+
+    async fn load_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        self.breaker.call(self.inner.load_region(region_x, region_z)).await
+    }
+
+    async fn load_chunks(&self, coords: &[(i32, i32)]) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        self.breaker.call(self.inner.load_chunks(coords)).await
+    }
+
+    async fn save_chunks(&self, chunks: &[(i32, i32, Vec<u8>)]) -> Result<()> {
+        self.breaker.call(self.inner.save_chunks(chunks)).await
+    }
+
+    async fn ingest_chunks(&self, chunks: &[(i32, i32, Vec<u8>)]) -> Result<()> {
+        self.breaker.call(self.inner.ingest_chunks(chunks)).await
+    }
+
+    async fn load_range(&self, min: (i32, i32), max: (i32, i32)) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        self.breaker.call(self.inner.load_range(min, max)).await
+    }
+
+    async fn exists(&self, x: i32, z: i32) -> Result<bool> {
+        self.breaker.call(self.inner.exists(x, z)).await
+    }
+
+    async fn delete_chunk(&self, x: i32, z: i32) -> Result<bool> {
+        self.breaker.call(self.inner.delete_chunk(x, z)).await
+    }
+
+    fn list_chunks(&self, bounds: Option<((i32, i32), (i32, i32))>) -> ChunkKeyStream<'_> {
+        // A stream outlives any single call, so it bypasses the breaker
+        self.inner.list_chunks(bounds)
+    }
+
+    async fn get_total_size(&self) -> Result<u64> {
+        self.inner.get_total_size().await
+    }
+
+    async fn compression_stats(&self) -> Result<Option<CompressionStats>> {
+        self.inner.compression_stats().await
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        self.inner.pool_stats()
+    }
+
+    fn needs_generated_chunks(&self) -> bool {
+        self.inner.needs_generated_chunks()
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(threshold: u32) -> CircuitBreaker {
+        CircuitBreaker::new(BreakerConfig { failure_threshold: threshold, open_duration: Duration::from_secs(5) }, None)
+    }
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let breaker = breaker(3);
+        let now = Instant::now();
+        breaker.record_failure_at(now);
+        breaker.record_failure_at(now);
+        assert!(breaker.allow_at(now));
+        breaker.record_failure_at(now);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.allow_at(now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let breaker = breaker(2);
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(breaker.is_healthy());
+    }
+
+    #[test]
+    fn test_half_open_trial() {
+        let breaker = breaker(1);
+        let now = Instant::now();
+        breaker.record_failure_at(now);
+
+        // One trial after the open period; others are still rejected while it runs
+        let later = now + Duration::from_secs(5);
+        assert!(breaker.allow_at(later));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(!breaker.allow_at(later));
+
+        // Failed trial re-opens, successful one closes
+        breaker.record_failure_at(later);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(breaker.allow_at(later + Duration::from_secs(5)));
+        breaker.record_success();
+        assert!(breaker.is_healthy());
+    }
+
+    #[test]
+    fn test_logical_errors_pass_through() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let breaker = breaker(1);
+        rt.block_on(async {
+            for _ in 0..5 {
+                let err = breaker.call(async { Err::<(), _>(anyhow::anyhow!("Memory storage full")) }).await.unwrap_err();
+                assert_eq!(err.to_string(), "Memory storage full");
+            }
+            assert!(breaker.is_healthy());
+
+            let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+            let err = breaker.call(async { Err::<(), _>(anyhow::Error::new(refused).context("Failed to load chunk")) }).await.unwrap_err();
+            assert!(is_outage(&err));
+        });
+        assert_eq!(breaker.state(), BreakerState::Open);
+    }
+}
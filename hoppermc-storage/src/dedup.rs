@@ -0,0 +1,217 @@
+// Content-addressed chunk sections (pg_dedup).
+//
+// Air, stone and deepslate sections repeat endlessly, so each 16³ section is stored
+// once in `chunk_sections` under the SHA-256 of its content, and the chunk row keeps
+// the rest of the tree plus the ordered list of section hashes.
+//
+// A section's `Y` tag is kept with the chunk rather than the section, so the same
+// content at different heights shares one row. fastnbt compounds are hash maps, so
+// serialized bytes are not a stable identity: the hash is taken over a canonical
+// encoding with sorted keys instead.
+
+use crate::hybrid::{compress, decompress};
+use anyhow::Result;
+use fastnbt::Value;
+use sha2::{Digest, Sha256};
+
+/// Content hash of a section.
+pub type SectionHash = [u8; 32];
+
+/// One entry of a chunk's `sections` list.
+#[derive(Debug, Clone)]
+pub struct DedupSection {
+    pub hash: SectionHash,
+    /// The section's `Y` byte, stripped from `data` (None if it had no byte `Y`).
+    pub y: Option<i8>,
+    /// The section without `Y`, as compressed NBT.
+    pub data: Vec<u8>,
+}
+
+/// A chunk split for the `chunks_dedup` / `chunk_sections` tables.
+#[derive(Debug, Clone)]
+pub struct DedupChunk {
+    /// The chunk without its sections, as compressed NBT.
+    pub body: Vec<u8>,
+    /// Sections in order. None if the chunk has no `sections` list.
+    pub sections: Option<Vec<DedupSection>>,
+}
+
+fn hash_value(value: &Value, hasher: &mut Sha256) {
+    match value {
+        Value::Byte(v) => hasher.update([1, *v as u8]),
+        Value::Short(v) => {
+            hasher.update([2]);
+            hasher.update(v.to_le_bytes());
+        }
+        Value::Int(v) => {
+            hasher.update([3]);
+            hasher.update(v.to_le_bytes());
+        }
+        Value::Long(v) => {
+            hasher.update([4]);
+            hasher.update(v.to_le_bytes());
+        }
+        Value::Float(v) => {
+            hasher.update([5]);
+            hasher.update(v.to_bits().to_le_bytes());
+        }
+        Value::Double(v) => {
+            hasher.update([6]);
+            hasher.update(v.to_bits().to_le_bytes());
+        }
+        Value::String(s) => {
+            hasher.update([8]);
+            hasher.update((s.len() as u32).to_le_bytes());
+            hasher.update(s.as_bytes());
+        }
+        Value::ByteArray(a) => {
+            hasher.update([7]);
+            hasher.update((a.len() as u32).to_le_bytes());
+            a.iter().for_each(|b| hasher.update([*b as u8]));
+        }
+        Value::IntArray(a) => {
+            hasher.update([11]);
+            hasher.update((a.len() as u32).to_le_bytes());
+            a.iter().for_each(|v| hasher.update(v.to_le_bytes()));
+        }
+        Value::LongArray(a) => {
+            hasher.update([12]);
+            hasher.update((a.len() as u32).to_le_bytes());
+            a.iter().for_each(|v| hasher.update(v.to_le_bytes()));
+        }
+        Value::List(items) => {
+            hasher.update([9]);
+            hasher.update((items.len() as u32).to_le_bytes());
+            items.iter().for_each(|item| hash_value(item, hasher));
+        }
+        Value::Compound(map) => {
+            hasher.update([10]);
+            hasher.update((map.len() as u32).to_le_bytes());
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            for key in keys {
+                hasher.update((key.len() as u32).to_le_bytes());
+                hasher.update(key.as_bytes());
+                hash_value(&map[key], hasher);
+            }
+        }
+    }
+}
+
+/// Stable content hash of an NBT value.
+pub fn section_hash(section: &Value) -> SectionHash {
+    let mut hasher = Sha256::new();
+    hash_value(section, &mut hasher);
+    hasher.finalize().into()
+}
+
+/// Split raw chunk NBT into its body and content-addressed sections.
+pub fn split_sections(nbt: &[u8]) -> Result<DedupChunk> {
+    let mut root: Value = fastnbt::from_bytes(nbt).map_err(|e| anyhow::anyhow!("Failed to parse NBT: {:?}", e))?;
+    let Value::Compound(map) = &mut root else { anyhow::bail!("NBT root is not a compound") };
+
+    let sections = match map.remove("sections") {
+        Some(Value::List(items)) => {
+            let mut sections = Vec::with_capacity(items.len());
+            for mut section in items {
+                let y = match &mut section {
+                    Value::Compound(fields) => match fields.get("Y") {
+                        Some(Value::Byte(y)) => {
+                            let y = *y;
+                            fields.remove("Y");
+                            Some(y)
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let nbt = fastnbt::to_bytes(&section).map_err(|e| anyhow::anyhow!("Failed to encode section: {:?}", e))?;
+                sections.push(DedupSection { hash: section_hash(&section), y, data: compress(&nbt)? });
+            }
+            Some(sections)
+        }
+        // Not a list of sections: leave it in the body untouched
+        Some(other) => {
+            map.insert("sections".to_string(), other);
+            None
+        }
+        None => None,
+    };
+
+    let body = fastnbt::to_bytes(&root).map_err(|e| anyhow::anyhow!("Failed to encode chunk body: {:?}", e))?;
+    Ok(DedupChunk { body: compress(&body)?, sections })
+}
+
+/// Rebuild raw chunk NBT from its body and its sections as (Y, data), in order.
+pub fn join_sections(body: &[u8], sections: Option<Vec<(Option<i8>, &[u8])>>) -> Result<Vec<u8>> {
+    let mut root: Value = fastnbt::from_bytes(&decompress(body)?).map_err(|e| anyhow::anyhow!("Failed to parse chunk body: {:?}", e))?;
+    if let Some(sections) = sections {
+        let Value::Compound(map) = &mut root else { anyhow::bail!("NBT root is not a compound") };
+        let mut items = Vec::with_capacity(sections.len());
+        for (y, data) in sections {
+            let mut section = fastnbt::from_bytes(&decompress(data)?).map_err(|e| anyhow::anyhow!("Failed to parse section: {:?}", e))?;
+            if let (Some(y), Value::Compound(fields)) = (y, &mut section) {
+                fields.insert("Y".to_string(), Value::Byte(y));
+            }
+            items.push(section);
+        }
+        map.insert("sections".to_string(), Value::List(items));
+    }
+    fastnbt::to_bytes(&root).map_err(|e| anyhow::anyhow!("Failed to encode NBT: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastnbt::LongArray;
+    use std::collections::HashMap;
+
+    fn compound(entries: Vec<(&str, Value)>) -> Value {
+        Value::Compound(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    fn section(y: i8, block: &str) -> Value {
+        compound(vec![
+            ("Y", Value::Byte(y)),
+            ("block_states", compound(vec![
+                ("palette", Value::List(vec![compound(vec![("Name", Value::String(block.into()))])])),
+                ("data", Value::LongArray(LongArray::new(vec![0; 64]))),
+            ])),
+        ])
+    }
+
+    #[test]
+    fn test_hash_ignores_key_order() {
+        let mut a = HashMap::new();
+        let mut b = HashMap::new();
+        for i in 0..32 {
+            a.insert(format!("k{}", i), Value::Int(i));
+        }
+        for i in (0..32).rev() {
+            b.insert(format!("k{}", i), Value::Int(i));
+        }
+        let a = section_hash(&Value::Compound(a));
+        assert_eq!(a, section_hash(&Value::Compound(b.clone())));
+        // Tag types count, not just numeric values
+        b.insert("k0".into(), Value::Long(0));
+        assert_ne!(a, section_hash(&Value::Compound(b)));
+    }
+
+    #[test]
+    fn test_split_and_join_roundtrip() {
+        let chunk = compound(vec![
+            ("xPos", Value::Int(1)),
+            ("sections", Value::List(vec![section(0, "minecraft:stone"), section(1, "minecraft:air"), section(2, "minecraft:air")])),
+        ]);
+        let split = split_sections(&fastnbt::to_bytes(&chunk).unwrap()).unwrap();
+        let sections = split.sections.as_ref().unwrap();
+        assert_eq!(sections.len(), 3);
+        // Same content at different heights shares a hash
+        assert_ne!(sections[0].hash, sections[1].hash);
+        assert_eq!(sections[1].hash, sections[2].hash);
+        assert_eq!(sections.iter().map(|s| s.y).collect::<Vec<_>>(), vec![Some(0), Some(1), Some(2)]);
+
+        let joined = join_sections(&split.body, Some(sections.iter().map(|s| (s.y, s.data.as_slice())).collect())).unwrap();
+        assert_eq!(fastnbt::from_bytes::<Value>(&joined).unwrap(), chunk);
+    }
+}
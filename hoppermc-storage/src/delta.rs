@@ -0,0 +1,282 @@
+// Chunk deltas against generated terrain (weightless storage).
+//
+// A delta is a patch tree over the chunk NBT. At every compound or list it holds
+// - `set`: entries whose new value is stored whole (keys, or list indices as strings),
+// - `sub`: entries patched recursively (both sides have the same shape),
+// - `del`: removed compound keys, `len`: the new length of a resized list,
+// and for same-length numeric arrays (packed block states, light) only the changed
+// slots: `at` plus `byte` / `int` / `long`. The patch kind is implied by the base
+// value it applies to, so the tree itself carries no type tags.
+//
+// An unchanged chunk has no delta at all; the stored patch is NBT, zlib-compressed.
+
+use crate::hybrid::{compress, decompress};
+use anyhow::{Context, Result};
+use fastnbt::{ByteArray, IntArray, LongArray, Value};
+use std::collections::HashMap;
+
+/// Diff `chunk` against the generated `base` (both raw NBT).
+/// Returns None when they are identical, otherwise the compressed delta.
+pub fn diff_chunk(base: &[u8], chunk: &[u8]) -> Result<Option<Vec<u8>>> {
+    let base: Value = fastnbt::from_bytes(base).map_err(|e| anyhow::anyhow!("Failed to parse base NBT: {:?}", e))?;
+    let chunk: Value = fastnbt::from_bytes(chunk).map_err(|e| anyhow::anyhow!("Failed to parse chunk NBT: {:?}", e))?;
+    if base == chunk {
+        return Ok(None);
+    }
+    let (Value::Compound(_), Value::Compound(_)) = (&base, &chunk) else { anyhow::bail!("NBT root is not a compound") };
+    let patch = diff_value(&base, &chunk).expect("compounds always have a patch");
+    let nbt = fastnbt::to_bytes(&patch).map_err(|e| anyhow::anyhow!("Failed to encode delta: {:?}", e))?;
+    Ok(Some(compress(&nbt)?))
+}
+
+/// Rebuild raw chunk NBT from the generated `base` and a delta from `diff_chunk`.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut root: Value = fastnbt::from_bytes(base).map_err(|e| anyhow::anyhow!("Failed to parse base NBT: {:?}", e))?;
+    let patch: Value = fastnbt::from_bytes(&decompress(delta)?).map_err(|e| anyhow::anyhow!("Failed to parse delta: {:?}", e))?;
+    apply_patch(&mut root, &patch)?;
+    fastnbt::to_bytes(&root).map_err(|e| anyhow::anyhow!("Failed to encode NBT: {:?}", e))
+}
+
+fn patch_of(entries: Vec<(&str, Value)>) -> Value {
+    Value::Compound(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn field<'a>(patch: &'a Value, key: &str) -> Option<&'a Value> {
+    match patch {
+        Value::Compound(map) => map.get(key),
+        _ => None,
+    }
+}
+
+/// Patch turning `base` into `new`, or None if `new` has to be stored whole.
+/// Only called for values that differ.
+fn diff_value(base: &Value, new: &Value) -> Option<Value> {
+    match (base, new) {
+        (Value::Compound(old), Value::Compound(new)) => {
+            let mut set = HashMap::new();
+            let mut sub = HashMap::new();
+            for (key, value) in new {
+                match old.get(key) {
+                    Some(prev) if prev == value => {}
+                    Some(prev) => match diff_value(prev, value) {
+                        Some(patch) => {
+                            sub.insert(key.clone(), patch);
+                        }
+                        None => {
+                            set.insert(key.clone(), value.clone());
+                        }
+                    },
+                    None => {
+                        set.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            let del: Vec<Value> = old.keys().filter(|k| !new.contains_key(*k)).map(|k| Value::String(k.clone())).collect();
+            Some(container_patch(set, sub, (!del.is_empty()).then_some(("del", Value::List(del)))))
+        }
+        (Value::List(old), Value::List(new)) => {
+            let mut set = HashMap::new();
+            let mut sub = HashMap::new();
+            for (i, value) in new.iter().enumerate() {
+                match old.get(i) {
+                    Some(prev) if prev == value => {}
+                    Some(prev) => match diff_value(prev, value) {
+                        Some(patch) => {
+                            sub.insert(i.to_string(), patch);
+                        }
+                        None => {
+                            set.insert(i.to_string(), value.clone());
+                        }
+                    },
+                    None => {
+                        set.insert(i.to_string(), value.clone());
+                    }
+                }
+            }
+            let len = (old.len() != new.len()).then_some(("len", Value::Int(new.len() as i32)));
+            Some(container_patch(set, sub, len))
+        }
+        (Value::ByteArray(old), Value::ByteArray(new)) => {
+            sparse(old, new).map(|(at, values)| patch_of(vec![("at", at), ("byte", Value::ByteArray(ByteArray::new(values)))]))
+        }
+        (Value::IntArray(old), Value::IntArray(new)) => {
+            sparse(old, new).map(|(at, values)| patch_of(vec![("at", at), ("int", Value::IntArray(IntArray::new(values)))]))
+        }
+        (Value::LongArray(old), Value::LongArray(new)) => {
+            sparse(old, new).map(|(at, values)| patch_of(vec![("at", at), ("long", Value::LongArray(LongArray::new(values)))]))
+        }
+        _ => None,
+    }
+}
+
+fn container_patch(set: HashMap<String, Value>, sub: HashMap<String, Value>, extra: Option<(&str, Value)>) -> Value {
+    let mut entries = Vec::new();
+    if !set.is_empty() {
+        entries.push(("set", Value::Compound(set)));
+    }
+    if !sub.is_empty() {
+        entries.push(("sub", Value::Compound(sub)));
+    }
+    entries.extend(extra);
+    patch_of(entries)
+}
+
+/// Changed slots of two same-length arrays, if storing them beats storing the array.
+fn sparse<T: Copy + PartialEq>(old: &[T], new: &[T]) -> Option<(Value, Vec<T>)> {
+    if old.len() != new.len() {
+        return None;
+    }
+    let changed: Vec<usize> = (0..new.len()).filter(|&i| old[i] != new[i]).collect();
+    // Each slot costs an index on top of the value
+    if changed.len() * 2 >= new.len() {
+        return None;
+    }
+    let values = changed.iter().map(|&i| new[i]).collect();
+    Some((Value::IntArray(IntArray::new(changed.into_iter().map(|i| i as i32).collect())), values))
+}
+
+fn apply_sparse<T: Copy>(array: &mut [T], at: Option<&Value>, values: &[T]) -> Result<()> {
+    let Some(Value::IntArray(at)) = at else { anyhow::bail!("Array patch without indices") };
+    anyhow::ensure!(at.len() == values.len(), "Array patch has {} indices for {} values", at.len(), values.len());
+    for (&i, &value) in at.iter().zip(values) {
+        *array.get_mut(i as usize).context("Array patch index out of range")? = value;
+    }
+    Ok(())
+}
+
+fn apply_patch(target: &mut Value, patch: &Value) -> Result<()> {
+    let set = match field(patch, "set") {
+        Some(Value::Compound(set)) => Some(set),
+        _ => None,
+    };
+    let sub = match field(patch, "sub") {
+        Some(Value::Compound(sub)) => Some(sub),
+        _ => None,
+    };
+    match target {
+        Value::Compound(map) => {
+            if let Some(Value::List(del)) = field(patch, "del") {
+                for key in del.iter().filter_map(|k| k.as_str()) {
+                    map.remove(key);
+                }
+            }
+            for (key, value) in set.into_iter().flatten() {
+                map.insert(key.clone(), value.clone());
+            }
+            for (key, child) in sub.into_iter().flatten() {
+                let node = map.get_mut(key).with_context(|| format!("Delta patches missing tag '{}'", key))?;
+                apply_patch(node, child)?;
+            }
+        }
+        Value::List(items) => {
+            if let Some(len) = field(patch, "len").and_then(|v| v.as_i64()) {
+                // New slots are all covered by `set`
+                items.resize(len as usize, Value::Byte(0));
+            }
+            for (index, value) in set.into_iter().flatten() {
+                let i: usize = index.parse().context("Bad list index in delta")?;
+                *items.get_mut(i).context("Delta list index out of range")? = value.clone();
+            }
+            for (index, child) in sub.into_iter().flatten() {
+                let i: usize = index.parse().context("Bad list index in delta")?;
+                apply_patch(items.get_mut(i).context("Delta list index out of range")?, child)?;
+            }
+        }
+        Value::ByteArray(array) => {
+            let Some(Value::ByteArray(values)) = field(patch, "byte") else { anyhow::bail!("Byte array patch without values") };
+            let mut data = std::mem::replace(array, ByteArray::new(Vec::new())).into_inner();
+            apply_sparse(&mut data, field(patch, "at"), values)?;
+            *array = ByteArray::new(data);
+        }
+        Value::IntArray(array) => {
+            let Some(Value::IntArray(values)) = field(patch, "int") else { anyhow::bail!("Int array patch without values") };
+            let mut data = std::mem::replace(array, IntArray::new(Vec::new())).into_inner();
+            apply_sparse(&mut data, field(patch, "at"), values)?;
+            *array = IntArray::new(data);
+        }
+        Value::LongArray(array) => {
+            let Some(Value::LongArray(values)) = field(patch, "long") else { anyhow::bail!("Long array patch without values") };
+            let mut data = std::mem::replace(array, LongArray::new(Vec::new())).into_inner();
+            apply_sparse(&mut data, field(patch, "at"), values)?;
+            *array = LongArray::new(data);
+        }
+        _ => anyhow::bail!("Delta patches a scalar tag"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(entries: Vec<(&str, Value)>) -> Value {
+        Value::Compound(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    fn generated() -> Value {
+        let section = compound(vec![
+            ("Y", Value::Byte(0)),
+            ("block_states", compound(vec![
+                ("palette", Value::List(vec![compound(vec![("Name", Value::String("minecraft:stone".into()))])])),
+                ("data", Value::LongArray(LongArray::new(vec![0; 256]))),
+            ])),
+        ]);
+        compound(vec![
+            ("DataVersion", Value::Int(4189)),
+            ("Status", Value::String("minecraft:full".into())),
+            ("InhabitedTime", Value::Long(0)),
+            ("sections", Value::List(vec![section])),
+            ("block_entities", Value::List(Vec::new())),
+        ])
+    }
+
+    fn roundtrip(base: &Value, edited: &Value) -> (usize, Value) {
+        let base = fastnbt::to_bytes(base).unwrap();
+        let delta = diff_chunk(&base, &fastnbt::to_bytes(edited).unwrap()).unwrap().unwrap();
+        let rebuilt = fastnbt::from_bytes(&apply_delta(&base, &delta).unwrap()).unwrap();
+        (delta.len(), rebuilt)
+    }
+
+    #[test]
+    fn test_unchanged_chunk_has_no_delta() {
+        let nbt = fastnbt::to_bytes(&generated()).unwrap();
+        assert!(diff_chunk(&nbt, &nbt).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_edits_roundtrip() {
+        let mut edited = generated();
+        let Value::Compound(root) = &mut edited else { unreachable!() };
+        root.insert("InhabitedTime".into(), Value::Long(4000));
+        root.remove("Status");
+        root.insert("block_entities".into(), Value::List(vec![compound(vec![("id", Value::String("minecraft:chest".into()))])]));
+        let Some(Value::List(sections)) = root.get_mut("sections") else { unreachable!() };
+        let Value::Compound(section) = &mut sections[0] else { unreachable!() };
+        let Some(Value::Compound(states)) = section.get_mut("block_states") else { unreachable!() };
+        let Some(Value::List(palette)) = states.get_mut("palette") else { unreachable!() };
+        palette.push(compound(vec![("Name", Value::String("minecraft:air".into()))]));
+        let mut data = vec![0; 256];
+        data[17] = 1;
+        states.insert("data".into(), Value::LongArray(LongArray::new(data)));
+
+        let (_, rebuilt) = roundtrip(&generated(), &edited);
+        assert_eq!(rebuilt, edited);
+    }
+
+    #[test]
+    fn test_single_block_edit_stays_small() {
+        let mut edited = generated();
+        let Value::Compound(root) = &mut edited else { unreachable!() };
+        let Some(Value::List(sections)) = root.get_mut("sections") else { unreachable!() };
+        let Value::Compound(section) = &mut sections[0] else { unreachable!() };
+        let Some(Value::Compound(states)) = section.get_mut("block_states") else { unreachable!() };
+        let mut data = vec![0; 256];
+        data[200] = 7;
+        states.insert("data".into(), Value::LongArray(LongArray::new(data)));
+
+        let (delta_len, rebuilt) = roundtrip(&generated(), &edited);
+        assert_eq!(rebuilt, edited);
+        // Well below the 2 KiB of the full array
+        assert!(delta_len < 256, "delta is {} bytes", delta_len);
+    }
+}
@@ -0,0 +1,138 @@
+// Chunk version history for the Postgres backends.
+//
+// With history enabled every save also appends the raw chunk (zlib-compressed NBT)
+// to `chunk_history`, independent of the storage mode, so a griefed or corrupted
+// chunk can be put back. Retention is applied to the saved chunk's versions on every
+// save, and to the whole table by `prune`. Versions are kept per world and dimension,
+// like the chunks. Table: migrations/history/0001_chunk_history.sql.
+
+use crate::hybrid::{compress, decompress};
+use crate::worlds::WorldKey;
+use anyhow::{Context, Result};
+use std::time::SystemTime;
+
+/// Which versions to keep. Each limit applies when set; with neither, history grows forever.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HistoryConfig {
+    /// Keep only the newest N versions per chunk.
+    pub keep_last: Option<u32>,
+    /// Drop versions older than this many days.
+    pub keep_days: Option<u32>,
+}
+
+/// One stored version of a chunk.
+#[derive(Debug, Clone)]
+pub struct ChunkVersion {
+    pub id: i64,
+    pub x: i32,
+    pub z: i32,
+    pub saved_at: SystemTime,
+    /// Compressed size in bytes.
+    pub size: i64,
+}
+
+/// Outcome of an area rollback.
+#[derive(Debug, Clone, Default)]
+pub struct RollbackReport {
+    /// Chunks put back to their last version at or before the timestamp.
+    pub restored: Vec<(i32, i32)>,
+    /// Chunks with history but no version that old (created later, or pruned); left as they are.
+    pub skipped: Vec<(i32, i32)>,
+}
+
+/// Append a version for each saved chunk, then apply retention to those chunks. Runs in the
+/// save's transaction, so history never disagrees with what was committed.
+pub(crate) async fn record(tx: &deadpool_postgres::Transaction<'_>, config: &HistoryConfig, world: &WorldKey, chunks: &[(i32, i32, &[u8])]) -> Result<()> {
+    let xs: Vec<i32> = chunks.iter().map(|(x, _, _)| *x).collect();
+    let zs: Vec<i32> = chunks.iter().map(|(_, z, _)| *z).collect();
+    let data = chunks.iter().map(|(_, _, data)| compress(data)).collect::<Result<Vec<_>>>()?;
+    tx.execute(
+        "INSERT INTO chunk_history (world_id, dimension, x, z, data)
+         SELECT $1, $2, x, z, data FROM unnest($3::int[], $4::int[], $5::bytea[]) AS t(x, z, data)",
+        &[&world.world_id, &world.dimension, &xs, &zs, &data],
+    ).await.context("Failed to record chunk history")?;
+
+    if let Some(keep) = config.keep_last {
+        let keep = keep as i64;
+        tx.execute(
+            "DELETE FROM chunk_history WHERE id IN (
+                 SELECT id FROM (
+                     SELECT id, ROW_NUMBER() OVER (PARTITION BY x, z ORDER BY id DESC) AS n FROM chunk_history
+                     WHERE world_id = $1 AND dimension = $2 AND (x, z) IN (SELECT * FROM unnest($3::int[], $4::int[]))
+                 ) ranked WHERE n > $5)",
+            &[&world.world_id, &world.dimension, &xs, &zs, &keep],
+        ).await.context("Failed to prune chunk history")?;
+    }
+    if let Some(days) = config.keep_days {
+        let days = days as i32;
+        tx.execute(
+            "DELETE FROM chunk_history
+             WHERE world_id = $1 AND dimension = $2 AND (x, z) IN (SELECT * FROM unnest($3::int[], $4::int[]))
+               AND saved_at < NOW() - make_interval(days => $5)",
+            &[&world.world_id, &world.dimension, &xs, &zs, &days],
+        ).await.context("Failed to prune chunk history")?;
+    }
+    Ok(())
+}
+
+/// Apply retention to every chunk of every world. Returns the number of versions removed.
+pub(crate) async fn prune(client: &deadpool_postgres::Client, config: &HistoryConfig) -> Result<u64> {
+    let mut removed = 0;
+    if let Some(keep) = config.keep_last {
+        let keep = keep as i64;
+        removed += client.execute(
+            "DELETE FROM chunk_history WHERE id IN (
+                 SELECT id FROM (
+                     SELECT id, ROW_NUMBER() OVER (PARTITION BY world_id, dimension, x, z ORDER BY id DESC) AS n FROM chunk_history
+                 ) ranked WHERE n > $1)",
+            &[&keep],
+        ).await.context("Failed to prune chunk history")?;
+    }
+    if let Some(days) = config.keep_days {
+        let days = days as i32;
+        removed += client.execute("DELETE FROM chunk_history WHERE saved_at < NOW() - make_interval(days => $1)", &[&days])
+            .await.context("Failed to prune chunk history")?;
+    }
+    Ok(removed)
+}
+
+pub(crate) async fn list(client: &deadpool_postgres::Client, world: &WorldKey, x: i32, z: i32) -> Result<Vec<ChunkVersion>> {
+    let rows = client.query(
+        "SELECT id, saved_at, octet_length(data)::BIGINT FROM chunk_history
+         WHERE world_id = $1 AND dimension = $2 AND x = $3 AND z = $4 ORDER BY saved_at DESC, id DESC",
+        &[&world.world_id, &world.dimension, &x, &z],
+    ).await.context("Failed to list chunk history")?;
+    Ok(rows.iter().map(|row| ChunkVersion { id: row.get(0), x, z, saved_at: row.get(1), size: row.get(2) }).collect())
+}
+
+/// Raw NBT of version `id` of chunk (x, z), if it exists.
+pub(crate) async fn version_data(client: &deadpool_postgres::Client, world: &WorldKey, x: i32, z: i32, id: i64) -> Result<Option<Vec<u8>>> {
+    let row = client.query_opt(
+        "SELECT data FROM chunk_history WHERE id = $1 AND world_id = $2 AND dimension = $3 AND x = $4 AND z = $5",
+        &[&id, &world.world_id, &world.dimension, &x, &z],
+    ).await.context("Failed to read chunk history")?;
+    row.map(|row| decompress(row.get(0))).transpose()
+}
+
+/// For every chunk with history in the area: its last version id at or before `at`, if any.
+pub(crate) async fn versions_at(
+    client: &deadpool_postgres::Client,
+    world: &WorldKey,
+    (min_x, min_z): (i32, i32),
+    (max_x, max_z): (i32, i32),
+    at: SystemTime,
+) -> Result<Vec<(i32, i32, Option<i64>)>> {
+    let rows = client.query(
+        "SELECT c.x, c.z, v.id
+         FROM (SELECT DISTINCT x, z FROM chunk_history
+               WHERE world_id = $1 AND dimension = $2 AND x BETWEEN $3 AND $4 AND z BETWEEN $5 AND $6) c
+         LEFT JOIN LATERAL (
+             SELECT id FROM chunk_history h
+             WHERE h.world_id = $1 AND h.dimension = $2 AND h.x = c.x AND h.z = c.z AND h.saved_at <= $7
+             ORDER BY h.saved_at DESC, h.id DESC LIMIT 1
+         ) v ON TRUE
+         ORDER BY c.x, c.z",
+        &[&world.world_id, &world.dimension, &min_x, &max_x, &min_z, &max_z, &at],
+    ).await.context("Failed to read chunk history")?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect())
+}
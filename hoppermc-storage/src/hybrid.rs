@@ -0,0 +1,349 @@
+// Hybrid chunk layout: structured columns + binary payload.
+//
+// A chunk is split into
+// - `arrays`: every large numeric array (block states, biome data, light, heightmaps),
+//   addressed by its path in the tree, packed and zlib-compressed;
+// - `slim`: the rest of the tree (palettes, block entities, entities, small tags) as
+//   compressed NBT, so loading restores exact tag types;
+// - typed summary columns (DataVersion, Status, InhabitedTime, palettes, ...) and a JSONB
+//   copy of the slim tree for SQL queries. These are a projection: loads never read them.
+// Together that is roughly MCA-sized while staying queryable like `pg_jsonb`.
+
+use anyhow::{Context, Result};
+use fastnbt::{ByteArray, IntArray, LongArray, Value};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Write};
+
+// Arrays at least this long count as heavy numeric data (UUIDs and the like stay inline)
+const HEAVY_ARRAY_LEN: usize = 64;
+
+const KIND_BYTE: u8 = 7;
+const KIND_INT: u8 = 11;
+const KIND_LONG: u8 = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A chunk split for the `chunks_hybrid` table.
+#[derive(Debug, Clone)]
+pub struct HybridChunk {
+    pub data_version: Option<i32>,
+    pub status: Option<String>,
+    pub inhabited_time: Option<i64>,
+    pub last_update: Option<i64>,
+    /// Distinct block state names over all sections.
+    pub block_palette: Vec<String>,
+    /// Distinct biome names over all sections.
+    pub biome_palette: Vec<String>,
+    /// Block entity ids (one per block entity).
+    pub block_entities: Vec<String>,
+    /// Entity ids (proto-chunks only; full chunks keep entities in entity region files).
+    pub entities: Vec<String>,
+    /// Slim tree as JSON, for queries.
+    pub meta: serde_json::Value,
+    /// Slim tree as compressed NBT.
+    pub slim: Vec<u8>,
+    /// Heavy arrays, packed and compressed.
+    pub arrays: Vec<u8>,
+}
+
+pub(crate) fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Remove heavy arrays from `value` (recursively), recording each with its path.
+fn extract_arrays(value: &mut Value, path: &mut Vec<Segment>, out: &mut Vec<(Vec<Segment>, Value)>) {
+    match value {
+        Value::Compound(map) => {
+            let heavy: Vec<String> = map
+                .iter()
+                .filter(|(_, v)| match v {
+                    Value::ByteArray(a) => a.len() >= HEAVY_ARRAY_LEN,
+                    Value::IntArray(a) => a.len() >= HEAVY_ARRAY_LEN,
+                    Value::LongArray(a) => a.len() >= HEAVY_ARRAY_LEN,
+                    _ => false,
+                })
+                .map(|(k, _)| k.clone())
+                .collect();
+            for key in heavy {
+                let array = map.remove(&key).unwrap();
+                let mut array_path = path.clone();
+                array_path.push(Segment::Key(key));
+                out.push((array_path, array));
+            }
+            for (key, child) in map.iter_mut() {
+                path.push(Segment::Key(key.clone()));
+                extract_arrays(child, path, out);
+                path.pop();
+            }
+        }
+        // Arrays directly inside lists stay put: removing them would shift the indices
+        Value::List(items) => {
+            for (i, child) in items.iter_mut().enumerate() {
+                path.push(Segment::Index(i));
+                extract_arrays(child, path, out);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn insert_array(root: &mut Value, path: &[Segment], array: Value) -> Result<()> {
+    let (last, parents) = path.split_last().context("Empty array path")?;
+    let mut node = root;
+    for segment in parents {
+        node = match (node, segment) {
+            (Value::Compound(map), Segment::Key(key)) => map.get_mut(key),
+            (Value::List(items), Segment::Index(i)) => items.get_mut(*i),
+            _ => None,
+        }
+        .with_context(|| format!("Array path {:?} does not match the tree", path))?;
+    }
+    match (node, last) {
+        (Value::Compound(map), Segment::Key(key)) => {
+            map.insert(key.clone(), array);
+            Ok(())
+        }
+        _ => anyhow::bail!("Array path {:?} does not end in a compound", path),
+    }
+}
+
+fn pack_arrays(arrays: &[(Vec<Segment>, Value)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(arrays.len() as u32).to_le_bytes());
+    for (path, array) in arrays {
+        out.push(path.len() as u8);
+        for segment in path {
+            match segment {
+                Segment::Key(key) => {
+                    out.push(0);
+                    out.extend_from_slice(&(key.len() as u16).to_le_bytes());
+                    out.extend_from_slice(key.as_bytes());
+                }
+                Segment::Index(i) => {
+                    out.push(1);
+                    out.extend_from_slice(&(*i as u32).to_le_bytes());
+                }
+            }
+        }
+        match array {
+            Value::ByteArray(a) => {
+                out.push(KIND_BYTE);
+                out.extend_from_slice(&(a.len() as u32).to_le_bytes());
+                out.extend(a.iter().map(|b| *b as u8));
+            }
+            Value::IntArray(a) => {
+                out.push(KIND_INT);
+                out.extend_from_slice(&(a.len() as u32).to_le_bytes());
+                a.iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+            }
+            Value::LongArray(a) => {
+                out.push(KIND_LONG);
+                out.extend_from_slice(&(a.len() as u32).to_le_bytes());
+                a.iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+            }
+            _ => unreachable!("only arrays are extracted"),
+        }
+    }
+    out
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let slice = self.data.get(self.pos..self.pos + n).context("Hybrid array payload truncated")?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+fn unpack_arrays(data: &[u8]) -> Result<Vec<(Vec<Segment>, Value)>> {
+    let mut r = Reader { data, pos: 0 };
+    let count = r.u32()?;
+    let mut arrays = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let depth = r.u8()?;
+        let mut path = Vec::with_capacity(depth as usize);
+        for _ in 0..depth {
+            path.push(match r.u8()? {
+                0 => {
+                    let len = r.u16()? as usize;
+                    Segment::Key(String::from_utf8(r.take(len)?.to_vec())?)
+                }
+                _ => Segment::Index(r.u32()? as usize),
+            });
+        }
+        let kind = r.u8()?;
+        let len = r.u32()? as usize;
+        let array = match kind {
+            KIND_BYTE => Value::ByteArray(ByteArray::new(r.take(len)?.iter().map(|b| *b as i8).collect())),
+            KIND_INT => Value::IntArray(IntArray::new(r.take(len * 4)?.chunks_exact(4).map(|b| i32::from_le_bytes(b.try_into().unwrap())).collect())),
+            KIND_LONG => Value::LongArray(LongArray::new(r.take(len * 8)?.chunks_exact(8).map(|b| i64::from_le_bytes(b.try_into().unwrap())).collect())),
+            other => anyhow::bail!("Unknown array kind {} in hybrid payload", other),
+        };
+        arrays.push((path, array));
+    }
+    Ok(arrays)
+}
+
+fn list_of<'a>(root: &'a HashMap<String, Value>, key: &str) -> impl Iterator<Item = &'a HashMap<String, Value>> {
+    let items = match root.get(key) {
+        Some(Value::List(items)) => items.as_slice(),
+        _ => &[],
+    };
+    items.iter().filter_map(|v| match v {
+        Value::Compound(c) => Some(c),
+        _ => None,
+    })
+}
+
+fn string_at<'a>(map: &'a HashMap<String, Value>, key: &str) -> Option<&'a str> {
+    map.get(key).and_then(|v| v.as_str())
+}
+
+/// Split raw chunk NBT into its hybrid form.
+pub fn split_chunk(nbt: &[u8]) -> Result<HybridChunk> {
+    let mut root: Value = fastnbt::from_bytes(nbt).map_err(|e| anyhow::anyhow!("Failed to parse NBT: {:?}", e))?;
+    let Value::Compound(map) = &root else { anyhow::bail!("NBT root is not a compound") };
+
+    let mut block_palette = BTreeSet::new();
+    let mut biome_palette = BTreeSet::new();
+    for section in list_of(map, "sections") {
+        if let Some(Value::Compound(states)) = section.get("block_states") {
+            block_palette.extend(list_of(states, "palette").filter_map(|entry| string_at(entry, "Name")).map(str::to_string));
+        }
+        if let Some(Value::Compound(biomes)) = section.get("biomes")
+            && let Some(Value::List(palette)) = biomes.get("palette")
+        {
+            biome_palette.extend(palette.iter().filter_map(|b| b.as_str()).map(str::to_string));
+        }
+    }
+
+    let chunk = HybridChunk {
+        data_version: map.get("DataVersion").and_then(|v| v.as_i64()).map(|v| v as i32),
+        status: string_at(map, "Status").map(str::to_string),
+        inhabited_time: map.get("InhabitedTime").and_then(|v| v.as_i64()),
+        last_update: map.get("LastUpdate").and_then(|v| v.as_i64()),
+        block_palette: block_palette.into_iter().collect(),
+        biome_palette: biome_palette.into_iter().collect(),
+        block_entities: list_of(map, "block_entities").filter_map(|e| string_at(e, "id")).map(str::to_string).collect(),
+        entities: list_of(map, "entities").filter_map(|e| string_at(e, "id")).map(str::to_string).collect(),
+        meta: serde_json::Value::Null,
+        slim: Vec::new(),
+        arrays: Vec::new(),
+    };
+
+    let mut arrays = Vec::new();
+    extract_arrays(&mut root, &mut Vec::new(), &mut arrays);
+    let slim_nbt = fastnbt::to_bytes(&root).map_err(|e| anyhow::anyhow!("Failed to encode slim NBT: {:?}", e))?;
+    Ok(HybridChunk {
+        slim: compress(&slim_nbt)?,
+        arrays: compress(&pack_arrays(&arrays))?,
+        meta: crate::nbt_json::nbt_to_json(root),
+        ..chunk
+    })
+}
+
+/// Rebuild raw chunk NBT from the `slim` and `arrays` columns.
+pub fn merge_chunk(slim: &[u8], arrays: &[u8]) -> Result<Vec<u8>> {
+    let mut root: Value = fastnbt::from_bytes(&decompress(slim)?).map_err(|e| anyhow::anyhow!("Failed to parse slim NBT: {:?}", e))?;
+    for (path, array) in unpack_arrays(&decompress(arrays)?)? {
+        insert_array(&mut root, &path, array)?;
+    }
+    fastnbt::to_bytes(&root).map_err(|e| anyhow::anyhow!("Failed to encode NBT: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(entries: Vec<(&str, Value)>) -> Value {
+        Value::Compound(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    fn sample_chunk() -> Value {
+        let section = compound(vec![
+            ("Y", Value::Byte(-4)),
+            ("block_states", compound(vec![
+                ("palette", Value::List(vec![
+                    compound(vec![("Name", Value::String("minecraft:stone".into()))]),
+                    compound(vec![("Name", Value::String("minecraft:diamond_ore".into()))]),
+                ])),
+                ("data", Value::LongArray(LongArray::new((0..256).collect()))),
+            ])),
+            ("biomes", compound(vec![("palette", Value::List(vec![Value::String("minecraft:plains".into())]))])),
+            ("SkyLight", Value::ByteArray(ByteArray::new(vec![-1; 2048]))),
+        ]);
+        compound(vec![
+            ("DataVersion", Value::Int(4189)),
+            ("Status", Value::String("minecraft:full".into())),
+            ("InhabitedTime", Value::Long(1200)),
+            ("xPos", Value::Int(3)),
+            ("sections", Value::List(vec![section])),
+            ("block_entities", Value::List(vec![compound(vec![
+                ("id", Value::String("minecraft:chest".into())),
+                // Short array: stays inline
+                ("UUID", Value::IntArray(IntArray::new(vec![1, 2, 3, 4]))),
+            ])])),
+        ])
+    }
+
+    #[test]
+    fn test_split_extracts_summary_and_arrays() {
+        let nbt = fastnbt::to_bytes(&sample_chunk()).unwrap();
+        let chunk = split_chunk(&nbt).unwrap();
+
+        assert_eq!(chunk.data_version, Some(4189));
+        assert_eq!(chunk.status.as_deref(), Some("minecraft:full"));
+        assert_eq!(chunk.inhabited_time, Some(1200));
+        assert_eq!(chunk.block_palette, vec!["minecraft:diamond_ore", "minecraft:stone"]);
+        assert_eq!(chunk.biome_palette, vec!["minecraft:plains"]);
+        assert_eq!(chunk.block_entities, vec!["minecraft:chest"]);
+
+        // The JSON projection keeps palettes but not the heavy arrays
+        let section = &chunk.meta["sections"][0];
+        assert!(section["block_states"]["palette"].is_array());
+        assert!(section["block_states"].get("data").is_none());
+        assert!(section.get("SkyLight").is_none());
+        assert!(chunk.meta["block_entities"][0].get("UUID").is_some());
+    }
+
+    #[test]
+    fn test_merge_restores_exact_chunk() {
+        let original = sample_chunk();
+        let chunk = split_chunk(&fastnbt::to_bytes(&original).unwrap()).unwrap();
+        let merged: Value = fastnbt::from_bytes(&merge_chunk(&chunk.slim, &chunk.arrays).unwrap()).unwrap();
+        assert_eq!(merged, original);
+    }
+}
@@ -1,17 +1,69 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 
+pub mod anvil;
+pub mod breaker;
+pub mod dedup;
+pub mod delta;
+pub mod history;
+pub mod hybrid;
+pub mod memory;
+pub mod migrations;
 pub mod nbt_json;
 pub mod postgres;
+pub mod redis;
+pub mod registry;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod sqlite;
+pub mod tls;
+pub mod worlds;
+pub mod zstd_dict;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StorageMode {
     PgRaw,          // Phase 1: Blob (formerly Raw)
     PgJsonb,        // Phase 2: Json
     Hybrid,         // Phase 3: Structured
-    Weightless      // Phase 4: Diffs
+    Weightless,     // Phase 4: Diffs
+    PgDedup         // Content-addressed sections
 }
 
+/// Chunk payload bytes before and after backend-side compression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub plain_bytes: u64,
+    pub stored_bytes: u64,
+}
+
+/// Connection pool usage since the backend was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub max_size: usize,
+    /// Successful connection checkouts.
+    pub acquires: u64,
+    /// Checkouts that gave up after the wait timeout.
+    pub timeouts: u64,
+    pub total_wait: std::time::Duration,
+    pub max_wait: std::time::Duration,
+    /// Connections in use right after each checkout, summed (divide by `acquires` for the average).
+    pub in_use_sum: u64,
+    pub peak_in_use: usize,
+}
+
+/// Base terrain that `Weightless` stores chunks as deltas against, supplied by the caller
+/// (usually an adapter over the world generator).
+pub trait BaseGenerator: Send + Sync {
+    /// NBT of the untouched chunk. Runs on a blocking thread and may block on `rt`.
+    fn generate_chunk(&self, x: i32, z: i32, rt: &tokio::runtime::Handle) -> Result<Vec<u8>>;
+    /// Identifies what `generate_chunk` produces; deltas recorded under another one are refused.
+    fn fingerprint(&self) -> String;
+}
+
+/// Coordinates of stored chunks, as produced by `ChunkStorage::list_chunks`.
+pub type ChunkKeyStream<'a> = BoxStream<'a, Result<(i32, i32)>>;
+
 #[async_trait]
 pub trait ChunkStorage: Send + Sync {
     /// Save a chunk to the storage backend.
@@ -21,5 +73,131 @@ pub trait ChunkStorage: Send + Sync {
     /// Load a chunk from storage.
     /// Returns None if the chunk does not exist in the DB.
     async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>>;
+
+    /// Load every stored chunk of a 32x32 region in one go.
+    /// Returns (x, z, raw NBT) with absolute chunk coordinates; missing chunks are simply absent.
+    /// The default falls back to one `load_chunk` per chunk; backends should override it
+    /// with a single range query.
+    async fn load_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        let mut chunks = Vec::new();
+        for x in region_x * 32..region_x * 32 + 32 {
+            for z in region_z * 32..region_z * 32 + 32 {
+                if let Some(data) = self.load_chunk(x, z).await? {
+                    chunks.push((x, z, data));
+                }
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Load several chunks at once. Returns (x, z, raw NBT) for the stored ones, in no particular order.
+    /// The default falls back to one `load_chunk` per chunk.
+    async fn load_chunks(&self, coords: &[(i32, i32)]) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        let mut chunks = Vec::new();
+        for &(x, z) in coords {
+            if let Some(data) = self.load_chunk(x, z).await? {
+                chunks.push((x, z, data));
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Save several chunks at once. If a chunk is listed twice, the last entry wins.
+    /// The default falls back to one `save_chunk` per chunk.
+    async fn save_chunks(&self, chunks: &[(i32, i32, Vec<u8>)]) -> Result<()> {
+        for (x, z, data) in chunks {
+            self.save_chunk(*x, *z, data).await?;
+        }
+        Ok(())
+    }
+
+    /// Bulk-load a large batch of chunks (imports, pregeneration). Same result as `save_chunks`;
+    /// backends with a faster bulk path override it.
+    async fn ingest_chunks(&self, chunks: &[(i32, i32, Vec<u8>)]) -> Result<()> {
+        self.save_chunks(chunks).await
+    }
+
+    /// Load every stored chunk in the inclusive rectangle `min..=max` (chunk coordinates).
+    /// The default loads each overlapping region with `load_region` and filters.
+    async fn load_range(&self, min: (i32, i32), max: (i32, i32)) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        let mut chunks = Vec::new();
+        for region_x in min.0.div_euclid(32)..=max.0.div_euclid(32) {
+            for region_z in min.1.div_euclid(32)..=max.1.div_euclid(32) {
+                let region = self.load_region(region_x, region_z).await?;
+                chunks.extend(region.into_iter().filter(|(x, z, _)| (min.0..=max.0).contains(x) && (min.1..=max.1).contains(z)));
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Whether a chunk is stored, without transferring it where the backend allows.
+    /// The default loads the chunk and discards it.
+    async fn exists(&self, x: i32, z: i32) -> Result<bool> {
+        Ok(self.load_chunk(x, z).await?.is_some())
+    }
+
+    /// Delete a chunk. Returns whether it was stored.
+    async fn delete_chunk(&self, x: i32, z: i32) -> Result<bool> {
+        anyhow::bail!("This storage backend cannot delete chunk ({}, {})", x, z)
+    }
+
+    /// Stream the coordinates of every stored chunk, optionally only inside the inclusive
+    /// rectangle `(min, max)`. Order is backend-specific.
+    fn list_chunks(&self, bounds: Option<((i32, i32), (i32, i32))>) -> ChunkKeyStream<'_> {
+        let _ = bounds;
+        Box::pin(futures::stream::once(async { Err(anyhow::anyhow!("This storage backend cannot list its chunks")) }))
+    }
+
     async fn get_total_size(&self) -> Result<u64> { Ok(0) }
+
+    /// Stored payload size against raw NBT size, for backends that compress payloads themselves.
+    async fn compression_stats(&self) -> Result<Option<CompressionStats>> { Ok(None) }
+
+    /// Connection pool usage, for backends with a pool.
+    fn pool_stats(&self) -> Option<PoolStats> { None }
+
+    /// Whether unmodified generator output has to be saved. Backends storing chunks as deltas
+    /// against the generator (`Weightless`) have nothing to store for it: a miss is generated again.
+    fn needs_generated_chunks(&self) -> bool { true }
+
+    /// Called once on shutdown, after buffered writes are flushed.
+    async fn close(&self) -> Result<()> { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct SparseStorage;
+
+    #[async_trait]
+    impl ChunkStorage for SparseStorage {
+        async fn save_chunk(&self, _x: i32, _z: i32, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+        async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+            // Only the diagonal is stored
+            Ok((x == z).then(|| vec![x as u8]))
+        }
+    }
+
+    #[test]
+    fn test_default_load_region_covers_region_bounds() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let chunks = rt.block_on(SparseStorage.load_region(-1, -1)).unwrap();
+        assert_eq!(chunks.len(), 32);
+        assert!(chunks.iter().all(|(x, z, _)| x == z && (-32..0).contains(x)));
+    }
+
+    #[test]
+    fn test_default_batch_and_range_loads() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let chunks = rt.block_on(SparseStorage.load_chunks(&[(1, 1), (1, 2), (-5, -5)])).unwrap();
+        assert_eq!(chunks.iter().map(|(x, z, _)| (*x, *z)).collect::<Vec<_>>(), vec![(1, 1), (-5, -5)]);
+
+        // Spans four regions; only the diagonal inside the rectangle comes back
+        let mut chunks = rt.block_on(SparseStorage.load_range((-3, -40), (40, 5))).unwrap();
+        chunks.sort();
+        assert_eq!(chunks.iter().map(|(x, _, _)| *x).collect::<Vec<_>>(), (-3..=5).collect::<Vec<_>>());
+    }
 }
@@ -0,0 +1,318 @@
+// In-memory chunk storage.
+//
+// Raw NBT in a concurrent map: fast, ephemeral worlds (minigames, tests) without a
+// database. An optional byte limit refuses saves once full (evicting would silently
+// lose player builds), and an optional snapshot file is loaded on start and written
+// on `close`, so a world can survive a planned restart.
+
+use crate::{ChunkKeyStream, ChunkStorage};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"HMCMEM01";
+// Magic plus chunk count
+const SNAPSHOT_HEADER_BYTES: u64 = 16;
+// x, z and length before each chunk's data
+const SNAPSHOT_ENTRY_BYTES: u64 = 12;
+// Entries reserved up front; a larger snapshot grows the vector as it is read
+const SNAPSHOT_MAX_PREALLOC: usize = 1 << 16;
+
+#[derive(Default)]
+struct MemoryState {
+    chunks: HashMap<(i32, i32), Vec<u8>>,
+    used_bytes: u64,
+}
+
+pub struct MemoryStorage {
+    state: RwLock<MemoryState>,
+    max_bytes: Option<u64>,
+    snapshot_path: Option<PathBuf>,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl MemoryStorage {
+    /// `max_bytes`: total raw NBT size limit (None = unbounded).
+    pub fn new(max_bytes: Option<u64>) -> Self {
+        Self { state: RwLock::new(MemoryState::default()), max_bytes, snapshot_path: None }
+    }
+
+    /// Load chunks from `path` if it exists, and write them back there on `close`.
+    /// Fails if the snapshot is corrupt or holds more than the size limit.
+    pub fn with_snapshot(mut self, path: &Path) -> Result<Self> {
+        if path.exists() {
+            let chunks = read_snapshot(path, self.max_bytes)?;
+            let mut state = self.state.write().unwrap();
+            for (x, z, data) in chunks {
+                state.used_bytes += data.len() as u64;
+                if let Some(old) = state.chunks.insert((x, z), data) {
+                    state.used_bytes -= old.len() as u64;
+                }
+            }
+            log::info!("Memory storage: restored {} chunks ({:.1} MB) from {:?}", state.chunks.len(), state.used_bytes as f64 / 1024.0 / 1024.0, path);
+        }
+        self.snapshot_path = Some(path.to_path_buf());
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.read().unwrap().chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.state.read().unwrap().used_bytes
+    }
+
+    /// Write every chunk to `path` (temp file + rename, so a crash keeps the old snapshot).
+    pub fn snapshot_to(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        {
+            let state = self.state.read().unwrap();
+            let file = std::fs::File::create(&tmp).with_context(|| format!("Failed to create snapshot {:?}", tmp))?;
+            let mut out = BufWriter::new(file);
+            out.write_all(SNAPSHOT_MAGIC)?;
+            out.write_all(&(state.chunks.len() as u64).to_le_bytes())?;
+            for ((x, z), data) in &state.chunks {
+                out.write_all(&x.to_le_bytes())?;
+                out.write_all(&z.to_le_bytes())?;
+                out.write_all(&(data.len() as u32).to_le_bytes())?;
+                out.write_all(data)?;
+            }
+            out.flush()?;
+            out.get_ref().sync_all()?;
+        }
+        std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace snapshot {:?}", path))?;
+        Ok(())
+    }
+}
+
+/// Read a snapshot, checking every count and length against the file size before
+/// allocating, and the total against `max_bytes`.
+fn read_snapshot(path: &Path, max_bytes: Option<u64>) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open snapshot {:?}", path))?;
+    let file_len = file.metadata()?.len();
+    let mut input = BufReader::new(file);
+
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != SNAPSHOT_MAGIC {
+        anyhow::bail!("{:?} is not a memory storage snapshot", path);
+    }
+
+    let mut u64_buf = [0u8; 8];
+    let mut u32_buf = [0u8; 4];
+    input.read_exact(&mut u64_buf)?;
+    let count = u64::from_le_bytes(u64_buf);
+    let mut remaining = file_len.saturating_sub(SNAPSHOT_HEADER_BYTES);
+    if count > remaining / SNAPSHOT_ENTRY_BYTES {
+        anyhow::bail!("Snapshot {:?} claims {} chunks but is only {} bytes", path, count, file_len);
+    }
+
+    let mut chunks = Vec::with_capacity((count as usize).min(SNAPSHOT_MAX_PREALLOC));
+    let mut total = 0u64;
+    for _ in 0..count {
+        input.read_exact(&mut u32_buf)?;
+        let x = i32::from_le_bytes(u32_buf);
+        input.read_exact(&mut u32_buf)?;
+        let z = i32::from_le_bytes(u32_buf);
+        input.read_exact(&mut u32_buf)?;
+        let len = u32::from_le_bytes(u32_buf) as u64;
+        remaining -= SNAPSHOT_ENTRY_BYTES;
+        if len > remaining {
+            anyhow::bail!("Snapshot {:?} truncated: chunk ({}, {}) claims {} bytes, {} left", path, x, z, len, remaining);
+        }
+        remaining -= len;
+        total += len;
+        if let Some(max) = max_bytes && total > max {
+            anyhow::bail!("Snapshot {:?} holds more than the memory storage limit ({} bytes)", path, max);
+        }
+        let mut data = vec![0u8; len as usize];
+        input.read_exact(&mut data).context("Snapshot truncated")?;
+        chunks.push((x, z, data));
+    }
+    Ok(chunks)
+}
+
+#[async_trait]
+impl ChunkStorage for MemoryStorage {
+    async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let old = state.chunks.get(&(x, z)).map(|d| d.len() as u64).unwrap_or(0);
+        let new_used = state.used_bytes - old + data.len() as u64;
+        if let Some(max) = self.max_bytes && new_used > max {
+            anyhow::bail!("Memory storage full ({} of {} bytes), refusing chunk ({}, {})", state.used_bytes, max, x, z);
+        }
+        state.chunks.insert((x, z), data.to_vec());
+        state.used_bytes = new_used;
+        Ok(())
+    }
+
+    async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+        Ok(self.state.read().unwrap().chunks.get(&(x, z)).cloned())
+    }
+
+    async fn load_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        let state = self.state.read().unwrap();
+        Ok(state
+            .chunks
+            .iter()
+            .filter(|((x, z), _)| x >> 5 == region_x && z >> 5 == region_z)
+            .map(|((x, z), data)| (*x, *z, data.clone()))
+            .collect())
+    }
+
+    async fn exists(&self, x: i32, z: i32) -> Result<bool> {
+        Ok(self.state.read().unwrap().chunks.contains_key(&(x, z)))
+    }
+
+    async fn delete_chunk(&self, x: i32, z: i32) -> Result<bool> {
+        let mut state = self.state.write().unwrap();
+        match state.chunks.remove(&(x, z)) {
+            Some(data) => {
+                state.used_bytes -= data.len() as u64;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn list_chunks(&self, bounds: Option<((i32, i32), (i32, i32))>) -> ChunkKeyStream<'_> {
+        let inside = |(x, z): &(i32, i32)| bounds.is_none_or(|(min, max)| (min.0..=max.0).contains(x) && (min.1..=max.1).contains(z));
+        let keys: Vec<Result<(i32, i32)>> = self.state.read().unwrap().chunks.keys().copied().filter(inside).map(Ok).collect();
+        Box::pin(futures::stream::iter(keys))
+    }
+
+    async fn get_total_size(&self) -> Result<u64> {
+        Ok(self.used_bytes())
+    }
+
+    async fn close(&self) -> Result<()> {
+        if let Some(path) = &self.snapshot_path {
+            self.snapshot_to(path)?;
+            log::info!("Memory storage: wrote {} chunks to {:?}", self.len(), path);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    #[test]
+    fn test_save_load_and_region() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = MemoryStorage::default();
+        rt.block_on(async {
+            storage.save_chunk(0, 0, &[1]).await.unwrap();
+            storage.save_chunk(31, -1, &[2]).await.unwrap();
+            storage.save_chunk(32, 0, &[3]).await.unwrap();
+            storage.save_chunk(0, 0, &[4, 4]).await.unwrap();
+
+            assert_eq!(storage.load_chunk(0, 0).await.unwrap(), Some(vec![4, 4]));
+            assert_eq!(storage.load_chunk(5, 5).await.unwrap(), None);
+            assert_eq!(storage.get_total_size().await.unwrap(), 4);
+
+            let region = storage.load_region(0, 0).await.unwrap();
+            assert_eq!(region, vec![(0, 0, vec![4, 4])]);
+        });
+    }
+
+    #[test]
+    fn test_exists_delete_and_list() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = MemoryStorage::default();
+        rt.block_on(async {
+            for (x, z) in [(0, 0), (1, 5), (-3, 2)] {
+                storage.save_chunk(x, z, &[1, 2]).await.unwrap();
+            }
+            assert!(storage.exists(1, 5).await.unwrap());
+            assert!(!storage.exists(5, 1).await.unwrap());
+
+            let mut all: Vec<(i32, i32)> = storage.list_chunks(None).try_collect().await.unwrap();
+            all.sort();
+            assert_eq!(all, vec![(-3, 2), (0, 0), (1, 5)]);
+            let inside: Vec<(i32, i32)> = storage.list_chunks(Some(((-5, 1), (0, 4)))).try_collect().await.unwrap();
+            assert_eq!(inside, vec![(-3, 2)]);
+
+            assert!(storage.delete_chunk(1, 5).await.unwrap());
+            assert!(!storage.delete_chunk(1, 5).await.unwrap());
+            assert!(!storage.exists(1, 5).await.unwrap());
+            assert_eq!(storage.get_total_size().await.unwrap(), 4);
+        });
+    }
+
+    #[test]
+    fn test_size_limit_refuses_saves() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let storage = MemoryStorage::new(Some(10));
+        rt.block_on(async {
+            storage.save_chunk(0, 0, &[0; 8]).await.unwrap();
+            assert!(storage.save_chunk(1, 0, &[0; 8]).await.is_err());
+            // Replacing an existing chunk only counts the difference
+            storage.save_chunk(0, 0, &[0; 10]).await.unwrap();
+            assert_eq!(storage.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let path = std::env::temp_dir().join(format!("hoppermc-memory-{}.snapshot", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        rt.block_on(async {
+            let storage = MemoryStorage::default().with_snapshot(&path).unwrap();
+            storage.save_chunk(-3, 7, &[9, 8, 7]).await.unwrap();
+            storage.close().await.unwrap();
+
+            let restored = MemoryStorage::default().with_snapshot(&path).unwrap();
+            assert_eq!(restored.load_chunk(-3, 7).await.unwrap(), Some(vec![9, 8, 7]));
+            assert_eq!(restored.used_bytes(), 3);
+        });
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_corrupt_or_oversized_snapshot_is_rejected() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let path = std::env::temp_dir().join(format!("hoppermc-memory-corrupt-{}.snapshot", std::process::id()));
+        let write = |count: u64, entries: &[(i32, i32, u32, &[u8])]| {
+            let mut bytes = SNAPSHOT_MAGIC.to_vec();
+            bytes.extend_from_slice(&count.to_le_bytes());
+            for (x, z, len, data) in entries {
+                bytes.extend_from_slice(&x.to_le_bytes());
+                bytes.extend_from_slice(&z.to_le_bytes());
+                bytes.extend_from_slice(&len.to_le_bytes());
+                bytes.extend_from_slice(data);
+            }
+            std::fs::write(&path, bytes).unwrap();
+        };
+
+        // Huge counts and lengths are refused before anything is allocated
+        write(u64::MAX, &[]);
+        assert!(MemoryStorage::default().with_snapshot(&path).is_err());
+        write(1, &[(0, 0, u32::MAX, &[1, 2, 3])]);
+        assert!(MemoryStorage::default().with_snapshot(&path).is_err());
+
+        // A valid snapshot over the size limit is refused too
+        write(2, &[(0, 0, 3, &[1, 2, 3]), (1, 0, 3, &[4, 5, 6])]);
+        assert!(MemoryStorage::new(Some(5)).with_snapshot(&path).is_err());
+        let storage = MemoryStorage::new(Some(6)).with_snapshot(&path).unwrap();
+        assert_eq!(rt.block_on(storage.load_chunk(1, 0)).unwrap(), Some(vec![4, 5, 6]));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,191 @@
+// Versioned schema migrations for the Postgres backends.
+//
+// Migrations are plain SQL files under `migrations/<scope>/`, embedded at compile
+// time and applied in version order. A scope is one storage mode's tables, plus
+// `worlds` and `history`, which every mode uses. `schema_migrations` records what
+// has been applied, so a later build can change columns and indexes of existing databases.
+//
+// Each `0001` migration uses `IF NOT EXISTS`, so databases created before this table
+// existed adopt it without changes. A database with migrations this build does not
+// know was written by a newer HopperMC, and is refused rather than guessed at.
+
+use crate::StorageMode;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+const MIGRATIONS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        scope TEXT NOT NULL,
+        version INT NOT NULL,
+        name TEXT NOT NULL,
+        applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (scope, version)
+    );
+";
+
+// Advisory lock key serializing concurrent migration runs
+const MIGRATION_LOCK: i64 = i64::from_be_bytes(*b"hmc-migr");
+
+/// One embedded migration, `migrations/<scope>/<version>_<name>.sql`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    pub scope: &'static str,
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+// Append only: never edit or renumber a migration that has shipped
+const MIGRATIONS: &[Migration] = &[
+    Migration { scope: "worlds", version: 1, name: "worlds", sql: include_str!("../migrations/worlds/0001_worlds.sql") },
+    Migration { scope: "history", version: 1, name: "chunk_history", sql: include_str!("../migrations/history/0001_chunk_history.sql") },
+    Migration { scope: "history", version: 2, name: "world_key", sql: include_str!("../migrations/history/0002_world_key.sql") },
+    Migration { scope: "raw", version: 1, name: "chunks_raw", sql: include_str!("../migrations/raw/0001_chunks_raw.sql") },
+    Migration { scope: "raw", version: 2, name: "zstd_compression", sql: include_str!("../migrations/raw/0002_zstd_compression.sql") },
+    Migration { scope: "raw", version: 3, name: "world_key", sql: include_str!("../migrations/raw/0003_world_key.sql") },
+    Migration { scope: "jsonb", version: 1, name: "chunks_jsonb", sql: include_str!("../migrations/jsonb/0001_chunks_jsonb.sql") },
+    Migration { scope: "jsonb", version: 2, name: "block_registry", sql: include_str!("../migrations/jsonb/0002_block_registry.sql") },
+    Migration { scope: "jsonb", version: 3, name: "world_key", sql: include_str!("../migrations/jsonb/0003_world_key.sql") },
+    Migration { scope: "hybrid", version: 1, name: "chunks_hybrid", sql: include_str!("../migrations/hybrid/0001_chunks_hybrid.sql") },
+    Migration { scope: "hybrid", version: 2, name: "world_key", sql: include_str!("../migrations/hybrid/0002_world_key.sql") },
+    Migration { scope: "weightless", version: 1, name: "chunks_weightless", sql: include_str!("../migrations/weightless/0001_chunks_weightless.sql") },
+    Migration { scope: "weightless", version: 2, name: "world_key", sql: include_str!("../migrations/weightless/0002_world_key.sql") },
+    Migration { scope: "dedup", version: 1, name: "chunks_dedup", sql: include_str!("../migrations/dedup/0001_chunks_dedup.sql") },
+    Migration { scope: "dedup", version: 2, name: "world_key", sql: include_str!("../migrations/dedup/0002_world_key.sql") },
+];
+
+/// The database has migrations of a scope that this build does not know.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaTooNew {
+    pub scope: &'static str,
+    pub version: i32,
+    pub latest: i32,
+}
+
+impl std::fmt::Display for SchemaTooNew {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Database schema '{}' is at version {}, but this build only knows version {}. Upgrade HopperMC before using this database",
+            self.scope, self.version, self.latest
+        )
+    }
+}
+
+impl std::error::Error for SchemaTooNew {}
+
+/// Migration scopes a storage mode needs, in the order they are applied
+/// (`worlds` first: the chunk tables reference it).
+pub fn scopes(mode: StorageMode) -> [&'static str; 3] {
+    let tables = match mode {
+        StorageMode::PgRaw => "raw",
+        StorageMode::PgJsonb => "jsonb",
+        StorageMode::Hybrid => "hybrid",
+        StorageMode::Weightless => "weightless",
+        StorageMode::PgDedup => "dedup",
+    };
+    ["worlds", tables, "history"]
+}
+
+/// Every known migration of a storage mode, in order.
+pub fn migrations(mode: StorageMode) -> Vec<&'static Migration> {
+    let scopes = scopes(mode);
+    let mut list: Vec<&Migration> = MIGRATIONS.iter().filter(|m| scopes.contains(&m.scope)).collect();
+    list.sort_by_key(|m| (scopes.iter().position(|s| *s == m.scope), m.version));
+    list
+}
+
+/// Migrations of `mode` missing from `applied` (highest applied version per scope).
+/// Fails if a scope is ahead of this build.
+fn pending(mode: StorageMode, applied: &HashMap<String, i32>) -> Result<Vec<&'static Migration>> {
+    let known = migrations(mode);
+    for scope in scopes(mode) {
+        let latest = known.iter().filter(|m| m.scope == scope).map(|m| m.version).max().unwrap_or(0);
+        if let Some(&version) = applied.get(scope) && version > latest {
+            return Err(SchemaTooNew { scope, version, latest }.into());
+        }
+    }
+    Ok(known.into_iter().filter(|m| applied.get(m.scope).is_none_or(|v| m.version > *v)).collect())
+}
+
+async fn applied_versions(client: &tokio_postgres::Client) -> Result<HashMap<String, i32>> {
+    let tracked: bool = client.query_one("SELECT to_regclass('schema_migrations') IS NOT NULL", &[])
+        .await.context("Failed to look up schema_migrations")?.get(0);
+    if !tracked {
+        return Ok(HashMap::new());
+    }
+    let rows = client.query("SELECT scope, MAX(version) FROM schema_migrations GROUP BY scope", &[])
+        .await.context("Failed to read schema_migrations")?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+/// Migrations `mode` still needs on this database, without applying anything.
+/// Fails if the database is newer than this build.
+pub(crate) async fn check(client: &tokio_postgres::Client, mode: StorageMode) -> Result<Vec<&'static Migration>> {
+    pending(mode, &applied_versions(client).await?)
+}
+
+/// Apply every pending migration of `mode`, each in its own transaction. Returns the ones applied.
+pub(crate) async fn migrate(client: &mut tokio_postgres::Client, mode: StorageMode) -> Result<Vec<&'static Migration>> {
+    client.batch_execute(MIGRATIONS_TABLE).await.context("Failed to init schema_migrations")?;
+    // Held for the whole run: a second instance starting at the same time waits, then finds nothing to do
+    client.execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK]).await.context("Failed to lock schema_migrations")?;
+    // Migrations may rewrite whole tables: lift a configured statement timeout for this session
+    client.batch_execute("SET statement_timeout = 0").await.context("Failed to lift statement_timeout")?;
+    let result = apply_pending(client, mode).await;
+    client.batch_execute("RESET statement_timeout").await.context("Failed to restore statement_timeout")?;
+    client.execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK]).await.context("Failed to unlock schema_migrations")?;
+    result
+}
+
+async fn apply_pending(client: &mut tokio_postgres::Client, mode: StorageMode) -> Result<Vec<&'static Migration>> {
+    let pending = pending(mode, &applied_versions(client).await?)?;
+    for migration in &pending {
+        log::info!("Applying migration {}/{:04}_{}", migration.scope, migration.version, migration.name);
+        let tx = client.transaction().await.context("Failed to begin transaction")?;
+        tx.batch_execute(migration.sql).await
+            .with_context(|| format!("Migration {}/{:04}_{} failed", migration.scope, migration.version, migration.name))?;
+        tx.execute(
+            "INSERT INTO schema_migrations (scope, version, name) VALUES ($1, $2, $3)",
+            &[&migration.scope, &migration.version, &migration.name],
+        ).await.context("Failed to record migration")?;
+        tx.commit().await.context("Failed to commit migration")?;
+    }
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_numbered_in_order() {
+        for mode in [StorageMode::PgRaw, StorageMode::PgJsonb, StorageMode::Hybrid, StorageMode::Weightless, StorageMode::PgDedup] {
+            let list = migrations(mode);
+            for scope in scopes(mode) {
+                let versions: Vec<i32> = list.iter().filter(|m| m.scope == scope).map(|m| m.version).collect();
+                assert_eq!(versions, (1..=versions.len() as i32).collect::<Vec<_>>(), "{} migrations", scope);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pending_and_newer_schema() {
+        let mode = StorageMode::PgJsonb;
+        assert_eq!(pending(mode, &HashMap::new()).unwrap().len(), 6);
+
+        let applied = HashMap::from([("jsonb".to_string(), 1), ("history".to_string(), 1)]);
+        let todo = pending(mode, &applied).unwrap();
+        assert_eq!(
+            todo.iter().map(|m| (m.scope, m.version)).collect::<Vec<_>>(),
+            vec![("worlds", 1), ("jsonb", 2), ("jsonb", 3), ("history", 2)]
+        );
+
+        // Other modes' scopes don't matter, a newer one of ours does
+        let applied = HashMap::from([("dedup".to_string(), 99), ("jsonb".to_string(), 3)]);
+        assert!(pending(mode, &applied).is_ok());
+        let applied = HashMap::from([("jsonb".to_string(), 4)]);
+        let err = pending(mode, &applied).unwrap_err();
+        assert_eq!(err.downcast_ref::<SchemaTooNew>(), Some(&SchemaTooNew { scope: "jsonb", version: 4, latest: 3 }));
+    }
+}
@@ -0,0 +1,595 @@
+// Redis storage: low-latency chunk state shared by several HopperMC instances.
+//
+// One hash per region (`<prefix>:r:<rx>:<rz>`), field = chunk index in the region (0..1023),
+// value = raw NBT. A region load is a single HGETALL.
+//
+// Optionally a slower backend (usually Postgres) sits behind Redis:
+// - writes go to Redis and are persisted to it asynchronously (write-behind, coalesced per chunk).
+//   The queue lives in Redis too: each save bumps the chunk's counter in `<prefix>:dirty` in the
+//   same transaction, so a crash loses nothing and the next start drains what is left. One
+//   instance drains at a time (`<prefix>:persist-lease`, renewed between batches);
+// - a region is read through from it the first time it is needed (cold Redis after a restart),
+//   marked with `<prefix>:loaded:<rx>:<rz>` so it is only copied once. Lookups of the region
+//   hash and its marker are pipelined into one round-trip.
+// Deletes remove the chunk from Redis, its queue entry and the fallback; listing yields what
+// Redis holds, then what only the fallback has.
+
+use crate::{ChunkKeyStream, ChunkStorage};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::{StreamExt, TryFutureExt, TryStreamExt};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+// Wait before retrying chunks the fallback refused
+const PERSIST_RETRY_DELAY: Duration = Duration::from_secs(1);
+// Chunks per `save_chunks` call towards the fallback
+const PERSIST_BATCH: usize = 256;
+// An instance that died while draining blocks others for at most this long
+const PERSIST_LEASE: Duration = Duration::from_secs(60);
+// On close, wait this long for another drainer to finish before leaving the queue to it
+const FLUSH_LEASE_WAIT: Duration = Duration::from_secs(10);
+const FLUSH_LEASE_POLL: Duration = Duration::from_millis(100);
+
+// Remove a dirty mark only if no newer write bumped it since it was read
+const CLEAR_IF_UNCHANGED: &str = "if redis.call('HGET', KEYS[1], ARGV[1]) == ARGV[2] then return redis.call('HDEL', KEYS[1], ARGV[1]) end return 0";
+// Release the drain lease only if this instance still holds it
+const RELEASE_LEASE: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) end return 0";
+// Extend the drain lease only if this instance still holds it
+const RENEW_LEASE: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('PEXPIRE', KEYS[1], ARGV[2]) end return 0";
+
+fn region_field(x: i32, z: i32) -> u16 {
+    ((x & 31) + (z & 31) * 32) as u16
+}
+
+fn region_key(prefix: &str, region_x: i32, region_z: i32) -> String {
+    format!("{}:r:{}:{}", prefix, region_x, region_z)
+}
+
+fn parse_region_key(prefix: &str, key: &str) -> Option<(i32, i32)> {
+    let (region_x, region_z) = key.strip_prefix(prefix)?.strip_prefix(":r:")?.split_once(':')?;
+    Some((region_x.parse().ok()?, region_z.parse().ok()?))
+}
+
+fn dirty_field(x: i32, z: i32) -> String {
+    format!("{}:{}", x, z)
+}
+
+fn parse_dirty_field(field: &str) -> Option<(i32, i32)> {
+    let (x, z) = field.split_once(':')?;
+    Some((x.parse().ok()?, z.parse().ok()?))
+}
+
+/// Save a batch to `fallback` in one call; if that fails, chunk by chunk so one bad chunk
+/// does not hold back the rest. Returns which chunks were saved, and the first error.
+async fn persist_batch(fallback: &dyn ChunkStorage, chunks: &[(i32, i32, Vec<u8>)]) -> (Vec<bool>, Option<anyhow::Error>) {
+    if fallback.save_chunks(chunks).await.is_ok() {
+        return (vec![true; chunks.len()], None);
+    }
+    let mut saved = Vec::with_capacity(chunks.len());
+    let mut first_error = None;
+    for (x, z, data) in chunks {
+        match fallback.save_chunk(*x, *z, data).await {
+            Ok(()) => saved.push(true),
+            Err(e) => {
+                saved.push(false);
+                first_error.get_or_insert(e.context(format!("Failed to persist chunk ({}, {}) to the fallback backend", x, z)));
+            }
+        }
+    }
+    (saved, first_error)
+}
+
+/// Write-behind queue towards the fallback backend, kept in Redis. Only the latest data per
+/// chunk is persisted.
+struct Persister {
+    fallback: Arc<dyn ChunkStorage>,
+    conn: ConnectionManager,
+    prefix: String,
+    wake: Notify,
+    stopped: AtomicBool,
+    // One drainer per process; the lease extends this across instances, so two versions
+    // of a chunk can never be written out of order
+    drain_lock: tokio::sync::Mutex<()>,
+}
+
+impl Persister {
+    fn new(fallback: Arc<dyn ChunkStorage>, conn: ConnectionManager, prefix: &str) -> Arc<Self> {
+        Arc::new(Self { fallback, conn, prefix: prefix.to_string(), wake: Notify::new(), stopped: AtomicBool::new(false), drain_lock: tokio::sync::Mutex::new(()) })
+    }
+
+    fn dirty_key(&self) -> String {
+        format!("{}:dirty", self.prefix)
+    }
+
+    fn lease_key(&self) -> String {
+        format!("{}:persist-lease", self.prefix)
+    }
+
+    async fn len(&self) -> Result<usize> {
+        Ok(self.conn.clone().hlen(self.dirty_key()).await?)
+    }
+
+    /// Persist every chunk marked dirty once. Returns how many were persisted, or None when
+    /// another instance holds the drain lease. Failed chunks stay marked; the first error is returned.
+    async fn drain(&self) -> Result<Option<usize>> {
+        let _guard = self.drain_lock.lock().await;
+        let mut conn = self.conn.clone();
+        let token = format!("{}-{:?}", std::process::id(), std::time::SystemTime::now());
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(self.lease_key()).arg(&token).arg("NX").arg("PX").arg(PERSIST_LEASE.as_millis() as u64)
+            .query_async(&mut conn).await?;
+        if acquired.is_none() {
+            return Ok(None);
+        }
+        let result = self.drain_marked(&mut conn, &token).await;
+        redis::cmd("EVAL").arg(RELEASE_LEASE).arg(1).arg(self.lease_key()).arg(&token).query_async::<()>(&mut conn).await?;
+        result.map(Some)
+    }
+
+    async fn drain_marked(&self, conn: &mut ConnectionManager, token: &str) -> Result<usize> {
+        let dirty: HashMap<String, String> = conn.hgetall(self.dirty_key()).await?;
+        let marked: Vec<((i32, i32), String, String)> = dirty.into_iter()
+            .filter_map(|(field, version)| parse_dirty_field(&field).map(|key| (key, field, version)))
+            .collect();
+
+        let (mut persisted, mut failed, mut first_error) = (0, 0, None);
+        for (i, batch) in marked.chunks(PERSIST_BATCH).enumerate() {
+            // Past the lease, another instance may already be draining: stop rather than
+            // write chunks out of order with it
+            if i > 0 && !self.renew_lease(conn, token).await? {
+                anyhow::bail!("Lost the Redis persist lease after {} chunks, leaving the rest queued", persisted);
+            }
+            let mut pipe = redis::pipe();
+            for ((x, z), _, _) in batch {
+                pipe.hget(region_key(&self.prefix, x >> 5, z >> 5), region_field(*x, *z));
+            }
+            let data: Vec<Option<Vec<u8>>> = pipe.query_async(conn).await?;
+
+            let mut chunks = Vec::with_capacity(batch.len());
+            let mut marks = Vec::with_capacity(batch.len());
+            let mut clear = Vec::with_capacity(batch.len());
+            for (((x, z), field, version), data) in batch.iter().zip(data) {
+                match data {
+                    Some(data) => {
+                        chunks.push((*x, *z, data));
+                        marks.push((field, version));
+                    }
+                    // Nothing left to persist (region deleted from Redis)
+                    None => clear.push((field, version)),
+                }
+            }
+            let (saved, error) = persist_batch(self.fallback.as_ref(), &chunks).await;
+            clear.extend(marks.into_iter().zip(&saved).filter(|(_, ok)| **ok).map(|(mark, _)| mark));
+            persisted += saved.iter().filter(|ok| **ok).count();
+            failed += saved.iter().filter(|ok| !**ok).count();
+            if let Some(e) = error {
+                first_error.get_or_insert(e);
+            }
+
+            let mut pipe = redis::pipe();
+            for (field, version) in clear {
+                pipe.cmd("EVAL").arg(CLEAR_IF_UNCHANGED).arg(1).arg(self.dirty_key()).arg(field).arg(version).ignore();
+            }
+            pipe.query_async::<()>(conn).await?;
+        }
+        match first_error {
+            Some(e) => Err(e.context(format!("{} chunks could not be persisted and stay queued", failed))),
+            None => Ok(persisted),
+        }
+    }
+
+    async fn renew_lease(&self, conn: &mut ConnectionManager, token: &str) -> Result<bool> {
+        let renewed: i64 = redis::cmd("EVAL")
+            .arg(RENEW_LEASE).arg(1).arg(self.lease_key()).arg(token).arg(PERSIST_LEASE.as_millis() as u64)
+            .query_async(conn).await?;
+        Ok(renewed == 1)
+    }
+
+    /// Let the background task exit; a drain in flight still finishes and releases the lease.
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+        self.wake.notify_one();
+    }
+
+    async fn run(self: Arc<Self>) {
+        loop {
+            self.wake.notified().await;
+            if self.stopped.load(Ordering::Acquire) {
+                return;
+            }
+            match self.drain().await {
+                Ok(Some(_)) => {}
+                // Another instance is draining; check again later
+                Ok(None) => {
+                    tokio::time::sleep(PERSIST_RETRY_DELAY).await;
+                    self.wake.notify_one();
+                }
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    tokio::time::sleep(PERSIST_RETRY_DELAY).await;
+                    self.wake.notify_one();
+                }
+            }
+        }
+    }
+
+    /// Drain until nothing is pending (used on shutdown). If another instance keeps the
+    /// drain lease past `FLUSH_LEASE_WAIT`, what is left stays queued in Redis for it.
+    async fn flush(&self) -> Result<()> {
+        let mut waiting_since = None;
+        while self.len().await? > 0 {
+            if self.drain().await?.is_some() {
+                waiting_since = None;
+                continue;
+            }
+            if waiting_since.get_or_insert_with(Instant::now).elapsed() >= FLUSH_LEASE_WAIT {
+                log::info!("Another instance is persisting queued Redis writes, leaving them to it");
+                break;
+            }
+            tokio::time::sleep(FLUSH_LEASE_POLL).await;
+        }
+        Ok(())
+    }
+}
+
+pub struct RedisStorage {
+    conn: ConnectionManager,
+    prefix: String,
+    persister: Option<Arc<Persister>>,
+}
+
+impl RedisStorage {
+    /// Connect to `url` (e.g. `redis://127.0.0.1:6379/0`). `prefix` namespaces every key.
+    pub async fn connect(url: &str, prefix: &str) -> Result<Self> {
+        let client = redis::Client::open(url).with_context(|| format!("Invalid Redis URL {}", url))?;
+        let conn = client.get_connection_manager().await.with_context(|| format!("Failed to connect to Redis at {}", url))?;
+        Ok(Self { conn, prefix: prefix.to_string(), persister: None })
+    }
+
+    /// Persist every write asynchronously to `fallback`, and read regions through from it
+    /// the first time they are loaded. Writes still queued from a previous run are drained first.
+    pub fn with_fallback(mut self, fallback: Arc<dyn ChunkStorage>) -> Self {
+        let persister = Persister::new(fallback, self.conn.clone(), &self.prefix);
+        persister.wake.notify_one();
+        tokio::spawn(persister.clone().run());
+        self.persister = Some(persister);
+        self
+    }
+
+    fn region_key(&self, region_x: i32, region_z: i32) -> String {
+        region_key(&self.prefix, region_x, region_z)
+    }
+
+    fn loaded_key(&self, region_x: i32, region_z: i32) -> String {
+        format!("{}:loaded:{}:{}", self.prefix, region_x, region_z)
+    }
+
+    /// Copy a region from the fallback into Redis (without overwriting newer Redis values)
+    /// and mark it loaded. Returns what the fallback had.
+    async fn read_through(&self, fallback: &dyn ChunkStorage, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        let chunks = fallback.load_region(region_x, region_z).await?;
+        let key = self.region_key(region_x, region_z);
+        let mut pipe = redis::pipe();
+        for (x, z, data) in &chunks {
+            pipe.hset_nx(&key, region_field(*x, *z), data.as_slice()).ignore();
+        }
+        pipe.set(self.loaded_key(region_x, region_z), 1).ignore();
+        pipe.query_async::<()>(&mut self.conn.clone()).await?;
+        log::debug!("Redis: read {} chunks of region ({}, {}) through from the fallback", chunks.len(), region_x, region_z);
+        Ok(chunks)
+    }
+}
+
+#[async_trait]
+impl ChunkStorage for RedisStorage {
+    async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+        let key = self.region_key(x >> 5, z >> 5);
+        let Some(persister) = &self.persister else {
+            self.conn.clone().hset::<_, _, _, ()>(&key, region_field(x, z), data).await.with_context(|| format!("Failed to save chunk ({}, {}) to Redis", x, z))?;
+            return Ok(());
+        };
+        // The chunk and its queue entry are written together
+        redis::pipe()
+            .atomic()
+            .hset(&key, region_field(x, z), data).ignore()
+            .hincr(persister.dirty_key(), dirty_field(x, z), 1).ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await
+            .with_context(|| format!("Failed to save chunk ({}, {}) to Redis", x, z))?;
+        persister.wake.notify_one();
+        Ok(())
+    }
+
+    async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+        let (region_x, region_z) = (x >> 5, z >> 5);
+        let key = self.region_key(region_x, region_z);
+        let Some(persister) = &self.persister else {
+            return Ok(self.conn.clone().hget(&key, region_field(x, z)).await?);
+        };
+
+        let (data, loaded): (Option<Vec<u8>>, bool) = redis::pipe()
+            .hget(&key, region_field(x, z))
+            .exists(self.loaded_key(region_x, region_z))
+            .query_async(&mut self.conn.clone())
+            .await?;
+        if data.is_some() || loaded {
+            return Ok(data);
+        }
+        let chunks = self.read_through(persister.fallback.as_ref(), region_x, region_z).await?;
+        Ok(chunks.into_iter().find(|(cx, cz, _)| (*cx, *cz) == (x, z)).map(|(_, _, data)| data))
+    }
+
+    async fn load_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        let key = self.region_key(region_x, region_z);
+        let (fields, loaded): (HashMap<u16, Vec<u8>>, bool) = redis::pipe()
+            .hgetall(&key)
+            .exists(self.loaded_key(region_x, region_z))
+            .query_async(&mut self.conn.clone())
+            .await?;
+
+        let mut chunks: HashMap<(i32, i32), Vec<u8>> = fields
+            .into_iter()
+            .map(|(field, data)| ((region_x * 32 + (field % 32) as i32, region_z * 32 + (field / 32) as i32), data))
+            .collect();
+        if let Some(persister) = &self.persister
+            && !loaded
+        {
+            // Redis values are newer than anything the fallback has
+            for (x, z, data) in self.read_through(persister.fallback.as_ref(), region_x, region_z).await? {
+                chunks.entry((x, z)).or_insert(data);
+            }
+        }
+        Ok(chunks.into_iter().map(|((x, z), data)| (x, z, data)).collect())
+    }
+
+    async fn delete_chunk(&self, x: i32, z: i32) -> Result<bool> {
+        let key = self.region_key(x >> 5, z >> 5);
+        let Some(persister) = &self.persister else {
+            let removed: usize = self.conn.clone().hdel(&key, region_field(x, z)).await.with_context(|| format!("Failed to delete chunk ({}, {}) from Redis", x, z))?;
+            return Ok(removed > 0);
+        };
+        // No drain of this instance may persist the chunk again between the two deletes
+        let _guard = persister.drain_lock.lock().await;
+        let (removed, _): (usize, usize) = redis::pipe()
+            .atomic()
+            .hdel(&key, region_field(x, z))
+            .hdel(persister.dirty_key(), dirty_field(x, z))
+            .query_async(&mut self.conn.clone())
+            .await
+            .with_context(|| format!("Failed to delete chunk ({}, {}) from Redis", x, z))?;
+        let persisted = persister.fallback.delete_chunk(x, z).await?;
+        Ok(removed > 0 || persisted)
+    }
+
+    fn list_chunks(&self, bounds: Option<((i32, i32), (i32, i32))>) -> ChunkKeyStream<'_> {
+        let inside = move |x: i32, z: i32| bounds.is_none_or(|(min, max)| (min.0..=max.0).contains(&x) && (min.1..=max.1).contains(&z));
+        let overlaps = move |(rx, rz): &(i32, i32)| {
+            bounds.is_none_or(|(min, max)| (min.0 >> 5..=max.0 >> 5).contains(rx) && (min.1 >> 5..=max.1 >> 5).contains(rz))
+        };
+        async move {
+            let mut conn = self.conn.clone();
+            let mut regions = Vec::new();
+            {
+                let mut iter = conn.scan_match::<_, String>(format!("{}:r:*", self.prefix)).await?;
+                while let Some(key) = iter.next_item().await {
+                    regions.extend(parse_region_key(&self.prefix, &key).filter(overlaps));
+                }
+            }
+
+            let mut keys = Vec::new();
+            for (region_x, region_z) in regions {
+                let fields: Vec<u16> = conn.hkeys(self.region_key(region_x, region_z)).await?;
+                keys.extend(fields.into_iter()
+                    .map(|field| (region_x * 32 + (field % 32) as i32, region_z * 32 + (field / 32) as i32))
+                    .filter(|(x, z)| inside(*x, *z)));
+            }
+            // Chunks of regions never read through live only in the fallback
+            let persisted = self.persister.as_ref().map(|persister| {
+                let in_redis: HashSet<(i32, i32)> = keys.iter().copied().collect();
+                persister.fallback.list_chunks(bounds).try_filter(move |key| futures::future::ready(!in_redis.contains(key)))
+            });
+            Ok(futures::stream::iter(keys.into_iter().map(Ok)).chain(futures::stream::iter(persisted).flatten()))
+        }
+        .try_flatten_stream()
+        .boxed()
+    }
+
+    async fn get_total_size(&self) -> Result<u64> {
+        let mut conn = self.conn.clone();
+        let keys: Vec<String> = {
+            let mut iter = conn.scan_match::<_, String>(format!("{}:r:*", self.prefix)).await?;
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let mut pipe = redis::pipe();
+        for key in &keys {
+            pipe.cmd("MEMORY").arg("USAGE").arg(key);
+        }
+        let sizes: Vec<Option<u64>> = pipe.query_async(&mut conn).await?;
+        Ok(sizes.into_iter().flatten().sum())
+    }
+
+    async fn close(&self) -> Result<()> {
+        if let Some(persister) = &self.persister {
+            persister.stop();
+            // Waits for a drain already in flight, then persists the rest
+            persister.flush().await?;
+            persister.fallback.close().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RedisStorage {
+    fn drop(&mut self) {
+        if let Some(persister) = &self.persister {
+            persister.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStorage;
+
+    #[test]
+    fn test_persist_batch_isolates_failures() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            // Fallback with room for two bytes: the batch as a whole does not fit
+            let fallback = MemoryStorage::new(Some(2));
+            let chunks = vec![(0, 0, vec![2, 2]), (1, 0, vec![3, 3, 3])];
+            let (saved, error) = persist_batch(&fallback, &chunks).await;
+            assert_eq!(saved, vec![true, false]);
+            assert!(error.unwrap().to_string().contains("(1, 0)"));
+            assert_eq!(fallback.load_chunk(0, 0).await.unwrap(), Some(vec![2, 2]));
+
+            let (saved, error) = persist_batch(&fallback, &[(0, 0, vec![1])]).await;
+            assert_eq!((saved, error.is_none()), (vec![true], true));
+        });
+    }
+
+    #[test]
+    fn test_dirty_field_roundtrip() {
+        assert_eq!(parse_dirty_field(&dirty_field(-33, 7)), Some((-33, 7)));
+        assert_eq!(parse_dirty_field("persist-lease"), None);
+    }
+
+    #[test]
+    fn test_parse_region_key() {
+        assert_eq!(parse_region_key("world", &region_key("world", -2, 5)), Some((-2, 5)));
+        assert_eq!(parse_region_key("world", "world:loaded:0:0"), None);
+        assert_eq!(parse_region_key("world", "other:r:0:0"), None);
+    }
+
+    fn test_url() -> String {
+        std::env::var("REDIS_TEST_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string())
+    }
+
+    /// Needs a local `redis-server` (or `REDIS_TEST_URL`): `cargo test -p hoppermc-storage -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_redis_roundtrip() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let prefix = format!("hoppermc-test-{}", std::process::id());
+            let redis = RedisStorage::connect(&test_url(), &prefix).await.unwrap();
+            redis.save_chunk(-1, 33, &[1, 2]).await.unwrap();
+            redis.save_chunk(-32, 32, &[3]).await.unwrap();
+            redis.save_chunk(0, 0, &[4]).await.unwrap();
+
+            assert_eq!(redis.load_chunk(-1, 33).await.unwrap(), Some(vec![1, 2]));
+            assert_eq!(redis.load_chunk(-2, 33).await.unwrap(), None);
+            let mut region = redis.load_region(-1, 1).await.unwrap();
+            region.sort();
+            assert_eq!(region, vec![(-32, 32, vec![3]), (-1, 33, vec![1, 2])]);
+            assert!(redis.get_total_size().await.unwrap() > 0);
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_redis_fallback_read_through_and_persist() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let prefix = format!("hoppermc-test-fallback-{}", std::process::id());
+            let fallback = Arc::new(MemoryStorage::default());
+            fallback.save_chunk(1, 1, &[7]).await.unwrap();
+            fallback.save_chunk(2, 2, &[8]).await.unwrap();
+
+            let redis = RedisStorage::connect(&test_url(), &prefix).await.unwrap().with_fallback(fallback.clone());
+            // Newer value in Redis wins over the fallback's copy
+            redis.save_chunk(2, 2, &[9]).await.unwrap();
+            let mut region = redis.load_region(0, 0).await.unwrap();
+            region.sort();
+            assert_eq!(region, vec![(1, 1, vec![7]), (2, 2, vec![9])]);
+
+            redis.close().await.unwrap();
+            assert_eq!(fallback.load_chunk(2, 2).await.unwrap(), Some(vec![9]));
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_redis_delete_and_list_cover_the_fallback() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let prefix = format!("hoppermc-test-list-{}", std::process::id());
+            let fallback = Arc::new(MemoryStorage::default());
+            fallback.save_chunk(40, 40, &[1]).await.unwrap();
+            fallback.save_chunk(2, 2, &[2]).await.unwrap();
+
+            let redis = RedisStorage::connect(&test_url(), &prefix).await.unwrap().with_fallback(fallback.clone());
+            redis.save_chunk(2, 2, &[3]).await.unwrap();
+            redis.save_chunk(-1, 0, &[4]).await.unwrap();
+            let mut keys: Vec<(i32, i32)> = redis.list_chunks(None).try_collect().await.unwrap();
+            keys.sort();
+            assert_eq!(keys, vec![(-1, 0), (2, 2), (40, 40)]);
+            let bounded: Vec<(i32, i32)> = redis.list_chunks(Some(((0, 0), (31, 31)))).try_collect().await.unwrap();
+            assert_eq!(bounded, vec![(2, 2)]);
+
+            // Gone from Redis, the queue and the fallback: neither a drain nor a read-through brings it back
+            assert!(redis.delete_chunk(2, 2).await.unwrap());
+            assert!(redis.delete_chunk(40, 40).await.unwrap());
+            assert!(!redis.delete_chunk(5, 5).await.unwrap());
+            redis.close().await.unwrap();
+            assert_eq!(fallback.load_chunk(2, 2).await.unwrap(), None);
+            assert_eq!(redis.load_chunk(40, 40).await.unwrap(), None);
+            assert_eq!(redis.load_chunk(-1, 0).await.unwrap(), Some(vec![4]));
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_redis_queue_survives_restart() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let prefix = format!("hoppermc-test-queue-{}", std::process::id());
+            // A fallback that refuses everything stands in for a crash before persisting
+            let full = Arc::new(MemoryStorage::new(Some(0)));
+            let crashed = RedisStorage::connect(&test_url(), &prefix).await.unwrap().with_fallback(full);
+            crashed.save_chunk(3, 4, &[1]).await.unwrap();
+            crashed.save_chunk(3, 4, &[2]).await.unwrap();
+            drop(crashed);
+
+            // The next start drains what is still queued
+            let fallback = Arc::new(MemoryStorage::default());
+            let redis = RedisStorage::connect(&test_url(), &prefix).await.unwrap().with_fallback(fallback.clone());
+            redis.close().await.unwrap();
+            assert_eq!(fallback.load_chunk(3, 4).await.unwrap(), Some(vec![2]));
+            assert_eq!(redis.persister.as_ref().unwrap().len().await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_lease_renewal_needs_the_lease() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let prefix = format!("hoppermc-test-lease-{}", std::process::id());
+            let redis = RedisStorage::connect(&test_url(), &prefix).await.unwrap().with_fallback(Arc::new(MemoryStorage::default()));
+            let persister = redis.persister.clone().unwrap();
+            let mut conn = redis.conn.clone();
+
+            conn.set::<_, _, ()>(persister.lease_key(), "ours").await.unwrap();
+            assert!(persister.renew_lease(&mut conn, "ours").await.unwrap());
+            // Expired and taken over by another instance
+            conn.set::<_, _, ()>(persister.lease_key(), "theirs").await.unwrap();
+            assert!(!persister.renew_lease(&mut conn, "ours").await.unwrap());
+            conn.del::<_, ()>(persister.lease_key()).await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,229 @@
+// Global block-state registry for `pg_jsonb`.
+//
+// Section palettes repeat the same block states ("minecraft:deepslate_diamond_ore",
+// stairs with their properties, ...) in every chunk. `block_registry` assigns each
+// full state a stable integer id, and stored palettes hold the ids instead. Rows
+// written before the registry keep their inline entries and still load as they are.
+//
+// Ids are cached in both directions; the table only grows, so the cache never goes stale.
+// Table and views: migrations/jsonb/0002_block_registry.sql.
+
+use anyhow::{Context, Result};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::RwLock;
+
+/// Readable key of a palette entry: `name` or `name[key=value,...]` with sorted keys.
+pub fn state_key(entry: &JsonValue) -> Option<String> {
+    let name = entry.get("Name")?.as_str()?;
+    let properties = match entry.get("Properties") {
+        Some(JsonValue::Object(props)) => {
+            let mut pairs = Vec::with_capacity(props.len());
+            for (k, v) in props {
+                pairs.push(format!("{}={}", k, v.as_str()?));
+            }
+            pairs.sort();
+            pairs
+        }
+        Some(_) => return None,
+        None => Vec::new(),
+    };
+    // Anything the key cannot express (extra tags, non-string values) stays inline
+    if entry.as_object()?.keys().any(|k| k != "Name" && k != "Properties") {
+        return None;
+    }
+    Some(if properties.is_empty() { name.to_string() } else { format!("{}[{}]", name, properties.join(",")) })
+}
+
+/// Every section palette of a chunk document.
+fn palettes_mut(doc: &mut JsonValue) -> impl Iterator<Item = &mut Vec<JsonValue>> {
+    let sections = match doc.get_mut("sections") {
+        Some(JsonValue::Array(sections)) => sections.as_mut_slice(),
+        _ => &mut [],
+    };
+    sections.iter_mut().filter_map(|section| match section.pointer_mut("/block_states/palette") {
+        Some(JsonValue::Array(palette)) => Some(palette),
+        _ => None,
+    })
+}
+
+/// Replace palette entries by registry ids (entries without an id stay inline).
+fn replace_entries(doc: &mut JsonValue, ids: &HashMap<String, i32>) {
+    for palette in palettes_mut(doc) {
+        for entry in palette.iter_mut() {
+            if let Some(id) = state_key(entry).and_then(|key| ids.get(&key)) {
+                *entry = JsonValue::from(*id);
+            }
+        }
+    }
+}
+
+/// Replace registry ids in palettes by their entries.
+fn replace_ids(doc: &mut JsonValue, entries: &HashMap<i32, JsonValue>) -> Result<()> {
+    for palette in palettes_mut(doc) {
+        for entry in palette.iter_mut() {
+            if let Some(id) = entry.as_i64() {
+                *entry = entries.get(&(id as i32)).with_context(|| format!("Unknown block registry id {}", id))?.clone();
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Cache {
+    ids: HashMap<String, i32>,
+    entries: HashMap<i32, JsonValue>,
+}
+
+#[derive(Default)]
+pub struct BlockRegistry {
+    cache: RwLock<Cache>,
+}
+
+impl BlockRegistry {
+    fn remember(&self, id: i32, key: String, entry: JsonValue) {
+        let mut cache = self.cache.write().unwrap();
+        cache.ids.insert(key, id);
+        cache.entries.insert(id, entry);
+    }
+
+    /// Swap the palette entries of `doc` for registry ids, registering new states.
+    pub async fn encode(&self, client: &deadpool_postgres::Client, doc: &mut JsonValue) -> Result<()> {
+        let mut missing: HashMap<String, JsonValue> = HashMap::new();
+        let mut ids = HashMap::new();
+        {
+            let cache = self.cache.read().unwrap();
+            for palette in palettes_mut(doc) {
+                for entry in palette.iter() {
+                    let Some(key) = state_key(entry) else { continue };
+                    match cache.ids.get(&key) {
+                        Some(id) => {
+                            ids.insert(key, *id);
+                        }
+                        None => {
+                            missing.entry(key).or_insert_with(|| entry.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            // Sorted so concurrent registrations lock the unique index in the same order
+            let keys: BTreeSet<&String> = missing.keys().collect();
+            let (states, (names, entries)): (Vec<&String>, (Vec<&str>, Vec<&JsonValue>)) = keys
+                .iter()
+                .map(|key| {
+                    let entry = &missing[*key];
+                    (*key, (entry["Name"].as_str().unwrap_or_default(), entry))
+                })
+                .unzip();
+            client.execute(
+                "INSERT INTO block_registry (state, name, entry)
+                 SELECT * FROM unnest($1::text[], $2::text[], $3::jsonb[])
+                 ON CONFLICT (state) DO NOTHING",
+                &[&states, &names, &entries],
+            ).await.context("Failed to register block states")?;
+            let rows = client.query("SELECT id, state FROM block_registry WHERE state = ANY($1)", &[&states])
+                .await.context("Failed to read block registry")?;
+            for row in rows {
+                let (id, state): (i32, String) = (row.get(0), row.get(1));
+                let entry = missing.remove(&state).context("Block registry returned an unrequested state")?;
+                ids.insert(state.clone(), id);
+                self.remember(id, state, entry);
+            }
+        }
+
+        replace_entries(doc, &ids);
+        Ok(())
+    }
+
+    /// Swap registry ids in the palettes of `doc` back for the full entries.
+    pub async fn decode(&self, client: &deadpool_postgres::Client, doc: &mut JsonValue) -> Result<()> {
+        let mut entries = HashMap::new();
+        let mut missing = BTreeSet::new();
+        {
+            let cache = self.cache.read().unwrap();
+            for palette in palettes_mut(doc) {
+                for id in palette.iter().filter_map(|entry| entry.as_i64()).map(|id| id as i32) {
+                    match cache.entries.get(&id) {
+                        Some(entry) => {
+                            entries.insert(id, entry.clone());
+                        }
+                        None => {
+                            missing.insert(id);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            let ids: Vec<i32> = missing.into_iter().collect();
+            let rows = client.query("SELECT id, state, entry FROM block_registry WHERE id = ANY($1)", &[&ids])
+                .await.context("Failed to read block registry")?;
+            for row in rows {
+                let (id, state, entry): (i32, String, JsonValue) = (row.get(0), row.get(1), row.get(2));
+                entries.insert(id, entry.clone());
+                self.remember(id, state, entry);
+            }
+        }
+
+        replace_ids(doc, &entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chunk() -> JsonValue {
+        json!({
+            "xPos": 0,
+            "sections": [{
+                "Y": 0,
+                "block_states": {
+                    "palette": [
+                        {"Name": "minecraft:stone"},
+                        {"Name": "minecraft:oak_stairs", "Properties": {"half": "bottom", "facing": "east"}}
+                    ]
+                }
+            }]
+        })
+    }
+
+    #[test]
+    fn test_state_key_sorts_properties() {
+        assert_eq!(state_key(&json!({"Name": "minecraft:stone"})).as_deref(), Some("minecraft:stone"));
+        assert_eq!(
+            state_key(&json!({"Name": "minecraft:oak_stairs", "Properties": {"half": "bottom", "facing": "east"}})).as_deref(),
+            Some("minecraft:oak_stairs[facing=east,half=bottom]")
+        );
+        assert_eq!(state_key(&json!({"Name": "minecraft:stone", "Extra": 1})), None);
+        assert_eq!(state_key(&json!({"Name": "minecraft:stone", "Properties": {"level": 1}})), None);
+    }
+
+    #[test]
+    fn test_palette_ids_roundtrip() {
+        let original = chunk();
+        let mut doc = original.clone();
+        let ids = HashMap::from([
+            ("minecraft:stone".to_string(), 1),
+            ("minecraft:oak_stairs[facing=east,half=bottom]".to_string(), 2),
+        ]);
+        replace_entries(&mut doc, &ids);
+        assert_eq!(doc["sections"][0]["block_states"]["palette"], json!([1, 2]));
+
+        let palette = original["sections"][0]["block_states"]["palette"].as_array().unwrap();
+        let entries = HashMap::from([(1, palette[0].clone()), (2, palette[1].clone())]);
+        replace_ids(&mut doc, &entries).unwrap();
+        assert_eq!(doc, original);
+
+        // Inline palettes from before the registry load unchanged
+        let mut legacy = original.clone();
+        replace_ids(&mut legacy, &HashMap::new()).unwrap();
+        assert_eq!(legacy, original);
+    }
+}
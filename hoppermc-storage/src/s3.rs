@@ -0,0 +1,716 @@
+// S3-compatible object storage (AWS S3, MinIO, Cloudflare R2, ...), behind the `s3` feature.
+//
+// Two key layouts:
+// - `Chunk`: one object per chunk (raw NBT). Cheap writes, region loads list the region prefix.
+// - `Region`: one packed object per region holding all its stored chunks. One GET per
+//   region load; a save rewrites the pack, so it suits worlds that are mostly read.
+//
+// Region packs are written conditionally on the ETag they were read with, so two HopperMC
+// instances sharing a bucket never drop each other's chunks: a conflicting write re-reads
+// the pack, re-applies the chunk and tries again. Packs above the multipart threshold are
+// uploaded in parts. Fetched objects (and misses) are kept in a bounded local read cache
+// for `cache_ttl`; after that an object is revalidated with a conditional GET on its ETag
+// and a miss is looked up again, so other instances' writes become visible.
+// Listing walks the bucket under the key template's fixed prefix and parses keys back into
+// coordinates; in the region layout each pack in range is fetched to read its chunk list.
+
+use crate::{ChunkKeyStream, ChunkStorage};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{GetOptions, ObjectStore, PutMode, PutOptions, PutPayload, UpdateVersion, WriteMultipart};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const PACK_MAGIC: &[u8; 8] = b"HMCPACK1";
+// S3 requires parts of at least 5 MiB (except the last)
+const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+// Attempts at a conditional pack write before giving up
+const MAX_CONFLICT_RETRIES: usize = 5;
+// Bookkeeping cost of a read cache entry besides its data, so misses count against the budget
+const CACHE_ENTRY_OVERHEAD: u64 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum S3Layout {
+    /// One object per chunk.
+    Chunk,
+    /// One packed object per 32x32 region.
+    Region,
+}
+
+impl S3Layout {
+    pub fn default_template(self) -> &'static str {
+        match self {
+            S3Layout::Chunk => "chunks/r.{rx}.{rz}/c.{x}.{z}.nbt",
+            S3Layout::Region => "regions/r.{rx}.{rz}.pack",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    pub bucket: String,
+    /// Custom endpoint (MinIO, R2, ...). None = AWS.
+    pub endpoint: Option<String>,
+    pub region: String,
+    /// Key prefix shared by every object (e.g. a world name).
+    pub prefix: String,
+    pub layout: S3Layout,
+    /// Key template with `{x}`, `{z}` (chunk) and `{rx}`, `{rz}` (region) placeholders.
+    /// None = the layout's default.
+    pub key_template: Option<String>,
+    /// Local read cache budget (bytes).
+    pub cache_bytes: u64,
+    /// How long a cached object or miss is served before checking the bucket again.
+    pub cache_ttl: Duration,
+    /// Packs at least this large are uploaded with multipart upload (bytes).
+    pub multipart_threshold: usize,
+}
+
+impl S3Config {
+    pub fn new(bucket: &str) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            endpoint: None,
+            region: "us-east-1".to_string(),
+            prefix: String::new(),
+            layout: S3Layout::Region,
+            key_template: None,
+            cache_bytes: 64 * 1024 * 1024,
+            cache_ttl: Duration::from_secs(5),
+            multipart_threshold: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// A fetched object (or a miss), with the version it was read at.
+#[derive(Clone)]
+struct Cached {
+    data: Option<Bytes>,
+    e_tag: Option<String>,
+    version: Option<String>,
+    fetched_at: Instant,
+}
+
+impl Cached {
+    fn new(data: Option<Bytes>, e_tag: Option<String>, version: Option<String>) -> Self {
+        Self { data, e_tag, version, fetched_at: Instant::now() }
+    }
+
+    fn size(&self) -> u64 {
+        CACHE_ENTRY_OVERHEAD + self.data.as_ref().map(|d| d.len() as u64).unwrap_or(0)
+    }
+}
+
+/// Bounded read cache; evicts the oldest entries first.
+struct ReadCache {
+    entries: HashMap<Path, Cached>,
+    order: VecDeque<Path>,
+    bytes: u64,
+    max_bytes: u64,
+}
+
+impl ReadCache {
+    fn get(&self, key: &Path) -> Option<Cached> {
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: Path, value: Cached) {
+        self.remove(&key);
+        self.bytes += value.size();
+        self.entries.insert(key.clone(), value);
+        self.order.push_back(key);
+        while self.bytes > self.max_bytes {
+            let Some(oldest) = self.order.pop_front() else { break };
+            if let Some(old) = self.entries.remove(&oldest) {
+                self.bytes -= old.size();
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &Path) {
+        if let Some(old) = self.entries.remove(key) {
+            self.bytes -= old.size();
+            self.order.retain(|k| k != key);
+        }
+    }
+}
+
+fn render(template: &str, x: i32, z: i32) -> String {
+    template
+        .replace("{rx}", &(x >> 5).to_string())
+        .replace("{rz}", &(z >> 5).to_string())
+        .replace("{x}", &x.to_string())
+        .replace("{z}", &z.to_string())
+}
+
+/// Inverse of `render`: the placeholder values in `key`, if it was rendered from `template`.
+fn parse_key(template: &str, key: &str) -> Option<HashMap<&'static str, i32>> {
+    let (mut template, mut key) = (template, key);
+    let mut values = HashMap::new();
+    while !template.is_empty() {
+        if let Some(name) = ["{rx}", "{rz}", "{x}", "{z}"].into_iter().find(|name| template.starts_with(name)) {
+            let len = key.char_indices().take_while(|(i, c)| c.is_ascii_digit() || (*i == 0 && *c == '-')).count();
+            let value: i32 = key[..len].parse().ok()?;
+            if values.insert(name, value).is_some_and(|old| old != value) {
+                return None;
+            }
+            (template, key) = (&template[name.len()..], &key[len..]);
+        } else {
+            let c = template.chars().next()?;
+            key = key.strip_prefix(c)?;
+            template = &template[c.len_utf8()..];
+        }
+    }
+    key.is_empty().then_some(values)
+}
+
+fn encode_pack(chunks: &BTreeMap<u16, Vec<u8>>) -> Bytes {
+    let data_len: usize = chunks.values().map(|d| d.len()).sum();
+    let mut out = Vec::with_capacity(PACK_MAGIC.len() + 2 + chunks.len() * 6 + data_len);
+    out.extend_from_slice(PACK_MAGIC);
+    out.extend_from_slice(&(chunks.len() as u16).to_le_bytes());
+    for (index, data) in chunks {
+        out.extend_from_slice(&index.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    }
+    for data in chunks.values() {
+        out.extend_from_slice(data);
+    }
+    Bytes::from(out)
+}
+
+/// Pack layout: magic, u16 count, `count` x (u16 index, u32 length), then the chunk data in index order.
+fn decode_pack(pack: &[u8]) -> Result<BTreeMap<u16, Vec<u8>>> {
+    let header_end = PACK_MAGIC.len() + 2;
+    if pack.len() < header_end || &pack[..PACK_MAGIC.len()] != PACK_MAGIC {
+        anyhow::bail!("Not a region pack");
+    }
+    let count = u16::from_le_bytes([pack[8], pack[9]]) as usize;
+    let mut data_pos = header_end + count * 6;
+    if pack.len() < data_pos {
+        anyhow::bail!("Region pack index truncated");
+    }
+
+    let mut chunks = BTreeMap::new();
+    for entry in pack[header_end..header_end + count * 6].chunks_exact(6) {
+        let index = u16::from_le_bytes([entry[0], entry[1]]);
+        let len = u32::from_le_bytes([entry[2], entry[3], entry[4], entry[5]]) as usize;
+        let data = pack.get(data_pos..data_pos + len).context("Region pack data truncated")?;
+        chunks.insert(index, data.to_vec());
+        data_pos += len;
+    }
+    Ok(chunks)
+}
+
+fn is_conflict(e: &object_store::Error) -> bool {
+    matches!(e, object_store::Error::Precondition { .. } | object_store::Error::AlreadyExists { .. })
+}
+
+type RegionLock = Arc<tokio::sync::Mutex<()>>;
+
+pub struct S3Storage {
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<Path>,
+    layout: S3Layout,
+    template: String,
+    multipart_threshold: usize,
+    cache: Mutex<ReadCache>,
+    cache_ttl: Duration,
+    // Serialises pack rewrites of the same region within this process
+    region_locks: Mutex<HashMap<(i32, i32), RegionLock>>,
+}
+
+impl S3Storage {
+    /// Connect to the bucket in `config`. Credentials come from the usual `AWS_*` environment variables.
+    pub fn new(config: S3Config) -> Result<Self> {
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(&config.bucket).with_region(&config.region);
+        if let Some(endpoint) = &config.endpoint {
+            builder = builder.with_endpoint(endpoint).with_allow_http(endpoint.starts_with("http://"));
+        }
+        let store = builder.build().with_context(|| format!("Failed to configure S3 bucket {}", config.bucket))?;
+        log::info!("S3 storage: bucket {} ({:?} layout)", config.bucket, config.layout);
+        Self::with_store(Arc::new(store), config)
+    }
+
+    /// Use an existing object store (any `object_store` backend, e.g. in-memory for tests).
+    pub fn with_store(store: Arc<dyn ObjectStore>, config: S3Config) -> Result<Self> {
+        let template = config.key_template.clone().unwrap_or_else(|| config.layout.default_template().to_string());
+        let required: &[&str] = match config.layout {
+            S3Layout::Chunk => &["{x}", "{z}"],
+            S3Layout::Region => &["{rx}", "{rz}"],
+        };
+        if let Some(missing) = required.iter().find(|p| !template.contains(**p)) {
+            anyhow::bail!("S3 key template {:?} is missing {} for the {:?} layout", template, missing, config.layout);
+        }
+
+        let prefix = config.prefix.trim_matches('/');
+        Ok(Self {
+            store,
+            prefix: (!prefix.is_empty()).then(|| Path::from(prefix)),
+            layout: config.layout,
+            template,
+            multipart_threshold: config.multipart_threshold,
+            cache: Mutex::new(ReadCache { entries: HashMap::new(), order: VecDeque::new(), bytes: 0, max_bytes: config.cache_bytes }),
+            cache_ttl: config.cache_ttl,
+            region_locks: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn path(&self, key: &str) -> Path {
+        match &self.prefix {
+            Some(prefix) => Path::from(format!("{}/{}", prefix, key)),
+            None => Path::from(key),
+        }
+    }
+
+    /// Object key for chunk (x, z) (for the region layout: the pack holding it).
+    fn key(&self, x: i32, z: i32) -> Path {
+        self.path(&render(&self.template, x, z))
+    }
+
+    /// Directory holding every chunk object of a region (chunk layout), if the template has one:
+    /// the part before the first `{x}`/`{z}`, cut at a `/`, containing `{rx}` and `{rz}`.
+    fn region_prefix(&self, region_x: i32, region_z: i32) -> Option<Path> {
+        let chunk_part = self.template.find("{x}").into_iter().chain(self.template.find("{z}")).min()?;
+        let dir = &self.template[..self.template[..chunk_part].rfind('/')?];
+        if !dir.contains("{rx}") || !dir.contains("{rz}") {
+            return None;
+        }
+        Some(self.path(&render(dir, region_x * 32, region_z * 32)))
+    }
+
+    /// Chunk (chunk layout) or region (region layout) an object holds, if it is one of ours.
+    fn parse_location(&self, location: &Path) -> Option<(i32, i32)> {
+        let key = match &self.prefix {
+            Some(prefix) => location.as_ref().strip_prefix(prefix.as_ref())?.strip_prefix('/')?,
+            None => location.as_ref(),
+        };
+        let values = parse_key(&self.template, key)?;
+        match self.layout {
+            S3Layout::Region => Some((*values.get("{rx}")?, *values.get("{rz}")?)),
+            S3Layout::Chunk => {
+                let (x, z) = (*values.get("{x}")?, *values.get("{z}")?);
+                // An object outside its region's directory would never be loaded
+                let consistent = values.get("{rx}").is_none_or(|rx| *rx == x >> 5) && values.get("{rz}").is_none_or(|rz| *rz == z >> 5);
+                consistent.then_some((x, z))
+            }
+        }
+    }
+
+    /// Deepest key prefix every object shares: the template up to its first placeholder, cut at a `/`.
+    fn list_root(&self) -> Option<Path> {
+        let fixed = &self.template[..self.template.find('{').unwrap_or(self.template.len())];
+        match fixed.rfind('/') {
+            Some(end) => Some(self.path(&fixed[..end])),
+            None => self.prefix.clone(),
+        }
+    }
+
+    async fn fetch(&self, key: &Path) -> Result<Cached> {
+        let cached = self.cache.lock().unwrap().get(key);
+        if let Some(cached) = &cached
+            && cached.fetched_at.elapsed() < self.cache_ttl
+        {
+            return Ok(cached.clone());
+        }
+        // Expired: a cached object is only re-downloaded if its ETag changed
+        let if_none_match = cached.as_ref().and_then(|c| c.data.as_ref().and(c.e_tag.clone()));
+        let cached = match self.store.get_opts(key, GetOptions { if_none_match, ..Default::default() }).await {
+            Ok(result) => {
+                let (e_tag, version) = (result.meta.e_tag.clone(), result.meta.version.clone());
+                Cached::new(Some(result.bytes().await?), e_tag, version)
+            }
+            Err(object_store::Error::NotModified { .. }) if cached.is_some() => {
+                Cached { fetched_at: Instant::now(), ..cached.unwrap() }
+            }
+            Err(object_store::Error::NotFound { .. }) => Cached::new(None, None, None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", key)),
+        };
+        self.cache.lock().unwrap().insert(key.clone(), cached.clone());
+        Ok(cached)
+    }
+
+    /// Write `data` to `key`, only if it is still at the version of `current`.
+    async fn put_conditional(&self, key: &Path, data: Bytes, current: &Cached) -> object_store::Result<object_store::PutResult> {
+        if data.len() >= self.multipart_threshold {
+            // Multipart uploads cannot carry a precondition: check the version right
+            // before uploading (a writer landing in between is not detected).
+            match self.store.head(key).await {
+                Ok(meta) if current.data.is_some() && meta.e_tag == current.e_tag => {}
+                Err(object_store::Error::NotFound { .. }) if current.data.is_none() => {}
+                Ok(_) | Err(object_store::Error::NotFound { .. }) => {
+                    return Err(object_store::Error::Precondition { path: key.to_string(), source: "pack changed".into() });
+                }
+                Err(e) => return Err(e),
+            }
+            let mut upload = WriteMultipart::new_with_chunk_size(self.store.put_multipart(key).await?, MULTIPART_PART_SIZE);
+            upload.put(data);
+            return upload.finish().await;
+        }
+
+        let mode = match (&current.data, &current.e_tag) {
+            (None, _) => PutMode::Create,
+            (Some(_), Some(e_tag)) => PutMode::Update(UpdateVersion { e_tag: Some(e_tag.clone()), version: current.version.clone() }),
+            (Some(_), None) => PutMode::Overwrite,
+        };
+        self.store.put_opts(key, PutPayload::from_bytes(data), PutOptions { mode, ..Default::default() }).await
+    }
+
+    /// Store chunk (x, z) in its region pack, or remove it when `data` is None. Returns
+    /// whether the pack held the chunk before. An emptied pack is kept: deleting it could
+    /// not be made conditional, and would drop a chunk another instance just added.
+    async fn write_pack(&self, x: i32, z: i32, data: Option<&[u8]>) -> Result<bool> {
+        let lock = self.region_locks.lock().unwrap().entry((x >> 5, z >> 5)).or_default().clone();
+        let _guard = lock.lock().await;
+        let key = self.key(x, z);
+        let index = ((x & 31) + (z & 31) * 32) as u16;
+
+        for attempt in 1..=MAX_CONFLICT_RETRIES {
+            let current = self.fetch(&key).await?;
+            let mut chunks = match &current.data {
+                Some(pack) => decode_pack(pack).with_context(|| format!("Corrupt region pack {}", key))?,
+                None => BTreeMap::new(),
+            };
+            let existed = match data {
+                Some(data) => chunks.insert(index, data.to_vec()).is_some(),
+                None => chunks.remove(&index).is_some(),
+            };
+            if data.is_none() && !existed {
+                return Ok(false);
+            }
+            let pack = encode_pack(&chunks);
+
+            match self.put_conditional(&key, pack.clone(), &current).await {
+                Ok(result) => {
+                    self.cache.lock().unwrap().insert(key, Cached::new(Some(pack), result.e_tag, result.version));
+                    return Ok(existed);
+                }
+                Err(e) if is_conflict(&e) => {
+                    log::debug!("Region pack {} changed concurrently (attempt {}), re-reading", key, attempt);
+                    self.cache.lock().unwrap().remove(&key);
+                }
+                Err(e) => return Err(e).with_context(|| format!("Failed to write {}", key)),
+            }
+        }
+        anyhow::bail!("Region pack {} kept changing, gave up after {} attempts", key, MAX_CONFLICT_RETRIES)
+    }
+}
+
+#[async_trait]
+impl ChunkStorage for S3Storage {
+    async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+        match self.layout {
+            S3Layout::Region => self.write_pack(x, z, Some(data)).await.map(|_| ()),
+            S3Layout::Chunk => {
+                let key = self.key(x, z);
+                let bytes = Bytes::copy_from_slice(data);
+                let result = self.store.put(&key, PutPayload::from_bytes(bytes.clone())).await.with_context(|| format!("Failed to write {}", key))?;
+                self.cache.lock().unwrap().insert(key, Cached::new(Some(bytes), result.e_tag, result.version));
+                Ok(())
+            }
+        }
+    }
+
+    async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+        let key = self.key(x, z);
+        let cached = self.fetch(&key).await?;
+        match (self.layout, cached.data) {
+            (_, None) => Ok(None),
+            (S3Layout::Chunk, Some(data)) => Ok(Some(data.to_vec())),
+            (S3Layout::Region, Some(pack)) => {
+                let index = ((x & 31) + (z & 31) * 32) as u16;
+                Ok(decode_pack(&pack).with_context(|| format!("Corrupt region pack {}", key))?.remove(&index))
+            }
+        }
+    }
+
+    async fn load_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        let (base_x, base_z) = (region_x * 32, region_z * 32);
+        if self.layout == S3Layout::Region {
+            let key = self.key(base_x, base_z);
+            let Some(pack) = self.fetch(&key).await?.data else { return Ok(Vec::new()) };
+            let chunks = decode_pack(&pack).with_context(|| format!("Corrupt region pack {}", key))?;
+            return Ok(chunks.into_iter().map(|(i, data)| (base_x + (i % 32) as i32, base_z + (i / 32) as i32, data)).collect());
+        }
+
+        // Chunk layout: list what exists under the region's key prefix, fetch only those
+        let mut wanted = HashMap::with_capacity(1024);
+        for i in 0..1024 {
+            let (x, z) = (base_x + i % 32, base_z + i / 32);
+            wanted.insert(self.key(x, z), (x, z));
+        }
+        let Some(prefix) = self.region_prefix(region_x, region_z) else {
+            // Template has no per-region directory: listing would scan the whole world
+            let mut chunks = Vec::new();
+            for (key, (x, z)) in wanted {
+                if let Some(data) = self.fetch(&key).await?.data {
+                    chunks.push((x, z, data.to_vec()));
+                }
+            }
+            return Ok(chunks);
+        };
+
+        let listed: Vec<_> = self.store.list(Some(&prefix)).try_collect().await.with_context(|| format!("Failed to list {}", prefix))?;
+        let mut chunks = Vec::new();
+        for meta in listed {
+            if let Some((x, z)) = wanted.get(&meta.location)
+                && let Some(data) = self.fetch(&meta.location).await?.data
+            {
+                chunks.push((*x, *z, data.to_vec()));
+            }
+        }
+        Ok(chunks)
+    }
+
+    async fn delete_chunk(&self, x: i32, z: i32) -> Result<bool> {
+        match self.layout {
+            S3Layout::Region => self.write_pack(x, z, None).await,
+            S3Layout::Chunk => {
+                let key = self.key(x, z);
+                let existed = match self.store.head(&key).await {
+                    Ok(_) => true,
+                    Err(object_store::Error::NotFound { .. }) => false,
+                    Err(e) => return Err(e).with_context(|| format!("Failed to read {}", key)),
+                };
+                if existed {
+                    self.store.delete(&key).await.with_context(|| format!("Failed to delete {}", key))?;
+                }
+                self.cache.lock().unwrap().insert(key, Cached::new(None, None, None));
+                Ok(existed)
+            }
+        }
+    }
+
+    fn list_chunks(&self, bounds: Option<((i32, i32), (i32, i32))>) -> ChunkKeyStream<'_> {
+        let inside = move |x: i32, z: i32| bounds.is_none_or(|(min, max)| (min.0..=max.0).contains(&x) && (min.1..=max.1).contains(&z));
+        let root = self.list_root();
+        let listed = self.store.list(root.as_ref())
+            .map_err(move |e| anyhow::Error::from(e).context(format!("Failed to list {}", root.as_ref().map(|p| p.as_ref()).unwrap_or("bucket"))))
+            .try_filter_map(move |meta| futures::future::ok(self.parse_location(&meta.location)));
+        match self.layout {
+            S3Layout::Chunk => listed.try_filter(move |(x, z)| futures::future::ready(inside(*x, *z))).boxed(),
+            S3Layout::Region => listed
+                .try_filter(move |(rx, rz)| {
+                    futures::future::ready(bounds.is_none_or(|(min, max)| (min.0 >> 5..=max.0 >> 5).contains(rx) && (min.1 >> 5..=max.1 >> 5).contains(rz)))
+                })
+                .and_then(move |(rx, rz)| async move {
+                    let keys: Vec<(i32, i32)> = self.load_region(rx, rz).await?.into_iter().map(|(x, z, _)| (x, z)).collect();
+                    Ok(futures::stream::iter(keys.into_iter().filter(move |(x, z)| inside(*x, *z)).map(Ok)))
+                })
+                .try_flatten()
+                .boxed(),
+        }
+    }
+
+    async fn get_total_size(&self) -> Result<u64> {
+        let listed: Vec<_> = self.store.list(self.prefix.as_ref()).try_collect().await?;
+        Ok(listed.iter().map(|meta| meta.size).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    fn storage(store: &Arc<InMemory>, layout: S3Layout) -> S3Storage {
+        let config = S3Config { layout, prefix: "world".to_string(), ..S3Config::new("test") };
+        S3Storage::with_store(store.clone(), config).unwrap()
+    }
+
+    #[test]
+    fn test_keys_and_template_validation() {
+        let store = Arc::new(InMemory::new());
+        let chunks = storage(&store, S3Layout::Chunk);
+        assert_eq!(chunks.key(-1, 33).as_ref(), "world/chunks/r.-1.1/c.-1.33.nbt");
+        assert_eq!(chunks.region_prefix(-1, 1).unwrap().as_ref(), "world/chunks/r.-1.1");
+        assert_eq!(storage(&store, S3Layout::Region).key(-1, 33).as_ref(), "world/regions/r.-1.1.pack");
+
+        let config = S3Config { layout: S3Layout::Chunk, key_template: Some("chunks/{rx}.{rz}".to_string()), ..S3Config::new("test") };
+        assert!(S3Storage::with_store(store, config).is_err());
+    }
+
+    #[test]
+    fn test_pack_roundtrip() {
+        let mut chunks = BTreeMap::new();
+        chunks.insert(0u16, vec![1, 2, 3]);
+        chunks.insert(1023u16, vec![]);
+        let pack = encode_pack(&chunks);
+        assert_eq!(decode_pack(&pack).unwrap(), chunks);
+        assert!(decode_pack(&pack[..pack.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_layouts_roundtrip() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for layout in [S3Layout::Chunk, S3Layout::Region] {
+            let store = Arc::new(InMemory::new());
+            rt.block_on(async {
+                let s3 = storage(&store, layout);
+                s3.save_chunk(1, 2, &[1]).await.unwrap();
+                s3.save_chunk(31, 31, &[2]).await.unwrap();
+                s3.save_chunk(32, 0, &[3]).await.unwrap();
+                s3.save_chunk(1, 2, &[4]).await.unwrap();
+
+                // A fresh instance (empty read cache) sees the same data
+                let fresh = storage(&store, layout);
+                assert_eq!(fresh.load_chunk(1, 2).await.unwrap(), Some(vec![4]));
+                assert_eq!(fresh.load_chunk(0, 0).await.unwrap(), None);
+                let mut region = fresh.load_region(0, 0).await.unwrap();
+                region.sort();
+                assert_eq!(region, vec![(1, 2, vec![4]), (31, 31, vec![2])], "{:?}", layout);
+            });
+        }
+    }
+
+    #[test]
+    fn test_parse_key_inverts_render() {
+        let template = "chunks/r.{rx}.{rz}/c.{x}.{z}.nbt";
+        let values = parse_key(template, &render(template, -33, 64)).unwrap();
+        assert_eq!((values["{x}"], values["{z}"], values["{rx}"], values["{rz}"]), (-33, 64, -2, 2));
+        assert!(parse_key(template, "chunks/r.0.0/c.1.2.nbt.tmp").is_none());
+        assert!(parse_key("{x}/{x}", "1/2").is_none());
+    }
+
+    #[test]
+    fn test_delete_and_list() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for layout in [S3Layout::Chunk, S3Layout::Region] {
+            let store = Arc::new(InMemory::new());
+            rt.block_on(async {
+                let s3 = &storage(&store, layout);
+                for (x, z) in [(1, 2), (31, 31), (32, 0), (-1, -40)] {
+                    s3.save_chunk(x, z, &[1]).await.unwrap();
+                }
+                // Objects of other tools under the same prefix are skipped
+                store.put(&Path::from("world/notes.txt"), PutPayload::from_static(b"hi")).await.unwrap();
+
+                let list = |bounds| async move {
+                    let mut keys: Vec<(i32, i32)> = s3.list_chunks(bounds).try_collect().await.unwrap();
+                    keys.sort();
+                    keys
+                };
+                assert_eq!(list(None).await, vec![(-1, -40), (1, 2), (31, 31), (32, 0)], "{:?}", layout);
+                assert_eq!(list(Some(((0, 0), (31, 31)))).await, vec![(1, 2), (31, 31)], "{:?}", layout);
+
+                assert!(s3.delete_chunk(31, 31).await.unwrap());
+                assert!(!s3.delete_chunk(31, 31).await.unwrap());
+                assert!(!s3.delete_chunk(5, 5).await.unwrap());
+                let fresh = storage(&store, layout);
+                assert_eq!(fresh.load_chunk(31, 31).await.unwrap(), None);
+                assert_eq!(fresh.load_chunk(1, 2).await.unwrap(), Some(vec![1]));
+                assert_eq!(list(Some(((0, 0), (31, 31)))).await, vec![(1, 2)], "{:?}", layout);
+            });
+        }
+    }
+
+    #[test]
+    fn test_concurrent_writer_is_not_overwritten() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let store = Arc::new(InMemory::new());
+        rt.block_on(async {
+            let a = storage(&store, S3Layout::Region);
+            let b = storage(&store, S3Layout::Region);
+            a.save_chunk(0, 0, &[1]).await.unwrap();
+            // b reads the pack, then a changes it behind b's back
+            assert_eq!(b.load_chunk(0, 0).await.unwrap(), Some(vec![1]));
+            a.save_chunk(1, 0, &[2]).await.unwrap();
+
+            // b's write conflicts on the ETag, re-reads and keeps a's chunk
+            b.save_chunk(2, 0, &[3]).await.unwrap();
+            let fresh = storage(&store, S3Layout::Region);
+            assert_eq!(fresh.load_region(0, 0).await.unwrap().len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_cached_misses_are_bounded() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let store = Arc::new(InMemory::new());
+        let config = S3Config { layout: S3Layout::Chunk, cache_bytes: 10 * CACHE_ENTRY_OVERHEAD, ..S3Config::new("test") };
+        let s3 = S3Storage::with_store(store, config).unwrap();
+        rt.block_on(async {
+            for x in 0..100 {
+                assert_eq!(s3.load_chunk(x, 0).await.unwrap(), None);
+            }
+        });
+        let cache = s3.cache.lock().unwrap();
+        assert_eq!(cache.entries.len(), 10);
+        assert!(cache.bytes <= 10 * CACHE_ENTRY_OVERHEAD);
+    }
+
+    #[test]
+    fn test_other_writers_visible_after_ttl() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let store = Arc::new(InMemory::new());
+        rt.block_on(async {
+            for layout in [S3Layout::Chunk, S3Layout::Region] {
+                let config = S3Config { layout, cache_ttl: Duration::from_millis(50), ..S3Config::new("test") };
+                let reader = S3Storage::with_store(store.clone(), config.clone()).unwrap();
+                let writer = S3Storage::with_store(store.clone(), config).unwrap();
+
+                // A cached miss, then a cached object, are each refreshed once the TTL passes
+                assert_eq!(reader.load_chunk(7, 7).await.unwrap(), None);
+                writer.save_chunk(7, 7, &[1]).await.unwrap();
+                assert_eq!(reader.load_chunk(7, 7).await.unwrap(), None);
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                assert_eq!(reader.load_chunk(7, 7).await.unwrap(), Some(vec![1]));
+
+                writer.save_chunk(7, 7, &[2]).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                assert_eq!(reader.load_chunk(7, 7).await.unwrap(), Some(vec![2]));
+                // Unchanged: revalidated without a new download, still served
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                assert_eq!(reader.load_chunk(7, 7).await.unwrap(), Some(vec![2]), "{:?}", layout);
+            }
+        });
+    }
+
+    #[test]
+    fn test_multipart_pack_upload() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let store = Arc::new(InMemory::new());
+        rt.block_on(async {
+            let config = S3Config { multipart_threshold: 1, ..S3Config::new("test") };
+            let s3 = S3Storage::with_store(store.clone(), config.clone()).unwrap();
+            s3.save_chunk(0, 0, &[1]).await.unwrap();
+            s3.save_chunk(0, 1, &[2]).await.unwrap();
+
+            let fresh = S3Storage::with_store(store, config).unwrap();
+            assert_eq!(fresh.load_chunk(0, 1).await.unwrap(), Some(vec![2]));
+            assert_eq!(fresh.load_region(0, 0).await.unwrap().len(), 2);
+        });
+    }
+
+    /// Against a local MinIO standing in for S3:
+    /// `docker compose --profile s3 up -d minio`, then
+    /// `AWS_ACCESS_KEY_ID=minioadmin AWS_SECRET_ACCESS_KEY=minioadmin cargo test -p hoppermc-storage --features s3 -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_minio_roundtrip() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let endpoint = std::env::var("S3_TEST_ENDPOINT").unwrap_or_else(|_| "http://localhost:9000".to_string());
+        let bucket = std::env::var("S3_TEST_BUCKET").unwrap_or_else(|_| "hoppermc".to_string());
+        rt.block_on(async {
+            for layout in [S3Layout::Chunk, S3Layout::Region] {
+                let config = S3Config {
+                    endpoint: Some(endpoint.clone()),
+                    prefix: format!("test-{}", std::process::id()),
+                    layout,
+                    ..S3Config::new(&bucket)
+                };
+                let s3 = S3Storage::new(config.clone()).unwrap();
+                s3.save_chunk(5, -5, &[5]).await.unwrap();
+                s3.save_chunk(5, -5, &[6]).await.unwrap();
+                let fresh = S3Storage::new(config).unwrap();
+                assert_eq!(fresh.load_chunk(5, -5).await.unwrap(), Some(vec![6]));
+                assert_eq!(fresh.load_region(0, -1).await.unwrap(), vec![(5, -5, vec![6])]);
+            }
+        });
+    }
+}
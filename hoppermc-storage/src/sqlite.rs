@@ -0,0 +1,491 @@
+// Embedded SQLite storage: a single-file world database, no server required.
+//
+// The database runs in WAL mode so reads never wait on the writer. All writes go
+// through one dedicated writer thread that groups whatever saves are queued into a
+// single transaction (group commit); reads use a small pool of read-only connections
+// on the blocking thread pool. A multi-chunk save is applied all or nothing inside the
+// group's transaction (under a savepoint); deletes go through the writer as well.
+
+use crate::nbt_json::{json_to_nbt, nbt_to_json};
+use crate::{ChunkKeyStream, ChunkStorage};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+
+// Chunks committed in one transaction at most (a larger save_chunks batch still goes in whole)
+const MAX_WRITE_BATCH: usize = 256;
+// Keys fetched per query while listing
+const LIST_PAGE: usize = 4096;
+// Read-only connections (WAL readers run concurrently)
+const READ_CONNECTIONS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqliteMode {
+    /// Raw NBT blobs (`chunks_raw`).
+    Raw,
+    /// NBT converted to JSON text (`chunks_json`), queryable with SQLite's JSON functions.
+    Json,
+}
+
+impl SqliteMode {
+    fn table(self) -> &'static str {
+        match self {
+            SqliteMode::Raw => "chunks_raw",
+            SqliteMode::Json => "chunks_json",
+        }
+    }
+}
+
+enum WriteOp {
+    /// One or more chunks, saved all or nothing.
+    Save(Vec<(i32, i32, Vec<u8>)>),
+    Delete(i32, i32),
+}
+
+impl WriteOp {
+    fn chunks(&self) -> usize {
+        match self {
+            WriteOp::Save(chunks) => chunks.len(),
+            WriteOp::Delete(..) => 1,
+        }
+    }
+}
+
+struct WriteRequest {
+    op: WriteOp,
+    /// Rows changed
+    done: tokio::sync::oneshot::Sender<Result<usize>>,
+}
+
+pub struct SqliteStorage {
+    path: PathBuf,
+    mode: SqliteMode,
+    writer: Mutex<Option<mpsc::Sender<WriteRequest>>>,
+    writer_thread: Mutex<Option<JoinHandle<()>>>,
+    readers: Arc<Vec<Mutex<Connection>>>,
+    next_reader: AtomicUsize,
+}
+
+fn open_connection(path: &Path, flags: OpenFlags) -> Result<Connection> {
+    let conn = Connection::open_with_flags(path, flags).with_context(|| format!("Failed to open SQLite database {:?}", path))?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    Ok(conn)
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path, mode: SqliteMode) -> Result<Self> {
+        let writer_conn = open_connection(path, OpenFlags::default())?;
+        writer_conn.pragma_update(None, "journal_mode", "WAL")?;
+        // WAL + NORMAL: a commit survives a process crash; only an OS crash can lose the last ones
+        writer_conn.pragma_update(None, "synchronous", "NORMAL")?;
+        writer_conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                x INTEGER NOT NULL,
+                z INTEGER NOT NULL,
+                data {} NOT NULL,
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                PRIMARY KEY (x, z)
+            ) WITHOUT ROWID;",
+            mode.table(),
+            if mode == SqliteMode::Raw { "BLOB" } else { "TEXT" }
+        )).context("Failed to init SQLite schema")?;
+
+        let mut readers = Vec::with_capacity(READ_CONNECTIONS);
+        for _ in 0..READ_CONNECTIONS {
+            let conn = open_connection(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+            readers.push(Mutex::new(conn));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let writer_thread = std::thread::Builder::new()
+            .name("hoppermc-sqlite-writer".to_string())
+            .spawn(move || run_writer(writer_conn, mode, rx))?;
+
+        log::info!("SQLite storage at {:?} ({:?} mode, WAL)", path, mode);
+        Ok(Self {
+            path: path.to_path_buf(),
+            mode,
+            writer: Mutex::new(Some(tx)),
+            writer_thread: Mutex::new(Some(writer_thread)),
+            readers: Arc::new(readers),
+            next_reader: AtomicUsize::new(0),
+        })
+    }
+
+    /// Run `f` on one of the read connections, on the blocking thread pool.
+    async fn read<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection, SqliteMode) -> Result<T> + Send + 'static,
+    {
+        let readers = self.readers.clone();
+        let index = self.next_reader.fetch_add(1, Ordering::Relaxed) % readers.len();
+        let mode = self.mode;
+        tokio::task::spawn_blocking(move || {
+            let conn = readers[index].lock().unwrap();
+            f(&conn, mode)
+        })
+        .await?
+    }
+
+    /// Queue a write for the writer thread and wait for its commit.
+    async fn write(&self, op: WriteOp) -> Result<usize> {
+        let (done, result) = tokio::sync::oneshot::channel();
+        {
+            let writer = self.writer.lock().unwrap();
+            let writer = writer.as_ref().ok_or_else(|| anyhow::anyhow!("SQLite storage is closed"))?;
+            writer.send(WriteRequest { op, done }).map_err(|_| anyhow::anyhow!("SQLite writer thread stopped"))?;
+        }
+        result.await.context("SQLite writer thread stopped")?
+    }
+}
+
+fn encode(mode: SqliteMode, x: i32, z: i32, data: &[u8]) -> Result<rusqlite::types::Value> {
+    Ok(match mode {
+        SqliteMode::Raw => rusqlite::types::Value::Blob(data.to_vec()),
+        SqliteMode::Json => {
+            let nbt = fastnbt::from_bytes::<fastnbt::Value>(data)
+                .map_err(|e| anyhow::anyhow!("Failed to parse NBT for ({}, {}): {:?}", x, z, e))?;
+            rusqlite::types::Value::Text(nbt_to_json(nbt).to_string())
+        }
+    })
+}
+
+fn decode(mode: SqliteMode, x: i32, z: i32, value: rusqlite::types::Value) -> Result<Vec<u8>> {
+    match (mode, value) {
+        (SqliteMode::Raw, rusqlite::types::Value::Blob(data)) => Ok(data),
+        (SqliteMode::Json, rusqlite::types::Value::Text(text)) => {
+            let json: serde_json::Value = serde_json::from_str(&text)?;
+            fastnbt::to_bytes(&json_to_nbt(json)).map_err(|e| anyhow::anyhow!("Failed to encode NBT for ({}, {}): {:?}", x, z, e))
+        }
+        (mode, _) => anyhow::bail!("Unexpected column type for ({}, {}) in {:?} mode", x, z, mode),
+    }
+}
+
+/// Apply one request inside the group's transaction. A multi-chunk save runs under a
+/// savepoint, so a chunk that fails rolls back the rest of its batch only.
+fn apply(tx: &rusqlite::Transaction, mode: SqliteMode, op: &WriteOp) -> Result<usize> {
+    let table = mode.table();
+    match op {
+        WriteOp::Save(chunks) => {
+            let mut stmt = tx.prepare_cached(&format!(
+                "INSERT INTO {} (x, z, data, updated_at) VALUES (?1, ?2, ?3, strftime('%s', 'now'))
+                 ON CONFLICT (x, z) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+                table
+            ))?;
+            let atomic = chunks.len() > 1;
+            if atomic {
+                tx.execute_batch("SAVEPOINT save_chunks")?;
+            }
+            let saved = chunks.iter().try_fold(0, |saved, (x, z, data)| {
+                let value = encode(mode, *x, *z, data)?;
+                Ok::<_, anyhow::Error>(saved + stmt.execute(params![x, z, value])?)
+            });
+            if atomic {
+                tx.execute_batch(if saved.is_ok() { "RELEASE save_chunks" } else { "ROLLBACK TO save_chunks; RELEASE save_chunks" })?;
+            }
+            saved
+        }
+        WriteOp::Delete(x, z) => {
+            let mut stmt = tx.prepare_cached(&format!("DELETE FROM {} WHERE x = ?1 AND z = ?2", table))?;
+            Ok(stmt.execute(params![x, z])?)
+        }
+    }
+}
+
+/// Writer thread: block for one write, drain whatever else is queued, commit them together.
+fn run_writer(mut conn: Connection, mode: SqliteMode, rx: mpsc::Receiver<WriteRequest>) {
+    while let Ok(first) = rx.recv() {
+        let mut chunks = first.op.chunks();
+        let mut batch = vec![first];
+        while chunks < MAX_WRITE_BATCH {
+            match rx.try_recv() {
+                Ok(req) => {
+                    chunks += req.op.chunks();
+                    batch.push(req);
+                }
+                Err(_) => break,
+            }
+        }
+
+        let mut results: Vec<Result<usize>> = Vec::with_capacity(batch.len());
+        let committed = (|| -> Result<()> {
+            let tx = conn.transaction()?;
+            for req in &batch {
+                results.push(apply(&tx, mode, &req.op));
+            }
+            tx.commit()?;
+            Ok(())
+        })();
+
+        if let Err(e) = &committed {
+            log::error!("SQLite batch of {} chunks failed to commit: {:?}", chunks, e);
+        }
+        for (i, req) in batch.into_iter().enumerate() {
+            let res = match (&committed, results.get_mut(i)) {
+                (Err(e), _) => Err(anyhow::anyhow!("SQLite commit failed: {}", e)),
+                (Ok(()), Some(res)) => std::mem::replace(res, Ok(0)),
+                (Ok(()), None) => Err(anyhow::anyhow!("SQLite batch aborted")),
+            };
+            let _ = req.done.send(res);
+        }
+    }
+}
+
+#[async_trait]
+impl ChunkStorage for SqliteStorage {
+    async fn save_chunk(&self, x: i32, z: i32, data: &[u8]) -> Result<()> {
+        self.write(WriteOp::Save(vec![(x, z, data.to_vec())])).await.map(|_| ())
+    }
+
+    async fn save_chunks(&self, chunks: &[(i32, i32, Vec<u8>)]) -> Result<()> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        self.write(WriteOp::Save(chunks.to_vec())).await.map(|_| ())
+    }
+
+    async fn delete_chunk(&self, x: i32, z: i32) -> Result<bool> {
+        Ok(self.write(WriteOp::Delete(x, z)).await? > 0)
+    }
+
+    async fn load_chunk(&self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+        self.read(move |conn, mode| {
+            let mut stmt = conn.prepare_cached(&format!("SELECT data FROM {} WHERE x = ?1 AND z = ?2", mode.table()))?;
+            let value: Option<rusqlite::types::Value> = stmt.query_row(params![x, z], |row| row.get(0)).optional()?;
+            value.map(|v| decode(mode, x, z, v)).transpose()
+        })
+        .await
+    }
+
+    async fn load_region(&self, region_x: i32, region_z: i32) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        self.read(move |conn, mode| {
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT x, z, data FROM {} WHERE x BETWEEN ?1 AND ?2 AND z BETWEEN ?3 AND ?4",
+                mode.table()
+            ))?;
+            let rows = stmt.query_map(
+                params![region_x * 32, region_x * 32 + 31, region_z * 32, region_z * 32 + 31],
+                |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?, row.get::<_, rusqlite::types::Value>(2)?)),
+            )?;
+
+            let mut chunks = Vec::new();
+            for row in rows {
+                let (x, z, value) = row?;
+                match decode(mode, x, z, value) {
+                    Ok(data) => chunks.push((x, z, data)),
+                    Err(e) => log::error!("{:?}", e),
+                }
+            }
+            Ok(chunks)
+        })
+        .await
+    }
+
+    async fn load_chunks(&self, coords: &[(i32, i32)]) -> Result<Vec<(i32, i32, Vec<u8>)>> {
+        if coords.is_empty() {
+            return Ok(Vec::new());
+        }
+        // One query: the keys go in as a JSON array of [x, z] pairs and are joined on the primary key
+        let keys = serde_json::to_string(coords)?;
+        self.read(move |conn, mode| {
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT c.x, c.z, c.data FROM json_each(?1) AS k
+                 JOIN {} AS c ON c.x = json_extract(k.value, '$[0]') AND c.z = json_extract(k.value, '$[1]')",
+                mode.table()
+            ))?;
+            let rows = stmt.query_map(params![keys], |row| {
+                Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?, row.get::<_, rusqlite::types::Value>(2)?))
+            })?;
+
+            let mut chunks = Vec::new();
+            for row in rows {
+                let (x, z, value) = row?;
+                match decode(mode, x, z, value) {
+                    Ok(data) => chunks.push((x, z, data)),
+                    Err(e) => log::error!("{:?}", e),
+                }
+            }
+            Ok(chunks)
+        })
+        .await
+    }
+
+    async fn exists(&self, x: i32, z: i32) -> Result<bool> {
+        self.read(move |conn, mode| {
+            let mut stmt = conn.prepare_cached(&format!("SELECT 1 FROM {} WHERE x = ?1 AND z = ?2", mode.table()))?;
+            Ok(stmt.exists(params![x, z])?)
+        })
+        .await
+    }
+
+    fn list_chunks(&self, bounds: Option<((i32, i32), (i32, i32))>) -> ChunkKeyStream<'_> {
+        let (min, max) = bounds.unwrap_or(((i32::MIN, i32::MIN), (i32::MAX, i32::MAX)));
+        // Paged by primary key, so a read connection is only held for one page at a time
+        futures::stream::try_unfold(Some((i64::MIN, i64::MIN)), move |after| async move {
+            let Some(after) = after else { return Ok::<_, anyhow::Error>(None) };
+            let page = self
+                .read(move |conn, mode| {
+                    let mut stmt = conn.prepare_cached(&format!(
+                        "SELECT x, z FROM {} WHERE (x, z) > (?1, ?2) AND x BETWEEN ?3 AND ?4 AND z BETWEEN ?5 AND ?6
+                         ORDER BY x, z LIMIT ?7",
+                        mode.table()
+                    ))?;
+                    let rows = stmt.query_map(params![after.0, after.1, min.0, max.0, min.1, max.1, LIST_PAGE as i64], |row| {
+                        Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?))
+                    })?;
+                    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+                })
+                .await?;
+            let next = (page.len() == LIST_PAGE).then(|| page.last().map(|&(x, z)| (x as i64, z as i64))).flatten();
+            Ok(Some((futures::stream::iter(page.into_iter().map(Ok)), next)))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn get_total_size(&self) -> Result<u64> {
+        let wal = PathBuf::from(format!("{}-wal", self.path.display()));
+        let size = |p: &Path| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+        Ok(size(&self.path) + size(&wal))
+    }
+
+    async fn close(&self) -> Result<()> {
+        // Dropping the sender lets the writer drain its queue and exit
+        drop(self.writer.lock().unwrap().take());
+        let handle = self.writer_thread.lock().unwrap().take();
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            if let Some(handle) = handle {
+                handle.join().map_err(|_| anyhow::anyhow!("SQLite writer thread panicked"))?;
+            }
+            // Fold the WAL back into the main file so the world is a single file again
+            let conn = open_connection(&path, OpenFlags::default())?;
+            conn.pragma_update(None, "wal_checkpoint", "TRUNCATE")?;
+            Ok(())
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("hoppermc-sqlite-{}-{}.sqlite3", name, std::process::id()));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        path
+    }
+
+    #[test]
+    fn test_raw_roundtrip_and_region() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let path = temp_db("raw");
+        rt.block_on(async {
+            let storage = SqliteStorage::open(&path, SqliteMode::Raw).unwrap();
+            storage.save_chunk(1, 2, &[1, 2, 3]).await.unwrap();
+            storage.save_chunk(1, 2, &[4]).await.unwrap();
+            storage.save_chunk(40, 2, &[5]).await.unwrap();
+
+            assert_eq!(storage.load_chunk(1, 2).await.unwrap(), Some(vec![4]));
+            assert_eq!(storage.load_chunk(0, 0).await.unwrap(), None);
+            assert_eq!(storage.load_region(0, 0).await.unwrap(), vec![(1, 2, vec![4])]);
+            storage.close().await.unwrap();
+        });
+
+        // Reopening sees the committed data
+        rt.block_on(async {
+            let storage = SqliteStorage::open(&path, SqliteMode::Raw).unwrap();
+            assert_eq!(storage.load_chunk(40, 2).await.unwrap(), Some(vec![5]));
+        });
+    }
+
+    #[test]
+    fn test_concurrent_saves_are_grouped() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let path = temp_db("batch");
+        rt.block_on(async {
+            let storage = Arc::new(SqliteStorage::open(&path, SqliteMode::Raw).unwrap());
+            let mut tasks = tokio::task::JoinSet::new();
+            for i in 0..200 {
+                let storage = storage.clone();
+                tasks.spawn(async move { storage.save_chunk(i, -i, &[i as u8]).await });
+            }
+            while let Some(res) = tasks.join_next().await {
+                res.unwrap().unwrap();
+            }
+            assert_eq!(storage.load_chunk(199, -199).await.unwrap(), Some(vec![199]));
+        });
+    }
+
+    #[test]
+    fn test_batches_exists_delete_and_list() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let path = temp_db("batches");
+        rt.block_on(async {
+            let storage = SqliteStorage::open(&path, SqliteMode::Raw).unwrap();
+            // More than a listing page, in one transaction
+            let chunks: Vec<_> = (0..LIST_PAGE as i32 + 10).map(|i| (i % 100, i / 100, vec![i as u8])).collect();
+            storage.save_chunks(&chunks).await.unwrap();
+
+            let mut loaded = storage.load_chunks(&[(5, 0), (7, 3), (-1, -1)]).await.unwrap();
+            loaded.sort();
+            assert_eq!(loaded, vec![(5, 0, vec![5]), (7, 3, vec![51])]);
+            assert!(storage.exists(99, 2).await.unwrap());
+            assert!(!storage.exists(100, 0).await.unwrap());
+
+            assert!(storage.delete_chunk(99, 2).await.unwrap());
+            assert!(!storage.delete_chunk(99, 2).await.unwrap());
+            assert!(!storage.exists(99, 2).await.unwrap());
+
+            let all: Vec<_> = storage.list_chunks(None).try_collect().await.unwrap();
+            assert_eq!(all.len(), chunks.len() - 1);
+            assert!(all.windows(2).all(|w| w[0] < w[1]));
+            let area: Vec<_> = storage.list_chunks(Some(((98, 1), (99, 3)))).try_collect().await.unwrap();
+            assert_eq!(area, vec![(98, 1), (98, 2), (98, 3), (99, 1), (99, 3)]);
+        });
+    }
+
+    #[test]
+    fn test_failed_batch_saves_nothing() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let path = temp_db("atomic");
+        let nbt = fastnbt::to_bytes(&fastnbt::Value::Compound(std::collections::HashMap::new())).unwrap();
+        rt.block_on(async {
+            let storage = SqliteStorage::open(&path, SqliteMode::Json).unwrap();
+            // The second chunk is not NBT, so JSON mode cannot encode it
+            let batch = vec![(0, 0, nbt.clone()), (1, 0, vec![0xff])];
+            assert!(storage.save_chunks(&batch).await.is_err());
+            assert!(!storage.exists(0, 0).await.unwrap());
+
+            storage.save_chunk(2, 0, &nbt).await.unwrap();
+            assert!(storage.exists(2, 0).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_json_mode_roundtrip() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let path = temp_db("json");
+        let mut root = std::collections::HashMap::new();
+        root.insert("xPos".to_string(), fastnbt::Value::Int(3));
+        let nbt = fastnbt::to_bytes(&fastnbt::Value::Compound(root)).unwrap();
+
+        rt.block_on(async {
+            let storage = SqliteStorage::open(&path, SqliteMode::Json).unwrap();
+            storage.save_chunk(3, 0, &nbt).await.unwrap();
+            let loaded = storage.load_chunk(3, 0).await.unwrap().unwrap();
+            let value: fastnbt::Value = fastnbt::from_bytes(&loaded).unwrap();
+            let fastnbt::Value::Compound(map) = value else { panic!("not a compound") };
+            assert_eq!(map.get("xPos").and_then(|v| v.as_i64()), Some(3));
+        });
+    }
+}
@@ -0,0 +1,102 @@
+// Worlds sharing one Postgres database.
+//
+// Every chunk table is keyed by (world_id, dimension, x, z), so many servers and
+// dimensions can live in one database. A world is a row of `worlds` holding the
+// generator settings it was created with; a mount picks one world and one dimension
+// and only ever sees that slice. Rows from before worlds existed belong to world 1,
+// `default`, whose settings are recorded by the first mount that opens it.
+// Table: migrations/worlds/0001_worlds.sql.
+
+use anyhow::{Context, Result};
+use std::time::SystemTime;
+
+/// World mounted when none is configured (and owner of pre-world rows).
+pub const DEFAULT_WORLD: &str = "default";
+/// Dimension mounted when none is configured (and dimension of pre-world rows).
+pub const DEFAULT_DIMENSION: &str = "overworld";
+
+const COLUMNS: &str = "id, name, generator, seed, created_at";
+
+/// The slice of the chunk tables a storage handle reads and writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorldKey {
+    pub world_id: i32,
+    pub dimension: String,
+}
+
+impl Default for WorldKey {
+    fn default() -> Self {
+        Self { world_id: 1, dimension: DEFAULT_DIMENSION.to_string() }
+    }
+}
+
+/// A row of `worlds`.
+#[derive(Debug, Clone)]
+pub struct WorldInfo {
+    pub id: i32,
+    pub name: String,
+    /// Generator name ("flat", "vanilla"); None until a mount records it.
+    pub generator: Option<String>,
+    pub seed: Option<u64>,
+    pub created_at: SystemTime,
+}
+
+impl WorldInfo {
+    fn from_row(row: &tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            name: row.get(1),
+            generator: row.get(2),
+            seed: row.get::<_, Option<i64>>(3).map(|seed| seed as u64),
+            created_at: row.get(4),
+        }
+    }
+}
+
+pub(crate) fn validate_name(name: &str) -> Result<()> {
+    anyhow::ensure!(!name.trim().is_empty(), "World name must not be empty");
+    Ok(())
+}
+
+pub(crate) async fn list(client: &tokio_postgres::Client) -> Result<Vec<WorldInfo>> {
+    let rows = client.query(format!("SELECT {} FROM worlds ORDER BY id", COLUMNS).as_str(), &[])
+        .await.context("Failed to list worlds")?;
+    Ok(rows.iter().map(WorldInfo::from_row).collect())
+}
+
+/// World `name`, if it exists. None as well on a database not migrated to worlds yet.
+pub(crate) async fn find(client: &tokio_postgres::Client, name: &str) -> Result<Option<WorldInfo>> {
+    let tracked: bool = client.query_one("SELECT to_regclass('worlds') IS NOT NULL", &[])
+        .await.context("Failed to look up worlds")?.get(0);
+    if !tracked {
+        return Ok(None);
+    }
+    let row = client.query_opt(format!("SELECT {} FROM worlds WHERE name = $1", COLUMNS).as_str(), &[&name])
+        .await.context("Failed to read worlds")?;
+    Ok(row.as_ref().map(WorldInfo::from_row))
+}
+
+/// Create world `name`. Fails if it already exists.
+pub(crate) async fn create(client: &tokio_postgres::Client, name: &str, generator: &str, seed: u64) -> Result<WorldInfo> {
+    validate_name(name)?;
+    let row = client.query_opt(
+        format!("INSERT INTO worlds (name, generator, seed) VALUES ($1, $2, $3) ON CONFLICT (name) DO NOTHING RETURNING {}", COLUMNS).as_str(),
+        &[&name, &generator, &(seed as i64)],
+    ).await.context("Failed to create world")?;
+    row.as_ref().map(WorldInfo::from_row).with_context(|| format!("World '{}' already exists", name))
+}
+
+/// World `name`, created with `generator` / `seed` if missing. A world without recorded
+/// settings (`default` on an older database) adopts them.
+pub(crate) async fn open(client: &tokio_postgres::Client, name: &str, generator: &str, seed: u64) -> Result<WorldInfo> {
+    validate_name(name)?;
+    client.execute(
+        "INSERT INTO worlds (name, generator, seed) VALUES ($1, $2, $3) ON CONFLICT (name) DO NOTHING",
+        &[&name, &generator, &(seed as i64)],
+    ).await.context("Failed to create world")?;
+    client.execute(
+        "UPDATE worlds SET generator = $2, seed = $3 WHERE name = $1 AND generator IS NULL",
+        &[&name, &generator, &(seed as i64)],
+    ).await.context("Failed to record world settings")?;
+    find(client, name).await?.with_context(|| format!("World '{}' vanished while opening it", name))
+}
@@ -0,0 +1,158 @@
+// zstd compression of `pg_raw` payloads with trained dictionaries.
+//
+// Chunk NBT repeats the same tag names, palettes and heightmap layouts in every
+// chunk, which per-row compression (TOAST's pglz) cannot exploit. A zstd dictionary
+// trained on a sample of the world carries those shared parts, so each row only
+// stores what is specific to it.
+//
+// Dictionaries are versioned in `zstd_dictionaries` and never deleted: every row
+// records the dictionary it was compressed with (`dict_id`), so training a new one
+// only affects later saves. `dict_id` NULL is plain NBT (rows from before
+// compression), `NO_DICTIONARY` is zstd without a dictionary (before the first training).
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, RwLock};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+/// zstd level used when none is configured.
+pub const DEFAULT_LEVEL: i32 = 3;
+/// zstd's own default dictionary size.
+pub const DEFAULT_DICT_SIZE: usize = 112_640;
+/// `dict_id` of rows compressed without a dictionary.
+pub const NO_DICTIONARY: i32 = 0;
+
+/// Metadata of a stored dictionary.
+#[derive(Debug, Clone)]
+pub struct DictionaryInfo {
+    pub id: i32,
+    pub created_at: std::time::SystemTime,
+    /// Chunks it was trained on.
+    pub samples: i32,
+    pub size: i32,
+}
+
+/// Id and prepared dictionary used for new saves (None before the first training).
+type CurrentDictionary = RwLock<Option<(i32, Arc<EncoderDictionary<'static>>)>>;
+
+pub struct ZstdCodec {
+    /// Compression level for saves; None stores plain NBT (existing rows still decode).
+    level: Option<i32>,
+    /// Dictionary for new saves, once looked up.
+    current: tokio::sync::OnceCell<CurrentDictionary>,
+    decoders: RwLock<HashMap<i32, Arc<DecoderDictionary<'static>>>>,
+}
+
+impl ZstdCodec {
+    pub fn new(level: Option<i32>) -> Self {
+        Self { level, current: tokio::sync::OnceCell::new(), decoders: RwLock::new(HashMap::new()) }
+    }
+
+    /// Newest dictionary, fetched on first use.
+    async fn current(&self, client: &deadpool_postgres::Client) -> Result<&CurrentDictionary> {
+        self.current
+            .get_or_try_init(|| async {
+                let row = client.query_opt("SELECT id, data FROM zstd_dictionaries ORDER BY id DESC LIMIT 1", &[])
+                    .await.context("Failed to read zstd dictionaries")?;
+                Ok::<_, anyhow::Error>(RwLock::new(row.map(|row| {
+                    let (id, data): (i32, Vec<u8>) = (row.get(0), row.get(1));
+                    (id, Arc::new(EncoderDictionary::copy(&data, self.level.unwrap_or(DEFAULT_LEVEL))))
+                })))
+            })
+            .await
+    }
+
+    /// Compress `data` for storage. Returns the `dict_id` to store with it (None = stored as is).
+    pub(crate) async fn compress(&self, client: &deadpool_postgres::Client, data: &[u8]) -> Result<(Option<i32>, Vec<u8>)> {
+        let Some(level) = self.level else { return Ok((None, data.to_vec())) };
+        let current = self.current(client).await?.read().unwrap().clone();
+        match current {
+            Some((id, dict)) => {
+                let mut compressor = zstd::bulk::Compressor::with_prepared_dictionary(&dict)?;
+                Ok((Some(id), compressor.compress(data)?))
+            }
+            None => Ok((Some(NO_DICTIONARY), zstd::bulk::compress(data, level)?)),
+        }
+    }
+
+    /// Make sure the dictionaries `ids` are cached for `decompress`.
+    pub(crate) async fn load_dictionaries(&self, client: &deadpool_postgres::Client, ids: impl IntoIterator<Item = i32>) -> Result<()> {
+        let missing: Vec<i32> = {
+            let decoders = self.decoders.read().unwrap();
+            let mut missing: Vec<i32> = ids.into_iter().filter(|id| *id != NO_DICTIONARY && !decoders.contains_key(id)).collect();
+            missing.sort();
+            missing.dedup();
+            missing
+        };
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let rows = client.query("SELECT id, data FROM zstd_dictionaries WHERE id = ANY($1)", &[&missing])
+            .await.context("Failed to read zstd dictionaries")?;
+        let mut decoders = self.decoders.write().unwrap();
+        for row in rows {
+            let (id, data): (i32, Vec<u8>) = (row.get(0), row.get(1));
+            decoders.insert(id, Arc::new(DecoderDictionary::copy(&data)));
+        }
+        Ok(())
+    }
+
+    /// Decode a stored payload. Its dictionary must have been loaded with `load_dictionaries`.
+    pub fn decompress(&self, dict_id: Option<i32>, data: &[u8]) -> Result<Vec<u8>> {
+        match dict_id {
+            None => Ok(data.to_vec()),
+            Some(NO_DICTIONARY) => Ok(zstd::stream::decode_all(data)?),
+            Some(id) => {
+                let dict = self.decoders.read().unwrap().get(&id).cloned()
+                    .with_context(|| format!("Unknown zstd dictionary {}", id))?;
+                let mut out = Vec::new();
+                zstd::stream::Decoder::with_prepared_dictionary(data, &dict)?.read_to_end(&mut out)?;
+                Ok(out)
+            }
+        }
+    }
+
+    /// Store a newly trained dictionary and use it for later saves. Returns its id.
+    pub(crate) async fn add_dictionary(&self, client: &deadpool_postgres::Client, dict: &[u8], samples: usize) -> Result<i32> {
+        let row = client.query_one(
+            "INSERT INTO zstd_dictionaries (samples, data) VALUES ($1, $2) RETURNING id",
+            &[&(samples as i32), &dict],
+        ).await.context("Failed to store zstd dictionary")?;
+        let id: i32 = row.get(0);
+        let current = self.current(client).await?;
+        *current.write().unwrap() = Some((id, Arc::new(EncoderDictionary::copy(dict, self.level.unwrap_or(DEFAULT_LEVEL)))));
+        self.decoders.write().unwrap().insert(id, Arc::new(DecoderDictionary::copy(dict)));
+        Ok(id)
+    }
+}
+
+/// Train a dictionary of at most `max_size` bytes on raw NBT samples.
+pub fn train(samples: &[Vec<u8>], max_size: usize) -> Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size)
+        .with_context(|| format!("Failed to train a zstd dictionary on {} samples", samples.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress_every_dict_id() {
+        let codec = ZstdCodec::new(Some(DEFAULT_LEVEL));
+        let nbt = b"\x0a\x00\x00\x03\x00\x04xPos\x00\x00\x00\x07\x00".repeat(8);
+
+        assert_eq!(codec.decompress(None, &nbt).unwrap(), nbt);
+        let plain_zstd = zstd::bulk::compress(&nbt, DEFAULT_LEVEL).unwrap();
+        assert_eq!(codec.decompress(Some(NO_DICTIONARY), &plain_zstd).unwrap(), nbt);
+
+        // Any bytes work as a raw-content dictionary
+        let dict = nbt[..32].to_vec();
+        let encoder = EncoderDictionary::copy(&dict, DEFAULT_LEVEL);
+        let compressed = zstd::bulk::Compressor::with_prepared_dictionary(&encoder).unwrap().compress(&nbt).unwrap();
+        // Unknown until loaded
+        assert!(codec.decompress(Some(7), &compressed).is_err());
+        codec.decoders.write().unwrap().insert(7, Arc::new(DecoderDictionary::copy(&dict)));
+        assert_eq!(codec.decompress(Some(7), &compressed).unwrap(), nbt);
+    }
+}
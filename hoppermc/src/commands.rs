@@ -0,0 +1,404 @@
+// Maintenance subcommands: run against the configured storage, then exit.
+
+use crate::{
+    connect_options, connect_postgres, database_url, generator_name, history_config, is_postgres_kind, open_storage, pool_config,
+    postgres_mode, storage_scope, Args, Command, DictCommand, HistoryCommand, WorldCommand,
+};
+use futures::{StreamExt, TryStreamExt};
+use hoppermc_fs::disk_cache::DiskCache;
+use hoppermc_gen::WorldGenerator;
+use hoppermc_storage::anvil::AnvilReader;
+use hoppermc_storage::postgres::PostgresStorage;
+use hoppermc_storage::ChunkStorage;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinSet;
+
+pub async fn run(command: &Command, args: &Args, generator: &Arc<dyn WorldGenerator>) {
+    match command {
+        Command::History { action } => {
+            let storage = open_postgres(args, generator).await;
+            if let Err(e) = run_history(action, &storage, args).await {
+                eprintln!("History command failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Dict { action } => {
+            let storage = open_postgres(args, generator).await;
+            if let Err(e) = run_dict(action, &storage).await {
+                eprintln!("Dictionary command failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Migrate { dry_run } => {
+            if let Err(e) = run_migrate(args, *dry_run).await {
+                eprintln!("Migration failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::World { action } => {
+            if let Err(e) = run_world(action, args).await {
+                eprintln!("World command failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Import { dir, batch } => {
+            let storage = open_target(args, generator).await;
+            let result = run_import(&storage, dir, *batch).await;
+            finish_bulk(&storage, result, "Import").await;
+        }
+        Command::Pregen { x1, z1, x2, z2, blocks, batch, threads } => {
+            let (mut a, mut b) = ((*x1, *z1), (*x2, *z2));
+            if *blocks {
+                a = (a.0.div_euclid(16), a.1.div_euclid(16));
+                b = (b.0.div_euclid(16), b.1.div_euclid(16));
+            }
+            let min = (a.0.min(b.0), a.1.min(b.1));
+            let max = (a.0.max(b.0), a.1.max(b.1));
+            let threads = threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()));
+            let storage = open_target(args, generator).await;
+            let result = run_pregen(&storage, generator, min, max, *batch, threads).await;
+            finish_bulk(&storage, result, "Pregeneration").await;
+        }
+    }
+}
+
+/// The configured storage for bulk writes; exits when STORAGE keeps nothing.
+async fn open_target(args: &Args, generator: &Arc<dyn WorldGenerator>) -> Arc<dyn ChunkStorage> {
+    open_storage(&args.storage, args, generator).await.unwrap_or_else(|| {
+        eprintln!("FATAL: this command needs a storage backend, STORAGE='{}' keeps nothing", args.storage);
+        std::process::exit(1);
+    })
+}
+
+/// Close the storage (memory snapshots are written here) and report a failed bulk command.
+async fn finish_bulk(storage: &Arc<dyn ChunkStorage>, result: anyhow::Result<()>, what: &str) {
+    let closed = storage.close().await;
+    if let Err(e) = result.and(closed) {
+        eprintln!("{} failed: {:#}", what, e);
+        std::process::exit(1);
+    }
+}
+
+/// Lowercased `STORAGE`, which has to be one of the Postgres modes.
+fn postgres_kind(args: &Args) -> String {
+    let kind = args.storage.to_lowercase();
+    if !is_postgres_kind(&kind) {
+        eprintln!("FATAL: this command needs a Postgres storage mode (STORAGE=pg_*), not '{}'", args.storage);
+        std::process::exit(1);
+    }
+    kind
+}
+
+/// The configured storage, connected and migrated.
+async fn open_postgres(args: &Args, generator: &Arc<dyn WorldGenerator>) -> PostgresStorage {
+    connect_postgres(postgres_mode(&postgres_kind(args)), args, generator).await
+}
+
+async fn run_migrate(args: &Args, dry_run: bool) -> anyhow::Result<()> {
+    let mode = postgres_mode(&postgres_kind(args));
+    // Connecting alone applies nothing, so --dry-run leaves the database untouched
+    let storage = PostgresStorage::connect_with(&database_url(), mode, &connect_options(args), &pool_config(args)).await?;
+    let pending = storage.pending_migrations().await?;
+    if pending.is_empty() {
+        println!("Schema for {:?} is up to date", mode);
+        return Ok(());
+    }
+    if dry_run {
+        println!("{} pending migrations for {:?} (dry run, nothing applied):", pending.len(), mode);
+        for m in &pending {
+            println!("  {}/{:04}_{}", m.scope, m.version, m.name);
+            for line in m.sql.lines().filter(|l| !l.trim().is_empty()) {
+                println!("      {}", line);
+            }
+        }
+        return Ok(());
+    }
+    for m in storage.migrate().await? {
+        println!("Applied {}/{:04}_{}", m.scope, m.version, m.name);
+    }
+    Ok(())
+}
+
+async fn run_world(action: &WorldCommand, args: &Args) -> anyhow::Result<()> {
+    let mode = postgres_mode(&postgres_kind(args));
+    // Migrated, but not bound to WORLD: these commands work across worlds
+    let storage = PostgresStorage::new_with(&database_url(), mode, &connect_options(args), &pool_config(args)).await?;
+    match action {
+        WorldCommand::List => {
+            let dimensions = storage.world_dimensions().await?;
+            for world in storage.list_worlds().await? {
+                let settings = match (&world.generator, world.seed) {
+                    (Some(generator), Some(seed)) => format!("{} (seed {})", generator, seed),
+                    _ => "settings not recorded yet".to_string(),
+                };
+                println!("{:>4}  {:<20}  {}  created {}", world.id, world.name, settings, describe_time(world.created_at));
+                for (_, dimension, chunks) in dimensions.iter().filter(|(id, _, _)| *id == world.id) {
+                    println!("        {:<20}  {:>8} chunks ({:?})", dimension, chunks, mode);
+                }
+            }
+        }
+        WorldCommand::Create { name } => {
+            let world = storage.create_world(name, generator_name(&args.generator), args.seed).await?;
+            println!("Created world '{}' (id {}) with generator {} (seed {})", world.name, world.id, generator_name(&args.generator), args.seed);
+        }
+        WorldCommand::Clone { source, name } => {
+            let (world, copied) = storage.clone_world(source, name).await?;
+            println!("Cloned '{}' to '{}' (id {}): {} chunks copied from {:?}", source, world.name, world.id, copied, mode);
+        }
+    }
+    Ok(())
+}
+
+/// Progress line for bulk commands: totals so far and the running rate.
+fn report_progress(done: usize, total: Option<usize>, started: Instant) {
+    let per_minute = done as f64 / started.elapsed().as_secs_f64().max(0.001) * 60.0;
+    match total {
+        Some(total) => println!("  {}/{} chunks ({:.0} chunks/min)", done, total, per_minute),
+        None => println!("  {} chunks ({:.0} chunks/min)", done, per_minute),
+    }
+}
+
+async fn run_import(storage: &Arc<dyn ChunkStorage>, dir: &Path, batch: usize) -> anyhow::Result<()> {
+    // Read-only: the source world is never created, truncated or compacted
+    let source = Arc::new(AnvilReader::open(dir)?);
+    let regions = source.regions()?;
+    anyhow::ensure!(!regions.is_empty(), "No r.<x>.<z>.mca region files in {:?}", dir);
+    println!("Importing {} region files from {:?}", regions.len(), dir);
+
+    let started = Instant::now();
+    let (mut pending, mut done) = (Vec::new(), 0);
+    for (region_x, region_z) in regions {
+        let source = source.clone();
+        pending.extend(tokio::task::spawn_blocking(move || source.read_region(region_x, region_z)).await??);
+        while pending.len() >= batch.max(1) {
+            let rest = pending.split_off(batch.max(1));
+            storage.ingest_chunks(&pending).await?;
+            done += pending.len();
+            pending = rest;
+            report_progress(done, None, started);
+        }
+    }
+    if !pending.is_empty() {
+        storage.ingest_chunks(&pending).await?;
+        done += pending.len();
+    }
+    report_progress(done, None, started);
+    println!("Imported {} chunks in {:.1?}", done, started.elapsed());
+    Ok(())
+}
+
+async fn run_pregen(
+    storage: &Arc<dyn ChunkStorage>,
+    generator: &Arc<dyn WorldGenerator>,
+    min: (i32, i32),
+    max: (i32, i32),
+    batch: usize,
+    threads: usize,
+) -> anyhow::Result<()> {
+    let total = ((max.0 as i64 - min.0 as i64 + 1) * (max.1 as i64 - min.1 as i64 + 1)) as usize;
+    println!("Pregenerating up to {} chunks in {:?}..={:?} ({} threads)", total, min, max, threads);
+
+    // One region at a time, so memory stays flat however large the area is. Never
+    // regenerate over stored (possibly player-edited) chunks.
+    let skipped = AtomicUsize::new(0);
+    let mut todo = futures::stream::iter(region_tiles(min, max))
+        .then(|tile| async move {
+            let stored: HashSet<(i32, i32)> = storage.list_chunks(Some(tile)).try_collect().await?;
+            Ok::<_, anyhow::Error>((tile, stored))
+        })
+        .map_ok(|((lo, hi), stored)| {
+            skipped.fetch_add(stored.len(), Ordering::Relaxed);
+            let area = (lo.0..=hi.0).flat_map(move |x| (lo.1..=hi.1).map(move |z| (x, z)));
+            futures::stream::iter(area.filter(move |key| !stored.contains(key)).map(Ok::<_, anyhow::Error>))
+        })
+        .try_flatten()
+        .boxed();
+
+    let started = Instant::now();
+    let rt = tokio::runtime::Handle::current();
+    let mut tasks = JoinSet::new();
+    let (mut pending, mut done) = (Vec::new(), 0);
+    loop {
+        // Keep the generators busy while a batch is written
+        while tasks.len() < threads.max(1) * 2 {
+            let Some((x, z)) = todo.try_next().await? else { break };
+            let (generator, rt) = (generator.clone(), rt.clone());
+            tasks.spawn_blocking(move || generator.generate_chunk(x, z, &rt, None).map(|data| (x, z, data)));
+        }
+        let Some(generated) = tasks.join_next().await else { break };
+        pending.push(generated??);
+        if pending.len() >= batch.max(1) || (tasks.is_empty() && !pending.is_empty()) {
+            storage.ingest_chunks(&pending).await?;
+            done += pending.len();
+            pending.clear();
+            report_progress(done + skipped.load(Ordering::Relaxed), Some(total), started);
+        }
+    }
+    println!("Pregenerated {} chunks in {:.1?} ({} already stored)", done, started.elapsed(), skipped.load(Ordering::Relaxed));
+    Ok(())
+}
+
+/// The parts of `min..=max` in each region it overlaps, region by region.
+fn region_tiles(min: (i32, i32), max: (i32, i32)) -> impl Iterator<Item = ((i32, i32), (i32, i32))> {
+    (min.0 >> 5..=max.0 >> 5).flat_map(move |region_x| {
+        (min.1 >> 5..=max.1 >> 5).map(move |region_z| {
+            let (base_x, base_z) = (region_x * 32, region_z * 32);
+            ((min.0.max(base_x), min.1.max(base_z)), (max.0.min(base_x + 31), max.1.min(base_z + 31)))
+        })
+    })
+}
+
+async fn run_history(action: &HistoryCommand, storage: &PostgresStorage, args: &Args) -> anyhow::Result<()> {
+    match action {
+        HistoryCommand::List { x, z } => {
+            let versions = storage.list_versions(*x, *z).await?;
+            if versions.is_empty() {
+                println!("No history for chunk ({}, {})", x, z);
+            }
+            for v in versions {
+                println!("{:>10}  {}  {:>8} bytes", v.id, describe_time(v.saved_at), v.size);
+            }
+        }
+        HistoryCommand::Restore { x, z, version } => {
+            storage.restore_version(*x, *z, *version).await?;
+            println!("Restored chunk ({}, {}) to version {}", x, z, version);
+            invalidate_disk_cache(args, &[(*x, *z)]);
+        }
+        HistoryCommand::Rollback { x1, z1, x2, z2, to, blocks } => {
+            let at = parse_point_in_time(to)?;
+            let (mut a, mut b) = ((*x1, *z1), (*x2, *z2));
+            if *blocks {
+                a = (a.0.div_euclid(16), a.1.div_euclid(16));
+                b = (b.0.div_euclid(16), b.1.div_euclid(16));
+            }
+            let min = (a.0.min(b.0), a.1.min(b.1));
+            let max = (a.0.max(b.0), a.1.max(b.1));
+            println!("Rolling back chunks {:?}..={:?} to {}", min, max, describe_time(at));
+            let report = storage.rollback_area(min, max, at).await?;
+            println!("Restored {} chunks", report.restored.len());
+            invalidate_disk_cache(args, &report.restored);
+            if !report.skipped.is_empty() {
+                println!("Left {} chunks unchanged (no version that old): {:?}", report.skipped.len(), report.skipped);
+            }
+        }
+        HistoryCommand::Prune => {
+            let config = history_config(args);
+            if config.keep_last.is_none() && config.keep_days.is_none() {
+                anyhow::bail!("Set HISTORY_KEEP_LAST and/or HISTORY_KEEP_DAYS to prune");
+            }
+            println!("Removed {} versions", storage.prune_history(&config).await?);
+        }
+    }
+    Ok(())
+}
+
+/// Drop disk cache copies of chunks changed behind the mounts' back, so they are read
+/// from storage again.
+fn invalidate_disk_cache(args: &Args, keys: &[(i32, i32)]) {
+    let Some(dir) = &args.disk_cache_dir else { return };
+    match DiskCache::invalidate(dir, &storage_scope(args), keys) {
+        Ok(0) => {}
+        Ok(removed) => println!("Dropped {} disk cache entries", removed),
+        Err(e) => eprintln!("Failed to invalidate disk cache at {:?}: {:#}. Clear it before remounting.", dir, e),
+    }
+}
+
+async fn run_dict(action: &DictCommand, storage: &PostgresStorage) -> anyhow::Result<()> {
+    match action {
+        DictCommand::Train { samples, max_size } => {
+            let id = storage.train_zstd_dictionary(*samples, *max_size).await?;
+            println!("Stored dictionary {}. Running instances pick it up on restart; existing rows keep theirs", id);
+        }
+        DictCommand::List => {
+            let dictionaries = storage.list_zstd_dictionaries().await?;
+            if dictionaries.is_empty() {
+                println!("No dictionaries (zstd saves use none)");
+            }
+            for d in dictionaries {
+                println!("{:>6}  {}  {:>8} bytes  {:>6} samples", d.id, describe_time(d.created_at), d.size, d.samples);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Unix timestamp (seconds), or an age relative to now: `90s`, `30m`, `6h`, `2d`.
+fn parse_point_in_time(value: &str) -> anyhow::Result<SystemTime> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(UNIX_EPOCH + Duration::from_secs(secs));
+    }
+    let unit_start = value.char_indices().last().map(|(i, _)| i).unwrap_or(0);
+    let (amount, unit) = value.split_at(unit_start);
+    let amount: u64 = amount.parse().map_err(|_| anyhow::anyhow!("Invalid time '{}': expected a unix timestamp or an age like 30m", value))?;
+    let secs = match unit {
+        "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 3600,
+        "d" => amount * 86400,
+        _ => anyhow::bail!("Invalid time unit in '{}': use s, m, h or d", value),
+    };
+    Ok(SystemTime::now() - Duration::from_secs(secs))
+}
+
+fn describe_time(time: SystemTime) -> String {
+    let unix = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let ago = SystemTime::now().duration_since(time).map(|d| d.as_secs()).unwrap_or(0);
+    let ago = match ago {
+        s if s < 120 => format!("{}s", s),
+        s if s < 7200 => format!("{}m", s / 60),
+        s if s < 172800 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    };
+    format!("{} ({} ago)", unix, ago)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_point_in_time() {
+        assert_eq!(parse_point_in_time("1700000000").unwrap(), UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let two_hours = SystemTime::now().duration_since(parse_point_in_time("2h").unwrap()).unwrap();
+        assert!((7199..7300).contains(&two_hours.as_secs()));
+        assert!(parse_point_in_time("2w").is_err());
+        assert!(parse_point_in_time("soon").is_err());
+    }
+
+    #[test]
+    fn test_region_tiles_cover_the_area_once() {
+        let tiles: Vec<_> = region_tiles((-2, 30), (33, 31)).collect();
+        assert_eq!(tiles, vec![((-2, 30), (-1, 31)), ((0, 30), (31, 31)), ((32, 30), (33, 31))]);
+        assert_eq!(region_tiles((5, 5), (5, 5)).collect::<Vec<_>>(), vec![((5, 5), (5, 5))]);
+    }
+
+    struct CoordGenerator;
+
+    impl WorldGenerator for CoordGenerator {
+        fn generate_chunk(&self, x: i32, z: i32, _rt: &tokio::runtime::Handle, _benchmark: Option<&hoppermc_benchmark::BenchmarkMetrics>) -> anyhow::Result<Vec<u8>> {
+            Ok(vec![x as u8, z as u8])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pregen_fills_the_area_and_keeps_stored_chunks() {
+        let storage: Arc<dyn ChunkStorage> = Arc::new(hoppermc_storage::memory::MemoryStorage::new(None));
+        let generator: Arc<dyn WorldGenerator> = Arc::new(CoordGenerator);
+        storage.save_chunk(0, 0, &[0xAA]).await.unwrap();
+        storage.save_chunk(40, 0, &[0xBB]).await.unwrap();
+
+        run_pregen(&storage, &generator, (-3, -1), (33, 1), 4, 2).await.unwrap();
+
+        let mut keys: Vec<(i32, i32)> = storage.list_chunks(None).try_collect().await.unwrap();
+        keys.sort();
+        let expected: Vec<(i32, i32)> = (-3..=33).flat_map(|x| (-1..=1).map(move |z| (x, z))).chain([(40, 0)]).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(storage.load_chunk(0, 0).await.unwrap(), Some(vec![0xAA]));
+        assert_eq!(storage.load_chunk(-3, 1).await.unwrap(), Some(vec![-3i32 as u8, 1]));
+    }
+}
@@ -1,6 +1,8 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+mod commands;
+
 use hoppermc_fs::McFUSE;
 use hoppermc_gen::flat::FlatGenerator;
 use hoppermc_gen::vanilla::VanillaWorldGenerator;
@@ -21,18 +23,212 @@ pub struct Args {
     #[arg(long, env = "SEED", default_value = "0")]
     pub seed: u64,
     
-    /// Storage mode: "nostorage", "pg_raw", or "pg_jsonb"
+    /// Storage mode: "nostorage", "memory", "sqlite", "sqlite_json", "anvil", "s3", "redis", "pg_raw", "pg_jsonb", "pg_hybrid", "pg_weightless", or "pg_dedup"
     #[arg(long, env = "STORAGE", default_value = "pg_raw")]
     pub storage: String,
 
-    /// Cache size (number of chunks)
-    #[arg(long, env("CACHE_SIZE"), default_value_t = 500)]
-    pub cache_size: usize,
+    /// In-memory chunk cache budget (megabytes of compressed chunk blobs)
+    #[arg(long, env("CACHE_SIZE_MB"), default_value_t = 256)]
+    pub cache_size_mb: usize,
+
+    /// Directory for the persistent on-disk chunk cache (disabled when unset)
+    #[arg(long, env("DISK_CACHE_DIR"))]
+    pub disk_cache_dir: Option<PathBuf>,
+
+    /// On-disk chunk cache budget (megabytes)
+    #[arg(long, env("DISK_CACHE_SIZE_MB"), default_value_t = 2048)]
+    pub disk_cache_size_mb: u64,
 
     /// Prefetch radius (chunks). 0 = disabled.
     #[arg(long, env("PREFETCH_RADIUS"), default_value_t = 0)]
     pub prefetch_radius: u8,
 
+    /// Prefetch mode: "motion" (cone ahead of the player's inferred heading, square
+    /// when stationary) or "square" (full square of PREFETCH_RADIUS around every miss)
+    #[arg(long, env("PREFETCH_MODE"), default_value = "motion")]
+    pub prefetch_mode: String,
+
+    /// How far ahead (chunks) motion-aware prefetch reaches along the heading
+    #[arg(long, env("PREFETCH_LOOKAHEAD"), default_value_t = 8)]
+    pub prefetch_lookahead: u8,
+
+    /// Background prefetch workers (defaults to half the CPU cores)
+    #[arg(long, env("PREFETCH_CONCURRENCY"))]
+    pub prefetch_concurrency: Option<usize>,
+
+    /// Write-back mode: "off" (save every write immediately), "buffer" (ack after
+    /// buffering in memory) or "commit" (ack after the batched flush commits)
+    #[arg(long, env("WRITE_BACK"), default_value = "commit")]
+    pub write_back: String,
+
+    /// Write-back flush interval (milliseconds)
+    #[arg(long, env("WRITE_BACK_FLUSH_MS"), default_value_t = 1000)]
+    pub write_back_flush_ms: u64,
+
+    /// Most chunks the write-back buffer holds; new chunks past it wait for a flush or fail
+    #[arg(long, env("WRITE_BACK_MAX_DIRTY"), default_value_t = 256)]
+    pub write_back_max_dirty: usize,
+
+    /// Size limit for STORAGE=memory (megabytes, unbounded when unset). Saves beyond it fail.
+    #[arg(long, env("MEMORY_STORAGE_LIMIT_MB"))]
+    pub memory_storage_limit_mb: Option<u64>,
+
+    /// Snapshot file for STORAGE=memory: loaded on start, written on shutdown
+    #[arg(long, env("MEMORY_SNAPSHOT"))]
+    pub memory_snapshot: Option<PathBuf>,
+
+    /// Database file for STORAGE=sqlite / sqlite_json (created if missing)
+    #[arg(long, env("SQLITE_PATH"), default_value = "world.sqlite3")]
+    pub sqlite_path: PathBuf,
+
+    /// Region directory for STORAGE=anvil (plain .mca files, created if missing)
+    #[arg(long, env("ANVIL_DIR"), default_value = "world/region")]
+    pub anvil_dir: PathBuf,
+
+    /// Region files STORAGE=anvil keeps open at once (least recently used closed first)
+    #[arg(long, env("ANVIL_MAX_OPEN_REGIONS"), default_value_t = hoppermc_storage::anvil::DEFAULT_MAX_OPEN_REGIONS)]
+    pub anvil_max_open_regions: usize,
+
+    /// Bucket for STORAGE=s3 (requires the `s3` build feature)
+    #[arg(long, env("S3_BUCKET"), default_value = "hoppermc")]
+    pub s3_bucket: String,
+
+    /// Custom S3 endpoint (MinIO, R2, ...); AWS when unset
+    #[arg(long, env("S3_ENDPOINT"))]
+    pub s3_endpoint: Option<String>,
+
+    /// S3 region
+    #[arg(long, env("S3_REGION"), default_value = "us-east-1")]
+    pub s3_region: String,
+
+    /// Key prefix for every object (e.g. a world name)
+    #[arg(long, env("S3_PREFIX"), default_value = "")]
+    pub s3_prefix: String,
+
+    /// Object layout: "region" (one packed object per region) or "chunk" (one object per chunk)
+    #[arg(long, env("S3_LAYOUT"), default_value = "region")]
+    pub s3_layout: String,
+
+    /// Object key template ({x}, {z}, {rx}, {rz}); defaults depend on S3_LAYOUT
+    #[arg(long, env("S3_KEY_TEMPLATE"))]
+    pub s3_key_template: Option<String>,
+
+    /// Local read cache for fetched objects (megabytes)
+    #[arg(long, env("S3_CACHE_MB"), default_value_t = 64)]
+    pub s3_cache_mb: u64,
+
+    /// How long cached objects and misses are served before checking the bucket again (milliseconds)
+    #[arg(long, env("S3_CACHE_TTL_MS"), default_value_t = 5000)]
+    pub s3_cache_ttl_ms: u64,
+
+    /// Region packs at least this large use multipart upload (megabytes)
+    #[arg(long, env("S3_MULTIPART_THRESHOLD_MB"), default_value_t = 16)]
+    pub s3_multipart_threshold_mb: usize,
+
+    /// Redis server for STORAGE=redis
+    #[arg(long, env("REDIS_URL"), default_value = "redis://redis:6379")]
+    pub redis_url: String,
+
+    /// Key prefix for STORAGE=redis, so several worlds can share one Redis
+    #[arg(long, env("REDIS_PREFIX"), default_value = "hoppermc")]
+    pub redis_prefix: String,
+
+    /// Slower backend STORAGE=redis persists to asynchronously and reads cold regions from
+    /// (any other STORAGE value; "nostorage" = Redis only)
+    #[arg(long, env("REDIS_PERSIST"), default_value = "nostorage")]
+    pub redis_persist: String,
+
+    /// Reads while storage is unavailable: "fail" (EIO), "retry" (block and retry up to
+    /// STORAGE_RETRY_TIMEOUT_MS, then EIO) or "readonly" (serve generated terrain, refuse
+    /// to write it back)
+    #[arg(long, env("STORAGE_DEGRADED"), default_value = "retry")]
+    pub storage_degraded: String,
+
+    /// How long the "retry" degraded policy keeps retrying a read (milliseconds, at most 500)
+    #[arg(long, env("STORAGE_RETRY_TIMEOUT_MS"), default_value_t = 500)]
+    pub storage_retry_timeout_ms: u64,
+
+    /// Consecutive storage failures that open the circuit breaker
+    #[arg(long, env("STORAGE_BREAKER_THRESHOLD"), default_value_t = 5)]
+    pub storage_breaker_threshold: u32,
+
+    /// How long the open circuit breaker rejects storage calls before probing again (milliseconds)
+    #[arg(long, env("STORAGE_BREAKER_OPEN_MS"), default_value_t = 5000)]
+    pub storage_breaker_open_ms: u64,
+
+    /// Payload compression for STORAGE=pg_raw: "none" or "zstd" (uses the newest trained
+    /// dictionary, see `hoppermc dict train`)
+    #[arg(long, env("PG_COMPRESSION"), default_value = "none")]
+    pub pg_compression: String,
+
+    /// zstd level for PG_COMPRESSION=zstd
+    #[arg(long, env("PG_ZSTD_LEVEL"), default_value_t = hoppermc_storage::zstd_dict::DEFAULT_LEVEL)]
+    pub pg_zstd_level: i32,
+
+    /// TLS for the Postgres connection, libpq semantics: "disable", "allow", "prefer",
+    /// "require", "verify-ca" or "verify-full". `sslmode=` in DATABASE_URL takes precedence
+    #[arg(long, env("PG_SSLMODE"), default_value = "prefer")]
+    pub pg_sslmode: String,
+
+    /// PEM root certificate(s) to verify the Postgres server against, or "system" for the
+    /// platform trust store (default: ~/.postgresql/root.crt when present)
+    #[arg(long, env("PG_SSLROOTCERT"))]
+    pub pg_sslrootcert: Option<String>,
+
+    /// PEM client certificate for certificate authentication (with PG_SSLKEY)
+    #[arg(long, env("PG_SSLCERT"))]
+    pub pg_sslcert: Option<PathBuf>,
+
+    /// PEM private key of PG_SSLCERT
+    #[arg(long, env("PG_SSLKEY"))]
+    pub pg_sslkey: Option<PathBuf>,
+
+    /// File holding the Postgres password (e.g. a Docker secret); overrides the one in DATABASE_URL
+    #[arg(long, env("PG_PASSWORD_FILE"))]
+    pub pg_password_file: Option<PathBuf>,
+
+    /// Maximum Postgres connections in the pool (default: 4 per CPU core)
+    #[arg(long, env("PG_POOL_SIZE"))]
+    pub pg_pool_size: Option<usize>,
+
+    /// How long a query waits for a free pooled connection before failing (milliseconds, unbounded when unset)
+    #[arg(long, env("PG_POOL_WAIT_TIMEOUT_MS"))]
+    pub pg_pool_wait_timeout_ms: Option<u64>,
+
+    /// How long opening a new Postgres connection may take (milliseconds, unbounded when unset)
+    #[arg(long, env("PG_POOL_CREATE_TIMEOUT_MS"))]
+    pub pg_pool_create_timeout_ms: Option<u64>,
+
+    /// How long checking a returned connection may take (milliseconds, unbounded when unset)
+    #[arg(long, env("PG_POOL_RECYCLE_TIMEOUT_MS"))]
+    pub pg_pool_recycle_timeout_ms: Option<u64>,
+
+    /// Server-side statement_timeout for every query except migrations (milliseconds, none when unset)
+    #[arg(long, env("PG_STATEMENT_TIMEOUT_MS"))]
+    pub pg_statement_timeout_ms: Option<u64>,
+
+    /// Append every chunk save to the `chunk_history` table (Postgres storage modes)
+    #[arg(long, env("HISTORY"), default_value_t = false)]
+    pub history: bool,
+
+    /// History retention: keep only the newest N versions per chunk
+    #[arg(long, env("HISTORY_KEEP_LAST"))]
+    pub history_keep_last: Option<u32>,
+
+    /// History retention: drop versions older than this many days
+    #[arg(long, env("HISTORY_KEEP_DAYS"))]
+    pub history_keep_days: Option<u32>,
+
+    /// World to mount (Postgres storage modes). Created with GENERATOR / SEED if missing;
+    /// an existing world runs the generator settings stored with it
+    #[arg(long, env("WORLD"), default_value = hoppermc_storage::worlds::DEFAULT_WORLD)]
+    pub world: String,
+
+    /// Dimension of WORLD to mount: "overworld", "the_nether", "the_end" (or any other name,
+    /// generated like the overworld)
+    #[arg(long, env("DIMENSION"), default_value = hoppermc_storage::worlds::DEFAULT_DIMENSION)]
+    pub dimension: String,
+
     /// Auto-benchmark mode: cycle through all configurations
     #[arg(long, env("AUTO_BENCHMARK"), default_value_t = false)]
     pub auto_benchmark: bool,
@@ -40,63 +236,142 @@ pub struct Args {
     /// Duration for each benchmark cycle (seconds)
     #[arg(long, env("BENCHMARK_CYCLE_DURATION"), default_value_t = 60)]
     pub benchmark_cycle_duration: u64,
+
+    /// Run a maintenance command instead of mounting
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Chunk version history (Postgres storage modes)
+    History {
+        #[command(subcommand)]
+        action: HistoryCommand,
+    },
+    /// zstd dictionaries for PG_COMPRESSION=zstd (STORAGE=pg_raw)
+    Dict {
+        #[command(subcommand)]
+        action: DictCommand,
+    },
+    /// Apply pending schema migrations for STORAGE (Postgres storage modes)
+    Migrate {
+        /// Only list the migrations that would run
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Worlds stored in the database (Postgres storage modes)
+    World {
+        #[command(subcommand)]
+        action: WorldCommand,
+    },
+    /// Copy every chunk of an Anvil region folder into STORAGE, in bulk
+    Import {
+        /// Folder holding the r.<x>.<z>.mca files (a world's `region` folder)
+        dir: PathBuf,
+        /// Chunks per bulk write
+        #[arg(long, default_value_t = 1024)]
+        batch: usize,
+    },
+    /// Generate every chunk of a rectangle that STORAGE does not hold yet, in bulk
+    #[command(allow_negative_numbers = true)]
+    Pregen {
+        /// Corners of the area (inclusive), chunk coordinates unless --blocks
+        x1: i32,
+        z1: i32,
+        x2: i32,
+        z2: i32,
+        /// Corners are block coordinates
+        #[arg(long)]
+        blocks: bool,
+        /// Chunks per bulk write
+        #[arg(long, default_value_t = 1024)]
+        batch: usize,
+        /// Chunks generated in parallel (default: one per CPU)
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WorldCommand {
+    /// List worlds with their generator settings and stored chunks per dimension
+    List,
+    /// Create an empty world using the GENERATOR / SEED settings
+    Create { name: String },
+    /// Copy a world's settings and chunks (every dimension, this STORAGE mode) to a new world
+    Clone { source: String, name: String },
+}
+
+#[derive(Subcommand)]
+pub enum DictCommand {
+    /// Train a new dictionary on a random sample of stored chunks; later saves use it
+    Train {
+        /// Chunks to sample
+        #[arg(long, default_value_t = 2000)]
+        samples: usize,
+        /// Maximum dictionary size (bytes)
+        #[arg(long, default_value_t = hoppermc_storage::zstd_dict::DEFAULT_DICT_SIZE)]
+        max_size: usize,
+    },
+    /// List stored dictionaries
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommand {
+    /// List the stored versions of a chunk
+    #[command(allow_negative_numbers = true)]
+    List { x: i32, z: i32 },
+    /// Restore a chunk to a version id from `history list`
+    #[command(allow_negative_numbers = true)]
+    Restore { x: i32, z: i32, version: i64 },
+    /// Roll every chunk in a rectangle back to how it was at a point in time
+    #[command(allow_negative_numbers = true)]
+    Rollback {
+        /// Corners of the area (inclusive), chunk coordinates unless --blocks
+        x1: i32,
+        z1: i32,
+        x2: i32,
+        z2: i32,
+        /// Unix timestamp, or an age such as 90s, 30m, 6h or 2d
+        #[arg(long)]
+        to: String,
+        /// Corners are block coordinates
+        #[arg(long)]
+        blocks: bool,
+    },
+    /// Apply HISTORY_KEEP_LAST / HISTORY_KEEP_DAYS to the whole history table
+    Prune,
 }
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let args = Args::parse();
+    check_renamed_settings();
+    let mut args = Args::parse();
+    apply_world_settings(&mut args).await;
+    // Mode settings are checked before connecting to storage, so a typo fails fast
+    let prefetch_shape = prefetch_shape(&args);
+    let write_back = write_back_config(&args);
+    let degraded_policy = degraded_policy(&args);
     
-    use hoppermc_storage::{postgres::PostgresStorage, StorageMode, ChunkStorage};
     use std::sync::Arc;
     
-    // Initialize storage based on mode
-    let storage: Option<Arc<dyn ChunkStorage>> = match args.storage.to_lowercase().as_str() {
-        "nostorage" | "none" | "stateless" => {
-            println!("Storage mode: NOSTORAGE (stateless, all chunks generated on-the-fly)");
-            None
-        },
-        "pg_raw" | "raw" | "postgres" | "pg_jsonb" | _ => {
-            let database_url = std::env::var("DATABASE_URL")
-                .unwrap_or_else(|_| "postgres://postgres:postgres@db:5432/hoppermc".to_string());
-            
-            let mode = if args.storage.to_lowercase() == "pg_jsonb" {
-                StorageMode::PgJsonb
-            } else {
-                StorageMode::PgRaw
-            };
-
-            println!("Storage mode: {:?} (PostgreSQL)", mode);
-            println!("Connecting to storage at {}...", database_url);
-            
-            // Retry loop for DB connection
-            let mut storage_backend = None;
-            for i in 0..30 {
-                match PostgresStorage::new(&database_url, mode).await {
-                    Ok(s) => {
-                        storage_backend = Some(s);
-                        break;
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to connect to storage: {}. Retrying {}/30 in 2s...", e, i + 1);
-                        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-                    }
-                }
-            }
-
-            let backend = storage_backend.expect("FATAL: Could not connect to storage after 30 retries.");
-            Some(Arc::new(backend) as Arc<dyn ChunkStorage>)
-        }
-    };
-
     use fuser::MountOption;
     let options = vec![MountOption::AllowOther, MountOption::RW];
 
     // Select generator based on CLI args
     let generator: Arc<dyn WorldGenerator> = match args.generator.as_str() {
         "vanilla" => {
-            println!("Using Pumpkin VanillaGenerator with seed: {}", args.seed);
-            Arc::new(VanillaWorldGenerator::new(args.seed))
+            println!("Using Pumpkin VanillaGenerator with seed: {} ({})", args.seed, args.dimension);
+            match VanillaWorldGenerator::for_dimension(args.seed, &args.dimension) {
+                Some(generator) => Arc::new(generator),
+                None => {
+                    eprintln!("No vanilla terrain for dimension '{}', generating it like the overworld", args.dimension);
+                    Arc::new(VanillaWorldGenerator::new(args.seed))
+                }
+            }
         },
         "flat" | _ => {
             println!("Using FlatGenerator");
@@ -104,13 +379,23 @@ async fn main() {
         },
     };
 
+    if let Some(command) = &args.command {
+        commands::run(command, &args, &generator).await;
+        return;
+    }
+
+    // Initialize storage based on mode (weightless storage diffs against the generator)
+    let storage = open_storage(&args.storage, &args, &generator).await;
+
     // Initialize Benchmark with Config Summary
     use hoppermc_benchmark::BenchmarkMetrics;
     let benchmark = if std::env::var("BENCHMARK").is_ok() {
         println!("BENCHMARK MODE ENABLED 🚀");
         let config_summary = format!(
-            "Gen: {} | Seed: {} | Storage: {} | Cache: {} | Prefetch: {}", 
-            args.generator, args.seed, args.storage, args.cache_size, args.prefetch_radius
+            "Gen: {} | Seed: {} | Storage: {} | Cache: {} MB | Disk Cache: {} | Prefetch: {} ({})", 
+            args.generator, args.seed, args.storage, args.cache_size_mb,
+            if args.disk_cache_dir.is_some() { format!("{} MB", args.disk_cache_size_mb) } else { "off".to_string() },
+            args.prefetch_radius, args.prefetch_mode
         );
         Some(Arc::new(BenchmarkMetrics::new(config_summary)))
     } else {
@@ -122,13 +407,29 @@ async fn main() {
         return;
     }
 
+    let storage = storage.map(|s| with_circuit_breaker(s, &args, benchmark.clone()));
+
     let handle = tokio::runtime::Handle::current();
-    let virtual_file = Arc::new(VirtualFile::new(generator, storage, handle, benchmark.clone(), args.cache_size, args.prefetch_radius));
+    let disk_cache = open_disk_cache(&args, generator.as_ref(), benchmark.clone());
+    let mut virtual_file = VirtualFile::new(generator, storage, handle, benchmark.clone(), args.cache_size_mb * 1024 * 1024, args.prefetch_radius)
+        .with_prefetch_shape(prefetch_shape)
+        .with_degraded_policy(degraded_policy);
+    if let Some(workers) = args.prefetch_concurrency {
+        virtual_file = virtual_file.with_prefetch_concurrency(workers);
+    }
+    if let Some(disk) = disk_cache {
+        virtual_file = virtual_file.with_disk_cache(disk);
+    }
+    if let Some(config) = write_back {
+        println!("Write-back enabled: {:?}, flush every {:?} or at {} dirty chunks", config.durability, config.flush_interval, config.max_dirty_chunks);
+        virtual_file = virtual_file.with_write_back(config);
+    }
+    let virtual_file = Arc::new(virtual_file);
     let fs = McFUSE { virtual_file: virtual_file.clone() };
 
     println!("Mounting HopperMC FUSE to {:?} (Background)", args.mountpoint);
     
-    let _session = fuser::spawn_mount2(fs, &args.mountpoint, &options).unwrap();
+    let session = fuser::spawn_mount2(fs, &args.mountpoint, &options).unwrap();
 
     println!("Mounted successfully! Waiting for shutdown signal...");
     
@@ -142,6 +443,20 @@ async fn main() {
         _ = sigterm.recv() => println!("Received SIGTERM"),
     }
 
+    // Unmount first so no new writes arrive, then commit whatever is still buffered
+    drop(session);
+    if let Some(wb) = &virtual_file.write_back {
+        println!("Flushing {} buffered chunks...", wb.dirty_count());
+        if let Err(e) = wb.flush().await {
+            eprintln!("Failed to flush buffered chunks on shutdown: {}", e);
+        }
+    }
+    if let Some(storage) = &virtual_file.storage
+        && let Err(e) = storage.close().await
+    {
+        eprintln!("Failed to close storage: {}", e);
+    }
+
     // Write Benchmark Report
     if let Some(bench) = benchmark {
         println!("Received shutdown signal, unmounting...");
@@ -152,6 +467,14 @@ async fn main() {
                  Ok(size) => bench.record_db_size(size),
                  Err(e) => eprintln!("Failed to fetch storage size for benchmark: {}", e),
              }
+             match storage.compression_stats().await {
+                 Ok(Some(stats)) => bench.record_storage_compression(stats.plain_bytes, stats.stored_bytes),
+                 Ok(None) => {}
+                 Err(e) => eprintln!("Failed to fetch storage compression for benchmark: {}", e),
+             }
+             if let Some(pool) = storage.pool_stats() {
+                 record_pool_stats(&bench, &pool);
+             }
         }
 
         let report = bench.generate_report();
@@ -159,6 +482,440 @@ async fn main() {
     }
 }
 
+/// Open the storage backend named `kind` (the `STORAGE` values). None = stateless.
+async fn open_storage(kind: &str, args: &Args, generator: &std::sync::Arc<dyn WorldGenerator>) -> Option<std::sync::Arc<dyn hoppermc_storage::ChunkStorage>> {
+    use hoppermc_storage::ChunkStorage;
+    use std::sync::Arc;
+
+    let kind = kind.to_lowercase();
+    match kind.as_str() {
+        "nostorage" | "none" | "stateless" => {
+            println!("Storage mode: NOSTORAGE (stateless, all chunks generated on-the-fly)");
+            None
+        },
+        "memory" | "mem" => {
+            use hoppermc_storage::memory::MemoryStorage;
+
+            let limit = args.memory_storage_limit_mb.map(|mb| mb * 1024 * 1024);
+            println!("Storage mode: MEMORY (limit: {})", args.memory_storage_limit_mb.map(|mb| format!("{} MB", mb)).unwrap_or_else(|| "none".to_string()));
+            let mut backend = MemoryStorage::new(limit);
+            if let Some(path) = &args.memory_snapshot {
+                println!("Memory storage snapshot: {:?} (loaded on start, written on shutdown)", path);
+                backend = backend.with_snapshot(path).expect("FATAL: Could not load memory storage snapshot");
+            }
+            Some(Arc::new(backend) as Arc<dyn ChunkStorage>)
+        },
+        "sqlite" | "sqlite_raw" | "sqlite_json" => {
+            use hoppermc_storage::sqlite::{SqliteMode, SqliteStorage};
+
+            let mode = if kind == "sqlite_json" { SqliteMode::Json } else { SqliteMode::Raw };
+            println!("Storage mode: SQLITE {:?} ({:?})", mode, args.sqlite_path);
+            let backend = SqliteStorage::open(&args.sqlite_path, mode).expect("FATAL: Could not open SQLite database");
+            Some(Arc::new(backend) as Arc<dyn ChunkStorage>)
+        },
+        "anvil" | "mca" => {
+            use hoppermc_storage::anvil::AnvilStorage;
+
+            println!("Storage mode: ANVIL ({:?})", args.anvil_dir);
+            let backend = AnvilStorage::open(&args.anvil_dir).expect("FATAL: Could not open Anvil region directory")
+                .with_max_open_regions(args.anvil_max_open_regions);
+            Some(Arc::new(backend) as Arc<dyn ChunkStorage>)
+        },
+        "redis" => {
+            use hoppermc_storage::redis::RedisStorage;
+
+            let persist = args.redis_persist.to_lowercase();
+            if persist == "redis" || !STORAGE_KINDS.contains(&persist.as_str()) {
+                eprintln!("FATAL: REDIS_PERSIST: Invalid backend '{}': use one of {} (not redis)", args.redis_persist, STORAGE_KINDS.join(", "));
+                std::process::exit(1);
+            }
+            println!("Storage mode: REDIS ({}, prefix: {})", args.redis_url, args.redis_prefix);
+            let mut backend = RedisStorage::connect(&args.redis_url, &args.redis_prefix).await.expect("FATAL: Could not connect to Redis");
+            if let Some(fallback) = Box::pin(open_storage(&args.redis_persist, args, generator)).await {
+                println!("Redis persists asynchronously to {}", args.redis_persist);
+                backend = backend.with_fallback(fallback);
+            }
+            Some(Arc::new(backend) as Arc<dyn ChunkStorage>)
+        },
+        #[cfg(feature = "s3")]
+        "s3" => {
+            use hoppermc_storage::s3::{S3Config, S3Layout, S3Storage};
+
+            let layout = match args.s3_layout.to_lowercase().as_str() {
+                "chunk" => S3Layout::Chunk,
+                "region" => S3Layout::Region,
+                other => {
+                    eprintln!("FATAL: S3_LAYOUT: Invalid layout '{}': use region or chunk", other);
+                    std::process::exit(1);
+                }
+            };
+            println!("Storage mode: S3 (bucket: {}, endpoint: {}, layout: {:?})", args.s3_bucket, args.s3_endpoint.as_deref().unwrap_or("AWS"), layout);
+            let config = S3Config {
+                bucket: args.s3_bucket.clone(),
+                endpoint: args.s3_endpoint.clone(),
+                region: args.s3_region.clone(),
+                prefix: args.s3_prefix.clone(),
+                layout,
+                key_template: args.s3_key_template.clone(),
+                cache_bytes: args.s3_cache_mb * 1024 * 1024,
+                cache_ttl: std::time::Duration::from_millis(args.s3_cache_ttl_ms),
+                multipart_threshold: args.s3_multipart_threshold_mb * 1024 * 1024,
+            };
+            let backend = S3Storage::new(config).expect("FATAL: Could not configure S3 storage");
+            Some(Arc::new(backend) as Arc<dyn ChunkStorage>)
+        },
+        #[cfg(not(feature = "s3"))]
+        "s3" => {
+            eprintln!("FATAL: STORAGE=s3 requires a build with the `s3` feature (cargo build --features s3)");
+            std::process::exit(1);
+        },
+        kind if is_postgres_kind(kind) => {
+            let backend = connect_postgres(postgres_mode(kind), args, generator).await;
+            Some(Arc::new(backend) as Arc<dyn ChunkStorage>)
+        }
+        other => {
+            eprintln!("FATAL: STORAGE: Invalid backend '{}': use one of {}", other, STORAGE_KINDS.join(", "));
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Every accepted `STORAGE` value, aliases included.
+const STORAGE_KINDS: &[&str] = &[
+    "nostorage", "none", "stateless", "memory", "mem", "sqlite", "sqlite_raw", "sqlite_json", "anvil", "mca", "redis", "s3",
+    "pg_raw", "raw", "postgres", "pg_jsonb", "pg_hybrid", "hybrid", "pg_weightless", "weightless", "pg_dedup", "dedup",
+];
+
+/// The `STORAGE` values naming one of the Postgres modes.
+const POSTGRES_KINDS: &[&str] = &[
+    "pg_raw", "raw", "postgres", "pg_jsonb", "pg_hybrid", "hybrid", "pg_weightless", "weightless", "pg_dedup", "dedup",
+];
+
+/// Whether a `STORAGE` value names one of the Postgres modes.
+fn is_postgres_kind(kind: &str) -> bool {
+    POSTGRES_KINDS.contains(&kind.to_lowercase().as_str())
+}
+
+/// Normalised GENERATOR value, as stored in `worlds`.
+fn generator_name(generator: &str) -> &'static str {
+    if generator.eq_ignore_ascii_case("vanilla") { "vanilla" } else { "flat" }
+}
+
+/// An existing Postgres world brings its own generator settings: replace GENERATOR / SEED
+/// with them before the generator is built. Without a reachable database the configured
+/// ones stay, and `connect_postgres` refuses a mismatch later.
+async fn apply_world_settings(args: &mut Args) {
+    use hoppermc_storage::postgres::PostgresStorage;
+
+    args.generator = generator_name(&args.generator).to_string();
+    if !is_postgres_kind(&args.storage) {
+        return;
+    }
+    let world = match PostgresStorage::connect_with(&database_url(), postgres_mode(&args.storage), &connect_options(args), &pool_config(args)).await {
+        Ok(storage) => storage.find_world(&args.world).await,
+        Err(e) => Err(e),
+    };
+    match world {
+        Ok(Some(world)) => {
+            if let Some(generator) = world.generator {
+                let seed = world.seed.unwrap_or_default();
+                if generator != args.generator || seed != args.seed {
+                    println!("World '{}' runs generator {} (seed {}), overriding GENERATOR / SEED", world.name, generator, seed);
+                }
+                args.generator = generator;
+                args.seed = seed;
+            }
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("Could not look up world '{}' ({}); using GENERATOR / SEED", args.world, e),
+    }
+}
+
+/// Postgres storage mode for one of `POSTGRES_KINDS` ("pg_raw", "raw" and "postgres" are pg_raw).
+fn postgres_mode(kind: &str) -> hoppermc_storage::StorageMode {
+    use hoppermc_storage::StorageMode;
+
+    match kind.to_lowercase().as_str() {
+        "pg_jsonb" => StorageMode::PgJsonb,
+        "pg_hybrid" | "hybrid" => StorageMode::Hybrid,
+        "pg_weightless" | "weightless" => StorageMode::Weightless,
+        "pg_dedup" | "dedup" => StorageMode::PgDedup,
+        _ => StorageMode::PgRaw,
+    }
+}
+
+/// Settings whose meaning changed: refuse to start rather than silently apply the new default.
+fn check_renamed_settings() {
+    if std::env::var_os("CACHE_SIZE").is_some() {
+        if std::env::var_os("CACHE_SIZE_MB").is_some() {
+            eprintln!("Ignoring CACHE_SIZE: the cache is sized by CACHE_SIZE_MB");
+        } else {
+            eprintln!("FATAL: CACHE_SIZE (a number of chunks) was replaced by CACHE_SIZE_MB (megabytes, default 256). Remove CACHE_SIZE and set CACHE_SIZE_MB instead.");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn database_url() -> String {
+    std::env::var("DATABASE_URL").unwrap_or_else(|_| "postgres://postgres:postgres@db:5432/hoppermc".to_string())
+}
+
+/// TLS and credential settings for DATABASE_URL.
+fn connect_options(args: &Args) -> hoppermc_storage::tls::ConnectOptions {
+    let ssl_mode = args.pg_sslmode.parse().unwrap_or_else(|e| {
+        eprintln!("FATAL: PG_SSLMODE: {}", e);
+        std::process::exit(1);
+    });
+    hoppermc_storage::tls::ConnectOptions {
+        ssl_mode: Some(ssl_mode),
+        root_cert: args.pg_sslrootcert.clone(),
+        client_cert: args.pg_sslcert.clone(),
+        client_key: args.pg_sslkey.clone(),
+        password_file: args.pg_password_file.clone(),
+    }
+}
+
+/// Pool limits and timeouts for DATABASE_URL.
+fn pool_config(args: &Args) -> hoppermc_storage::postgres::PoolConfig {
+    let millis = |ms: Option<u64>| ms.map(std::time::Duration::from_millis);
+    hoppermc_storage::postgres::PoolConfig {
+        max_size: args.pg_pool_size,
+        wait_timeout: millis(args.pg_pool_wait_timeout_ms),
+        create_timeout: millis(args.pg_pool_create_timeout_ms),
+        recycle_timeout: millis(args.pg_pool_recycle_timeout_ms),
+        statement_timeout: millis(args.pg_statement_timeout_ms),
+    }
+}
+
+/// Connect to DATABASE_URL (retrying while the database starts up) and apply the history settings.
+/// The world generator as the base terrain `pg_weightless` diffs against.
+struct GeneratorBase(std::sync::Arc<dyn WorldGenerator>);
+
+impl hoppermc_storage::BaseGenerator for GeneratorBase {
+    fn generate_chunk(&self, x: i32, z: i32, rt: &tokio::runtime::Handle) -> anyhow::Result<Vec<u8>> {
+        self.0.generate_chunk(x, z, rt, None)
+    }
+
+    fn fingerprint(&self) -> String {
+        self.0.fingerprint()
+    }
+}
+
+fn base_generator(generator: &std::sync::Arc<dyn WorldGenerator>) -> std::sync::Arc<dyn hoppermc_storage::BaseGenerator> {
+    std::sync::Arc::new(GeneratorBase(generator.clone()))
+}
+
+async fn connect_postgres(
+    mode: hoppermc_storage::StorageMode,
+    args: &Args,
+    generator: &std::sync::Arc<dyn WorldGenerator>,
+) -> hoppermc_storage::postgres::PostgresStorage {
+    use hoppermc_storage::migrations::SchemaTooNew;
+    use hoppermc_storage::postgres::PostgresStorage;
+
+    let database_url = database_url();
+    let (options, pool) = (connect_options(args), pool_config(args));
+    let zstd_level = match args.pg_compression.to_lowercase().as_str() {
+        "zstd" if mode == hoppermc_storage::StorageMode::PgRaw => Some(args.pg_zstd_level),
+        "zstd" => {
+            eprintln!("PG_COMPRESSION=zstd only applies to pg_raw; ignored for {:?}", mode);
+            None
+        }
+        "none" => None,
+        other => {
+            eprintln!("FATAL: PG_COMPRESSION: Invalid value '{}': use none or zstd", other);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Storage mode: {:?} (PostgreSQL)", mode);
+    println!("Connecting to storage at {}...", database_url);
+    
+    // Retry loop for DB connection
+    let mut storage_backend = None;
+    for i in 0..30 {
+        match PostgresStorage::new_with(&database_url, mode, &options, &pool).await {
+            Ok(s) => {
+                storage_backend = Some(s.with_generator(base_generator(generator)));
+                break;
+            }
+            Err(e) if e.downcast_ref::<SchemaTooNew>().is_some() => {
+                eprintln!("FATAL: {}", e);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to connect to storage: {}. Retrying {}/30 in 2s...", e, i + 1);
+                tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+            }
+        }
+    }
+
+    let mut backend = storage_backend.expect("FATAL: Could not connect to storage after 30 retries.");
+    if let Some(level) = zstd_level {
+        println!("pg_raw payloads compressed with zstd (level {})", level);
+        backend = backend.with_zstd(level);
+    }
+    if args.history {
+        let config = history_config(args);
+        println!("Chunk history enabled (keep last: {}, keep days: {})",
+            config.keep_last.map(|n| n.to_string()).unwrap_or_else(|| "all".to_string()),
+            config.keep_days.map(|n| n.to_string()).unwrap_or_else(|| "forever".to_string()));
+        backend = backend.with_history(config);
+    }
+    select_world(backend, args).await
+}
+
+/// Restrict the storage to WORLD / DIMENSION, creating the world if needed.
+async fn select_world(
+    backend: hoppermc_storage::postgres::PostgresStorage,
+    args: &Args,
+) -> hoppermc_storage::postgres::PostgresStorage {
+    use hoppermc_storage::worlds::WorldKey;
+
+    let generator = generator_name(&args.generator);
+    let world = backend.open_world(&args.world, generator, args.seed).await.unwrap_or_else(|e| {
+        eprintln!("FATAL: Could not open world '{}': {:#}", args.world, e);
+        std::process::exit(1);
+    });
+    // Only reachable when the lookup before generator selection failed
+    if world.generator.as_deref() != Some(generator) || world.seed != Some(args.seed) {
+        eprintln!(
+            "FATAL: World '{}' was created with generator {} (seed {}), not {} (seed {})",
+            world.name, world.generator.as_deref().unwrap_or("?"), world.seed.unwrap_or_default(), generator, args.seed
+        );
+        std::process::exit(1);
+    }
+    println!("World: {} (id {}), dimension: {}", world.name, world.id, args.dimension);
+    backend.with_world(WorldKey { world_id: world.id, dimension: args.dimension.clone() })
+}
+
+fn record_pool_stats(bench: &hoppermc_benchmark::BenchmarkMetrics, pool: &hoppermc_storage::PoolStats) {
+    bench.record_pool_wait(pool.acquires, pool.timeouts, pool.total_wait, pool.max_wait);
+    bench.record_pool_usage(pool.max_size, pool.in_use_sum, pool.peak_in_use);
+}
+
+fn history_config(args: &Args) -> hoppermc_storage::history::HistoryConfig {
+    hoppermc_storage::history::HistoryConfig {
+        keep_last: args.history_keep_last,
+        keep_days: args.history_keep_days,
+    }
+}
+
+fn with_circuit_breaker(
+    storage: std::sync::Arc<dyn hoppermc_storage::ChunkStorage>,
+    args: &Args,
+    benchmark: Option<std::sync::Arc<hoppermc_benchmark::BenchmarkMetrics>>,
+) -> std::sync::Arc<dyn hoppermc_storage::ChunkStorage> {
+    use hoppermc_storage::breaker::{BreakerConfig, BreakerStorage, CircuitBreaker};
+    use std::sync::Arc;
+
+    let config = BreakerConfig {
+        failure_threshold: args.storage_breaker_threshold.max(1),
+        open_duration: std::time::Duration::from_millis(args.storage_breaker_open_ms),
+    };
+    let breaker = Arc::new(CircuitBreaker::new(config, benchmark));
+    Arc::new(BreakerStorage::new(storage, breaker))
+}
+
+fn degraded_policy(args: &Args) -> hoppermc_fs::virtual_file::DegradedPolicy {
+    use hoppermc_fs::virtual_file::{DegradedPolicy, MAX_RETRY_TIMEOUT};
+
+    let policy = match args.storage_degraded.to_lowercase().as_str() {
+        "fail" | "eio" => DegradedPolicy::Fail,
+        "readonly" | "read-only" | "ro" => DegradedPolicy::ReadOnly,
+        "retry" => {
+            let timeout = std::time::Duration::from_millis(args.storage_retry_timeout_ms);
+            if timeout > MAX_RETRY_TIMEOUT {
+                eprintln!(
+                    "FATAL: STORAGE_RETRY_TIMEOUT_MS: {} is too long: reads block the filesystem while retrying, use at most {}",
+                    args.storage_retry_timeout_ms, MAX_RETRY_TIMEOUT.as_millis()
+                );
+                std::process::exit(1);
+            }
+            DegradedPolicy::Retry { timeout }
+        }
+        other => {
+            eprintln!("FATAL: STORAGE_DEGRADED: Invalid policy '{}': use retry, fail or readonly", other);
+            std::process::exit(1);
+        }
+    };
+    println!("Degraded storage policy: {:?}", policy);
+    policy
+}
+
+fn prefetch_shape(args: &Args) -> hoppermc_fs::prefetch::PrefetchShape {
+    use hoppermc_fs::prefetch::PrefetchShape;
+
+    match args.prefetch_mode.to_lowercase().as_str() {
+        "square" => PrefetchShape::Square,
+        "motion" => PrefetchShape::Motion { lookahead: args.prefetch_lookahead },
+        other => {
+            eprintln!("FATAL: PREFETCH_MODE: Invalid mode '{}': use motion or square", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn write_back_config(args: &Args) -> Option<hoppermc_fs::write_back::WriteBackConfig> {
+    use hoppermc_fs::write_back::{WriteBackConfig, WriteDurability};
+
+    if matches!(args.write_back.to_lowercase().as_str(), "off" | "none" | "sync") {
+        return None;
+    }
+    let durability: WriteDurability = args.write_back.parse().unwrap_or_else(|e| {
+        eprintln!("FATAL: WRITE_BACK: {} (or off)", e);
+        std::process::exit(1);
+    });
+    Some(WriteBackConfig {
+        durability,
+        flush_interval: std::time::Duration::from_millis(args.write_back_flush_ms),
+        max_dirty_chunks: args.write_back_max_dirty,
+    })
+}
+
+fn open_disk_cache(
+    args: &Args,
+    generator: &dyn WorldGenerator,
+    benchmark: Option<std::sync::Arc<hoppermc_benchmark::BenchmarkMetrics>>,
+) -> Option<hoppermc_fs::disk_cache::DiskCache> {
+    let dir = args.disk_cache_dir.as_ref()?;
+    let fingerprint = generator.fingerprint();
+    let scope = storage_scope(args);
+    match hoppermc_fs::disk_cache::DiskCache::open(dir, &scope, &fingerprint, args.disk_cache_size_mb * 1024 * 1024, benchmark) {
+        Ok(disk) => {
+            println!("Disk cache enabled at {:?} ({} MB, storage {}, generator {})", dir, args.disk_cache_size_mb, scope, fingerprint);
+            Some(disk)
+        }
+        Err(e) => {
+            eprintln!("Failed to open disk cache at {:?}: {}. Continuing without it.", dir, e);
+            None
+        }
+    }
+}
+
+/// Identity of the chunks a mount serves: backend, where it keeps them and, for Postgres,
+/// the world and dimension (world names are unique within a database). Keys the disk cache.
+fn storage_scope(args: &Args) -> String {
+    let kind = args.storage.to_lowercase();
+    let parts = match kind.as_str() {
+        kind if is_postgres_kind(kind) => vec![kind.to_string(), url_location(&database_url()), args.world.clone(), args.dimension.clone()],
+        "memory" | "mem" => vec![kind.clone(), args.memory_snapshot.as_ref().map(|p| p.display().to_string()).unwrap_or_default()],
+        "sqlite" | "sqlite_raw" | "sqlite_json" => vec![kind.clone(), args.sqlite_path.display().to_string()],
+        "anvil" | "mca" => vec![kind.clone(), args.anvil_dir.display().to_string()],
+        "redis" => vec![kind.clone(), url_location(&args.redis_url), args.redis_prefix.clone()],
+        "s3" => vec![kind.clone(), args.s3_endpoint.clone().unwrap_or_default(), args.s3_bucket.clone(), args.s3_prefix.clone()],
+        _ => vec![kind.clone()],
+    };
+    parts.join("-")
+}
+
+/// Host, port and path of a connection URL, without credentials or query parameters.
+fn url_location(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.rsplit_once('@').map_or(rest, |(_, host)| host);
+    rest.split('?').next().unwrap_or_default().to_string()
+}
+
 fn write_report(report: String) {
     let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
     if let Err(e) = std::fs::create_dir_all("benchmarks") {
@@ -174,7 +931,10 @@ fn write_report(report: String) {
 }
 
 async fn run_auto_benchmark(args: Args, _main_bench: Option<std::sync::Arc<hoppermc_benchmark::BenchmarkMetrics>>) {
-    use hoppermc_storage::{postgres::PostgresStorage, StorageMode, ChunkStorage};
+    use hoppermc_storage::{postgres::PostgresStorage, ChunkStorage};
+    use hoppermc_storage::anvil::AnvilStorage;
+    use hoppermc_storage::memory::MemoryStorage;
+    use hoppermc_storage::sqlite::{SqliteMode, SqliteStorage};
     use hoppermc_gen::flat::FlatGenerator;
     use hoppermc_gen::vanilla::VanillaWorldGenerator;
     use hoppermc_gen::WorldGenerator;
@@ -192,38 +952,69 @@ async fn run_auto_benchmark(args: Args, _main_bench: Option<std::sync::Arc<hoppe
         ("vanilla", Arc::new(VanillaWorldGenerator::new(args.seed)) as Arc<dyn WorldGenerator>),
     ];
 
-    let database_url = std::env::var("DATABASE_URL")
-        .unwrap_or_else(|_| "postgres://postgres:postgres@db:5432/hoppermc".to_string());
+    let database_url = database_url();
+    let (options, pool) = (connect_options(&args), pool_config(&args));
 
-    let storage_configs: Vec<(&str, Option<StorageMode>)> = vec![
-        ("nostorage", None),
-        ("pg_raw", Some(StorageMode::PgRaw)),
-        ("pg_jsonb", Some(StorageMode::PgJsonb)),
-    ];
+    let storage_configs = ["nostorage", "memory", "sqlite", "anvil", "pg_raw", "pg_jsonb", "pg_hybrid", "pg_weightless", "pg_dedup"];
 
     let mut full_report = String::new();
     full_report.push_str("# HopperMC Auto-Benchmark Suite\n\n");
 
     for (gen_name, gen_arc) in generators {
-        for (storage_name, storage_mode) in &storage_configs {
+        for storage_name in storage_configs {
             println!("\n>>> Testing: Gen={} | Storage={}", gen_name, storage_name);
             
-            let storage: Option<Arc<dyn ChunkStorage>> = if let Some(mode) = storage_mode {
-                match PostgresStorage::new(&database_url, *mode).await {
-                    Ok(s) => Some(Arc::new(s) as Arc<dyn ChunkStorage>),
-                    Err(e) => {
-                        eprintln!("Skipping {} due to connection error: {}", storage_name, e);
-                        continue;
+            let storage: Option<Arc<dyn ChunkStorage>> = match storage_name {
+                "nostorage" => None,
+                "memory" => Some(Arc::new(MemoryStorage::default()) as Arc<dyn ChunkStorage>),
+                "sqlite" => {
+                    // Fresh database per cycle so every generator starts from an empty world
+                    let path = std::path::Path::new("benchmarks/auto-benchmark.sqlite3");
+                    for suffix in ["", "-wal", "-shm"] {
+                        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+                    }
+                    match SqliteStorage::open(path, SqliteMode::Raw) {
+                        Ok(s) => Some(Arc::new(s) as Arc<dyn ChunkStorage>),
+                        Err(e) => {
+                            eprintln!("Skipping {} due to error: {}", storage_name, e);
+                            continue;
+                        }
+                    }
+                }
+                "anvil" => {
+                    let dir = std::path::Path::new("benchmarks/auto-benchmark-region");
+                    let _ = std::fs::remove_dir_all(dir);
+                    match AnvilStorage::open(dir) {
+                        Ok(s) => Some(Arc::new(s) as Arc<dyn ChunkStorage>),
+                        Err(e) => {
+                            eprintln!("Skipping {} due to error: {}", storage_name, e);
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    match PostgresStorage::new_with(&database_url, postgres_mode(storage_name), &options, &pool).await {
+                        Ok(s) => Some(Arc::new(s.with_generator(base_generator(&gen_arc))) as Arc<dyn ChunkStorage>),
+                        Err(e) => {
+                            eprintln!("Skipping {} due to connection error: {}", storage_name, e);
+                            continue;
+                        }
                     }
                 }
-            } else {
-                None
             };
 
             let config_summary = format!("Gen: {} | Storage: {}", gen_name, storage_name);
             let bench = Arc::new(BenchmarkMetrics::new(config_summary));
             let handle = tokio::runtime::Handle::current();
-            let vf = Arc::new(VirtualFile::new(gen_arc.clone(), storage.clone(), handle, Some(bench.clone()), args.cache_size, args.prefetch_radius));
+            let mut vf = VirtualFile::new(gen_arc.clone(), storage.clone(), handle, Some(bench.clone()), args.cache_size_mb * 1024 * 1024, args.prefetch_radius)
+                .with_prefetch_shape(prefetch_shape(&args));
+            if let Some(workers) = args.prefetch_concurrency {
+                vf = vf.with_prefetch_concurrency(workers);
+            }
+            if let Some(disk) = open_disk_cache(&args, gen_arc.as_ref(), Some(bench.clone())) {
+                vf = vf.with_disk_cache(disk);
+            }
+            let vf = Arc::new(vf);
 
             // Stress test: Read spiral of chunks in background
             let vf_clone = vf.clone();
@@ -237,11 +1028,11 @@ async fn run_auto_benchmark(args: Args, _main_bench: Option<std::sync::Arc<hoppe
                         for z in -radius..=radius {
                             if stop_flag_clone.load(std::sync::atomic::Ordering::Relaxed) { break; }
                             // Region header is 8KB, read it first to simulate Minecraft
-                            vf_clone.read_at(0, 4096, 0, 0); 
+                            let _ = vf_clone.read_at(0, 4096, 0, 0);
                             
                             // In r.0.0.mca, chunk (x,z) is at get_chunk_file_offset(x, z)
                             let offset = get_chunk_file_offset(x.rem_euclid(32), z.rem_euclid(32));
-                            vf_clone.read_at(offset, 4096, 0, 0);
+                            let _ = vf_clone.read_at(offset, 4096, 0, 0);
                         }
                         if stop_flag_clone.load(std::sync::atomic::Ordering::Relaxed) { break; }
                     }
@@ -258,6 +1049,15 @@ async fn run_auto_benchmark(args: Args, _main_bench: Option<std::sync::Arc<hoppe
                 if let Ok(size) = s.get_total_size().await {
                     bench.record_db_size(size);
                 }
+                if let Ok(Some(stats)) = s.compression_stats().await {
+                    bench.record_storage_compression(stats.plain_bytes, stats.stored_bytes);
+                }
+                if let Some(pool) = s.pool_stats() {
+                    record_pool_stats(&bench, &pool);
+                }
+                if let Err(e) = s.close().await {
+                    eprintln!("Failed to close {} storage: {}", storage_name, e);
+                }
             }
 
             let report = bench.generate_report();
@@ -273,4 +1073,17 @@ async fn run_auto_benchmark(args: Args, _main_bench: Option<std::sync::Arc<hoppe
         println!("🚀 AUTO-BENCHMARK COMPLETE!");
         println!("Combined report written to {}", filename);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_postgres_kinds() {
+        assert!(POSTGRES_KINDS.iter().all(|kind| STORAGE_KINDS.contains(kind)));
+        assert!(is_postgres_kind("PG_Hybrid"));
+        assert!(!is_postgres_kind("pg_typo"));
+        assert!(!is_postgres_kind("sqlite"));
+    }
+}